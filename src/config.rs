@@ -0,0 +1,428 @@
+use crate::output::RpmError;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Keys accepted by `rpm config set`, besides the per-scope `@scope:registry` and
+/// `@scope:token` keys and the per-registry `//host/:_authToken` keys
+pub const KNOWN_KEYS: &[&str] = &[
+    "node-gyp",
+    "script-shell",
+    "mirror-registry",
+    "mirror-timeout",
+    "exec-cache-days",
+    "trust-policy",
+    "postinstall-timeout",
+];
+
+/// Milliseconds to wait for the registry before asking the mirror, unless configured
+const DEFAULT_MIRROR_TIMEOUT_MS: u64 = 3000;
+
+/// Days an unused `rpm x` install is kept, unless configured
+const DEFAULT_EXEC_CACHE_DAYS: u64 = 7;
+
+/// Seconds a postinstall script may run before it is killed, unless configured
+const DEFAULT_POSTINSTALL_TIMEOUT_SECS: u64 = 60;
+
+/// Packages `rpm add --auto-dev` saves as devDependencies, unless
+/// ~/.rpm/dev-patterns.json has its own list
+pub const DEFAULT_DEV_PATTERNS: &[&str] = &[
+    "@types/*",
+    "eslint",
+    "eslint-*",
+    "@eslint/*",
+    "@typescript-eslint/*",
+    "prettier",
+    "prettier-plugin-*",
+    "typescript",
+    "jest",
+    "jest-*",
+    "@jest/*",
+    "vitest",
+    "@vitest/*",
+    "mocha",
+    "chai",
+    "nodemon",
+    "ts-node",
+    "husky",
+    "lint-staged",
+];
+
+/// Which dependencies may run their postinstall scripts (`trust-policy`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TrustPolicy {
+    /// Never run postinstall scripts, like --ignore-scripts
+    None,
+    /// Only run scripts of packages in the project's @scope, skip the rest
+    SameScope,
+    /// Run every postinstall script without asking, like --yes
+    All,
+}
+
+impl TrustPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::SameScope => "same-scope",
+            Self::All => "all",
+        }
+    }
+}
+
+/// User configuration stored in ~/.rpm/config.json
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(flatten)]
+    values: BTreeMap<String, String>,
+    #[serde(skip)]
+    tokens: Tokens,
+}
+
+/// Registry auth tokens managed by `rpm token`, stored in ~/.rpm/tokens.json
+/// (keyed by "//host/path/", like npmrc)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Tokens {
+    #[serde(flatten)]
+    tokens: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// Location of the config file
+    pub fn path() -> PathBuf {
+        let home = std::env::var("HOME")
+            .or_else(|_| std::env::var("USERPROFILE"))
+            .expect("Could not determine home directory");
+        PathBuf::from(home).join(".rpm").join("config.json")
+    }
+
+    /// Load the config file, an empty config if it does not exist
+    pub fn load() -> Result<Self> {
+        let path = Self::path();
+        let mut config: Self = if path.exists() {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", path.display()))?
+        } else {
+            Self::default()
+        };
+        config.tokens = Tokens::load()?;
+        Ok(config)
+    }
+
+    /// Write the config file, readable by the current user only on unix (it holds tokens)
+    pub fn save(&self) -> Result<()> {
+        write_private(&Self::path(), &serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        check_key(key)?;
+        if key == "trust-policy" && parse_trust_policy(value).is_none() {
+            return Err(RpmError::Other {
+                message: format!("Invalid trust-policy '{}'", value),
+                hint: Some("Use none, same-scope or all".to_string()),
+            }
+            .into());
+        }
+        self.values.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// Remove a key, returns whether it was set
+    pub fn delete(&mut self, key: &str) -> bool {
+        self.values.remove(key).is_some()
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.values.iter()
+    }
+
+    /// Path of node-gyp for native builds, `RPM_NODE_GYP` takes precedence over the config
+    pub fn node_gyp(&self) -> Option<String> {
+        std::env::var("RPM_NODE_GYP")
+            .ok()
+            .filter(|path| !path.is_empty())
+            .or_else(|| self.get("node-gyp").map(str::to_string))
+    }
+
+    /// Shell used for scripts as program + leading args, e.g. ["powershell.exe", "-Command"].
+    /// Defaults to `sh -c`, or `cmd /C` on Windows.
+    pub fn script_shell(&self) -> Vec<String> {
+        let configured: Vec<String> = self
+            .get("script-shell")
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        if !configured.is_empty() {
+            return configured;
+        }
+        if cfg!(windows) {
+            vec!["cmd".to_string(), "/C".to_string()]
+        } else {
+            vec!["sh".to_string(), "-c".to_string()]
+        }
+    }
+
+    /// Mirror of the default registry to use when it is slow, with how long to wait
+    /// for the registry first (`mirror-registry`, `mirror-timeout` in milliseconds)
+    pub fn mirror_registry(&self) -> Option<(String, Duration)> {
+        let url = self
+            .get("mirror-registry")?
+            .trim_end_matches('/')
+            .to_string();
+        let timeout_ms = self
+            .get("mirror-timeout")
+            .and_then(|ms| ms.parse().ok())
+            .unwrap_or(DEFAULT_MIRROR_TIMEOUT_MS);
+        Some((url, Duration::from_millis(timeout_ms)))
+    }
+
+    /// Days before an unused `rpm x` install is removed (`exec-cache-days`)
+    pub fn exec_cache_days(&self) -> u64 {
+        self.get("exec-cache-days")
+            .and_then(|days| days.parse().ok())
+            .unwrap_or(DEFAULT_EXEC_CACHE_DAYS)
+    }
+
+    /// How long a postinstall script may run before it is killed (`postinstall-timeout`
+    /// in seconds)
+    pub fn postinstall_timeout(&self) -> Duration {
+        let secs = self
+            .get("postinstall-timeout")
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(DEFAULT_POSTINSTALL_TIMEOUT_SECS);
+        Duration::from_secs(secs)
+    }
+
+    /// Postinstall scripts allowed to run (`trust-policy`), unset to ask every time
+    pub fn trust_policy(&self) -> Option<TrustPolicy> {
+        self.get("trust-policy").and_then(parse_trust_policy)
+    }
+
+    /// Registries for scoped packages: scope -> (base_url, token)
+    /// Built from `@scope:registry`; `@scope:token` is only sent to that registry
+    pub fn scope_registries(&self) -> HashMap<String, (String, Option<String>)> {
+        self.values
+            .iter()
+            .filter_map(|(key, url)| {
+                let scope = scope_key(key, "registry")?;
+                let token = self
+                    .get(&format!("{}:token", scope))
+                    .map(str::to_string)
+                    .or_else(|| self.auth_token(url));
+                Some((
+                    scope.to_string(),
+                    (url.trim_end_matches('/').to_string(), token),
+                ))
+            })
+            .collect()
+    }
+
+    /// Name patterns of dev-only packages (`*` wildcards), from ~/.rpm/dev-patterns.json
+    /// (a JSON array of strings) or the built-in list
+    pub fn dev_patterns() -> Result<Vec<String>> {
+        let path = Self::path().with_file_name("dev-patterns.json");
+        if !path.exists() {
+            return Ok(DEFAULT_DEV_PATTERNS.iter().map(|p| p.to_string()).collect());
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Token for a registry from its npmrc-style `//host/path/:_authToken` key, or
+    /// added with `rpm token add`
+    pub fn auth_token(&self, registry_url: &str) -> Option<String> {
+        self.get(&auth_token_key(registry_url))
+            .or_else(|| self.tokens.get(registry_url))
+            .map(str::to_string)
+    }
+
+    /// Value as shown to the user, with auth tokens masked
+    pub fn display_value(key: &str, value: &str) -> String {
+        if scope_key(key, "token").is_some() || key.ends_with(":_authToken") {
+            "********".to_string()
+        } else {
+            value.to_string()
+        }
+    }
+}
+
+impl Tokens {
+    pub fn path() -> PathBuf {
+        Config::path().with_file_name("tokens.json")
+    }
+
+    /// Load the token file, no tokens if it does not exist
+    pub fn load() -> Result<Self> {
+        let path = Self::path();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Write the token file, readable by the current user only on unix
+    pub fn save(&self) -> Result<()> {
+        write_private(&Self::path(), &serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn get(&self, registry_url: &str) -> Option<&str> {
+        self.tokens
+            .get(&registry_key(registry_url))
+            .map(String::as_str)
+    }
+
+    pub fn add(&mut self, registry_url: &str, token: &str) {
+        self.tokens
+            .insert(registry_key(registry_url), token.to_string());
+    }
+
+    /// Remove the token of a registry, returns whether there was one
+    pub fn remove(&mut self, registry_url: &str) -> bool {
+        self.tokens.remove(&registry_key(registry_url)).is_some()
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.tokens.iter()
+    }
+}
+
+/// Registry URL without its scheme, as used in npmrc keys: "//registry.npmjs.org/"
+pub fn registry_key(registry_url: &str) -> String {
+    let without_scheme = registry_url
+        .split_once("://")
+        .map_or(registry_url, |(_, rest)| rest);
+    format!(
+        "//{}/",
+        without_scheme.trim_start_matches('/').trim_end_matches('/')
+    )
+}
+
+/// Config key holding the token of a registry, e.g. "//registry.npmjs.org/:_authToken"
+pub fn auth_token_key(registry_url: &str) -> String {
+    format!("{}:_authToken", registry_key(registry_url))
+}
+
+/// Token as shown to the user, only its last 4 characters: "****abcd"
+pub fn mask_token(token: &str) -> String {
+    let chars: Vec<char> = token.chars().collect();
+    if chars.len() <= 4 {
+        return "****".to_string();
+    }
+    let last: String = chars[chars.len() - 4..].iter().collect();
+    format!("****{}", last)
+}
+
+/// Scope of a per-scope key such as "@myorg:registry", None for other keys
+fn scope_key<'a>(key: &'a str, setting: &str) -> Option<&'a str> {
+    let (scope, name) = key.split_once(':')?;
+    (name == setting && scope.len() > 1 && scope.starts_with('@')).then_some(scope)
+}
+
+fn check_key(key: &str) -> Result<()> {
+    if KNOWN_KEYS.contains(&key)
+        || scope_key(key, "registry").is_some()
+        || scope_key(key, "token").is_some()
+        || (key.starts_with("//") && key.ends_with("/:_authToken"))
+    {
+        return Ok(());
+    }
+    Err(RpmError::Other {
+        message: format!("Unknown config key '{}'", key),
+        hint: Some(format!(
+            "Available keys: {}, @<scope>:registry, @<scope>:token, //<host>/:_authToken",
+            KNOWN_KEYS.join(", ")
+        )),
+    }
+    .into())
+}
+
+fn parse_trust_policy(value: &str) -> Option<TrustPolicy> {
+    <TrustPolicy as clap::ValueEnum>::from_str(value, false).ok()
+}
+
+/// An npm setting from the environment (`npm_config_<key>`, or `NPM_CONFIG_<KEY>`),
+/// as set by CI images and Dockerfiles written for npm
+pub fn npm_config_env(key: &str) -> Option<String> {
+    std::env::var(format!("npm_config_{}", key))
+        .or_else(|_| std::env::var(format!("NPM_CONFIG_{}", key.to_uppercase())))
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+/// Whether a package name matches one of the dev patterns
+pub fn is_dev_package(name: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|pattern| {
+        glob::Pattern::new(pattern)
+            .map(|p| p.matches(name))
+            .unwrap_or(false)
+    })
+}
+
+/// Write a file that holds secrets, readable by the current user only on unix
+fn write_private(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    // The mode only applies to new files, tighten a file created before it was set
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_dev_package() {
+        let patterns: Vec<String> = DEFAULT_DEV_PATTERNS.iter().map(|p| p.to_string()).collect();
+        assert!(is_dev_package("@types/node", &patterns));
+        assert!(is_dev_package("eslint-plugin-react", &patterns));
+        assert!(is_dev_package("typescript", &patterns));
+        assert!(!is_dev_package("react", &patterns));
+        assert!(!is_dev_package("typescript-json", &patterns));
+    }
+
+    #[test]
+    fn test_registry_key() {
+        assert_eq!(
+            registry_key("https://registry.npmjs.org"),
+            "//registry.npmjs.org/"
+        );
+        assert_eq!(registry_key("//npm.myorg.com/api/"), "//npm.myorg.com/api/");
+        assert_eq!(
+            auth_token_key("http://localhost:4873/"),
+            "//localhost:4873/:_authToken"
+        );
+        assert_eq!(mask_token("npm_abcdef1234"), "****1234");
+        assert_eq!(mask_token("abc"), "****");
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Extensions scanned by `rpm install --report-unused-deps` when no glob is given
+const SOURCE_EXTENSIONS: &[&str] = &[
+    "js", "jsx", "mjs", "cjs", "ts", "tsx", "mts", "cts", "vue", "svelte",
+];
+
+/// Package names imported by the source files under `root`, skipping node_modules and
+/// hidden directories. With a `pattern`, only files whose path relative to `root`
+/// matches it are read.
+pub fn imported_packages(
+    root: &Path,
+    pattern: Option<&glob::Pattern>,
+) -> std::io::Result<BTreeSet<String>> {
+    let mut packages = BTreeSet::new();
+    scan_dir(root, root, pattern, &mut packages)?;
+    Ok(packages)
+}
+
+fn scan_dir(
+    root: &Path,
+    dir: &Path,
+    pattern: Option<&glob::Pattern>,
+    packages: &mut BTreeSet<String>,
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name != "node_modules" && !name.starts_with('.') {
+                scan_dir(root, &path, pattern, packages)?;
+            }
+            continue;
+        }
+        if !file_type.is_file() {
+            continue;
+        }
+        let matches = match pattern {
+            Some(pattern) => path
+                .strip_prefix(root)
+                .is_ok_and(|relative| pattern.matches_path(relative)),
+            None => path
+                .extension()
+                .is_some_and(|ext| SOURCE_EXTENSIONS.iter().any(|e| ext == *e)),
+        };
+        if !matches {
+            continue;
+        }
+        // Binary or non-UTF-8 files cannot contain imports we understand
+        if let Ok(source) = std::fs::read_to_string(&path) {
+            packages.extend(import_specifiers(&source).filter_map(package_name));
+        }
+    }
+    Ok(())
+}
+
+/// String literals after `require(`, `import(`, `import` and `from`. A plain text
+/// search: commented-out imports count, computed ones are missed.
+pub fn import_specifiers(source: &str) -> impl Iterator<Item = &str> {
+    ["require(", "import(", "import", "from"]
+        .into_iter()
+        .flat_map(move |keyword| {
+            source.match_indices(keyword).filter_map(move |(idx, _)| {
+                // Skip identifiers that merely end in the keyword (e.g. `reimport`)
+                let before = source[..idx].chars().next_back();
+                if before.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '$') {
+                    return None;
+                }
+                string_literal(&source[idx + keyword.len()..])
+            })
+        })
+}
+
+/// The contents of the string literal at the start of `text`, after whitespace
+fn string_literal(text: &str) -> Option<&str> {
+    let text = text.trim_start();
+    let quote = text
+        .chars()
+        .next()
+        .filter(|c| matches!(c, '\'' | '"' | '`'))?;
+    let rest = &text[1..];
+    let end = rest.find([quote, '\n'])?;
+    (rest[end..].starts_with(quote)).then(|| &rest[..end])
+}
+
+/// Package name of a bare import specifier ("lodash/fp" -> "lodash", "@a/b/c" -> "@a/b").
+/// None for relative and absolute paths, URLs and `node:` builtins.
+pub fn package_name(specifier: &str) -> Option<String> {
+    if specifier.is_empty()
+        || specifier.starts_with(['.', '/', '#'])
+        || specifier.contains(':')
+        || specifier.contains("${")
+    {
+        return None;
+    }
+    let mut segments = specifier.split('/');
+    let first = segments.next()?;
+    if first.starts_with('@') {
+        let second = segments.next().filter(|s| !s.is_empty())?;
+        Some(format!("{}/{}", first, second))
+    } else {
+        Some(first.to_string())
+    }
+}
+
+/// Whether a dependency is among the imported packages; `@types/x` counts as imported
+/// with `x` (`@types/scope__x` with `@scope/x`)
+pub fn is_imported(dependency: &str, imported: &BTreeSet<String>) -> bool {
+    if imported.contains(dependency) {
+        return true;
+    }
+    match dependency.strip_prefix("@types/") {
+        Some(typed) => match typed.split_once("__") {
+            Some((scope, name)) => imported.contains(&format!("@{}/{}", scope, name)),
+            None => imported.contains(typed),
+        },
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_specifiers() {
+        let source = r#"
+            import React from 'react';
+            import { render } from "react-dom/client";
+            import './styles.css';
+            export * from '@scope/pkg/sub';
+            const _ = require( "lodash/fp" );
+            const lazy = await import(`chart.js`);
+            const dynamic = require(name);
+            reimport('not-a-package');
+        "#;
+        let packages: BTreeSet<String> =
+            import_specifiers(source).filter_map(package_name).collect();
+        let expected = ["@scope/pkg", "chart.js", "lodash", "react", "react-dom"];
+        assert_eq!(packages, expected.iter().map(|s| s.to_string()).collect());
+    }
+}
@@ -1,55 +1,323 @@
-use anyhow::Result;
+use crate::integrity::{sha512_dir_integrity, sha512_integrity, verify_integrity};
+use crate::output::ColorMode;
+use anyhow::{Context, Result};
 use flate2::read::GzDecoder;
 use reqwest::Client;
+use std::collections::{BTreeMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use tar::Archive;
 use tokio::fs;
 
+/// Timestamp file of each cache entry, recording when it was last used
+pub const LAST_USED_FILE: &str = ".last_used";
+
+/// SHA-512 of the tarball a store entry was extracted from, then of its extracted
+/// contents (SRI format, one per line)
+pub const HASH_FILE: &str = ".hash";
+
+/// node_modules directories that symlink packages to the store, one per line
+pub const PROJECTS_FILE: &str = "projects";
+
+/// Top-level cache names that only make sense on this machine: in-progress extractions,
+/// `rpm x` installs and the projects list. Left out of cache exports and imports.
+pub fn is_machine_local(name: &std::ffi::OsStr) -> bool {
+    name == "tmp" || name == "_npx" || name == PROJECTS_FILE
+}
+
+/// Where tarballs are extracted (see --concurrency-model)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ConcurrencyModel {
+    /// Tokio's blocking pool, which grows with the number of parallel downloads
+    #[default]
+    Async,
+    /// A fixed pool with one thread per CPU, downloads stay async
+    Threaded,
+}
+
+/// Fixed set of threads running CPU-bound extraction jobs
+struct ExtractPool {
+    pool: rayon::ThreadPool,
+}
+
+impl ExtractPool {
+    fn new(threads: usize) -> Result<Self> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("rpm-extract-{}", i))
+            // A panicking job fails its own install, the thread keeps serving others
+            .panic_handler(|_| {})
+            .build()?;
+        Ok(Self { pool })
+    }
+
+    async fn run<T: Send + 'static>(&self, job: impl FnOnce() -> T + Send + 'static) -> Result<T> {
+        let (result_sender, result) = tokio::sync::oneshot::channel();
+        self.pool.spawn(move || {
+            let _ = result_sender.send(job());
+        });
+        result
+            .await
+            .map_err(|_| anyhow::anyhow!("Extraction job panicked"))
+    }
+}
+
 #[derive(Clone)]
 pub struct Installer {
     client: Client,
     pub cache_dir: PathBuf,
     force_no_cache: bool,
+    /// Symlink node_modules/<name> to the store instead of hard-linking the files
+    use_symlinks: Arc<AtomicBool>,
+    /// Also symlink packages with install scripts (--prefer-symlinks), they get a
+    /// private copy while their scripts run
+    symlink_scripted: Arc<AtomicBool>,
+    /// node_modules directories already recorded in PROJECTS_FILE by this run
+    registered_projects: Arc<tokio::sync::Mutex<HashSet<PathBuf>>>,
+    // Cumulative time spent downloading tarballs and extracting/linking them (nanoseconds)
+    pub download_nanos: Arc<AtomicU64>,
+    pub extract_nanos: Arc<AtomicU64>,
+    // Threads extracting tarballs with --concurrency-model threaded
+    extract_pool: Option<Arc<ExtractPool>>,
+    // Whether warnings are colored (see --color / --no-color)
+    color_mode: ColorMode,
+}
+
+/// Package store: `RPM_CACHE_DIR`, an "rpm" directory inside npm's cache when
+/// `npm_config_cache` is set, or ~/.rpm/store
+fn cache_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("RPM_CACHE_DIR").filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir);
+    }
+    // A directory of its own, so `rpm cache clean` leaves npm's cache alone
+    if let Some(dir) = crate::config::npm_config_env("cache") {
+        return PathBuf::from(dir).join("rpm");
+    }
+    let home = std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .expect("Could not determine home directory");
+    PathBuf::from(home).join(".rpm").join("store")
 }
 
 impl Installer {
-    pub fn new(force_no_cache: bool) -> Self {
-        let home = std::env::var("HOME")
-            .or_else(|_| std::env::var("USERPROFILE"))
-            .expect("Could not determine home directory");
-        let cache_dir = PathBuf::from(home).join(".rpm").join("store");
+    pub fn new(
+        client: Client,
+        force_no_cache: bool,
+        use_symlinks: bool,
+        color_mode: ColorMode,
+    ) -> Self {
+        let cache_dir = cache_dir();
 
         Self {
-            client: Client::new(),
+            client,
             cache_dir,
             force_no_cache,
+            use_symlinks: Arc::new(AtomicBool::new(use_symlinks)),
+            symlink_scripted: Arc::new(AtomicBool::new(false)),
+            registered_projects: Arc::new(tokio::sync::Mutex::new(HashSet::new())),
+            download_nanos: Arc::new(AtomicU64::new(0)),
+            extract_nanos: Arc::new(AtomicU64::new(0)),
+            extract_pool: None,
+            color_mode,
+        }
+    }
+
+    /// Extract on tokio's blocking pool (async) or a pool with one thread per CPU
+    pub fn set_concurrency_model(&mut self, model: ConcurrencyModel) -> Result<()> {
+        self.extract_pool = match model {
+            ConcurrencyModel::Async => None,
+            ConcurrencyModel::Threaded => {
+                let threads = std::thread::available_parallelism().map_or(4, |n| n.get());
+                Some(Arc::new(ExtractPool::new(threads)?))
+            }
+        };
+        Ok(())
+    }
+
+    pub fn uses_symlinks(&self) -> bool {
+        self.use_symlinks.load(Ordering::Relaxed)
+    }
+
+    /// Hard-link or copy every package, whatever --symlinks says
+    pub fn copy_packages(&self) {
+        self.use_symlinks.store(false, Ordering::Relaxed);
+        self.symlink_scripted.store(false, Ordering::Relaxed);
+    }
+
+    /// Symlink every package to the store, including packages with install scripts
+    pub fn prefer_symlinks(&self) {
+        self.use_symlinks.store(true, Ordering::Relaxed);
+        self.symlink_scripted.store(true, Ordering::Relaxed);
+    }
+
+    /// When a cache entry was last used (seconds since the Unix epoch), falling back to
+    /// the entry's modification time for entries created before this was tracked
+    pub fn last_used(entry: &Path) -> Option<u64> {
+        if let Some(content) = read_metadata(entry, LAST_USED_FILE) {
+            if let Ok(secs) = content.trim().parse() {
+                return Some(secs);
+            }
         }
+        let modified = std::fs::metadata(entry).ok()?.modified().ok()?;
+        Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
     }
 
-    fn get_cache_path(&self, name: &str, version: &str) -> PathBuf {
+    /// Cached package directories ("<name>@<version>", scopes written as "@scope+name"),
+    /// sorted. Skips tmp/ and _npx/.
+    pub fn package_entries(&self) -> Vec<PathBuf> {
+        let mut entries: Vec<PathBuf> = match std::fs::read_dir(&self.cache_dir) {
+            Ok(dir) => dir
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| {
+                    p.is_dir()
+                        && p.file_name()
+                            .is_some_and(|n| n.to_string_lossy().contains('@'))
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        entries.sort();
+        entries
+    }
+
+    /// Package name and version of a cache entry
+    pub fn parse_entry(entry: &Path) -> Option<(String, String)> {
+        let file_name = entry.file_name()?.to_string_lossy().to_string();
+        let (name, version) = file_name.rsplit_once('@')?;
+        if name.is_empty() {
+            return None;
+        }
+        Some((name.replace('+', "/"), version.to_string()))
+    }
+
+    /// Hash of a cache entry's files, excluding rpm's own bookkeeping files
+    pub fn content_hash(entry: &Path) -> std::io::Result<String> {
+        sha512_dir_integrity(entry, &[LAST_USED_FILE, HASH_FILE])
+    }
+
+    /// Content hash recorded when a cache entry was extracted (None for entries
+    /// created before this was tracked)
+    pub fn stored_content_hash(entry: &Path) -> Option<String> {
+        let stored = read_metadata(entry, HASH_FILE)?;
+        stored.lines().nth(1).map(|line| line.trim().to_string())
+    }
+
+    /// Whether a cache entry was extracted from the tarball with hash `tarball_hash` and
+    /// its files still match the content hash recorded then
+    async fn entry_intact(entry: &Path, tarball_hash: &str) -> bool {
+        let entry = entry.to_path_buf();
+        let tarball_hash = tarball_hash.to_string();
+        tokio::task::spawn_blocking(move || {
+            let Some(stored) = read_metadata(&entry, HASH_FILE) else {
+                return false;
+            };
+            let mut lines = stored.lines();
+            if lines.next() != Some(tarball_hash.as_str()) {
+                return false;
+            }
+            let Some(content_hash) = lines.next().map(str::trim) else {
+                return false;
+            };
+            Self::content_hash(&entry).is_ok_and(|hash| hash == content_hash)
+        })
+        .await
+        .unwrap_or(false)
+    }
+
+    pub async fn touch_last_used(entry: &Path) {
+        if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
+            let _ = fs::write(
+                metadata_path(entry, LAST_USED_FILE),
+                now.as_secs().to_string(),
+            )
+            .await;
+        }
+        // Entries extracted by older versions keep these files inside the package
+        let _ = fs::remove_file(entry.join(LAST_USED_FILE)).await;
+        let _ = fs::rename(entry.join(HASH_FILE), metadata_path(entry, HASH_FILE)).await;
+    }
+
+    /// Remove a store entry together with its bookkeeping files
+    pub async fn remove_entry(entry: &Path) -> std::io::Result<()> {
+        fs::remove_dir_all(entry).await?;
+        for file in [LAST_USED_FILE, HASH_FILE] {
+            let _ = fs::remove_file(metadata_path(entry, file)).await;
+        }
+        Ok(())
+    }
+
+    /// Store directory of a package version
+    pub fn get_cache_path(&self, name: &str, version: &str) -> PathBuf {
         let safe_name = name.replace('/', "+");
         self.cache_dir.join(format!("{}@{}", safe_name, version))
     }
 
-    async fn ensure_cache_entry(
+    /// Store directory of a package version, downloaded and extracted if not cached yet
+    pub async fn ensure_cache_entry(
         &self,
         name: &str,
         version: &str,
         tarball_url: &str,
+        integrity: Option<&str>,
+        auth_token: Option<&str>,
     ) -> Result<PathBuf> {
         let cache_path = self.get_cache_path(name, version);
 
         if !self.force_no_cache && cache_path.exists() {
+            Self::touch_last_used(&cache_path).await;
             return Ok(cache_path);
         }
+        self.fetch_cache_entry(name, version, tarball_url, integrity, auth_token)
+            .await
+    }
 
-        if self.force_no_cache && cache_path.exists() {
-            fs::remove_dir_all(&cache_path).await?;
-        }
+    /// Download a package version and extract it into the store. An existing entry is kept
+    /// if it came from the same tarball and its files are unchanged, otherwise it is
+    /// replaced in place once the new files are extracted.
+    pub async fn fetch_cache_entry(
+        &self,
+        name: &str,
+        version: &str,
+        tarball_url: &str,
+        integrity: Option<&str>,
+        auth_token: Option<&str>,
+    ) -> Result<PathBuf> {
+        let cache_path = self.get_cache_path(name, version);
 
         // Download
-        let resp = self.client.get(tarball_url).send().await?;
+        let started = Instant::now();
+        let mut request = self.client.get(tarball_url);
+        if let Some(token) = auth_token {
+            request = request.bearer_auth(token);
+        }
+        // An error page must not be unpacked as if it were the package
+        let resp = request
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Failed to download {}@{}", name, version))?;
         let bytes = resp.bytes().await?;
+        add_elapsed(&self.download_nanos, started);
+
+        if let Some(integrity) = integrity {
+            if !verify_integrity(&bytes, integrity) {
+                anyhow::bail!("Integrity check failed for {}@{}", name, version);
+            }
+        }
+
+        // With --force-no-cache, an entry extracted from the same tarball is kept as long
+        // as its files are unchanged since
+        let hash = sha512_integrity(&bytes);
+        let replace = cache_path.exists();
+        if replace && Self::entry_intact(&cache_path, &hash).await {
+            Self::touch_last_used(&cache_path).await;
+            return Ok(cache_path);
+        }
+
+        let started = Instant::now();
 
         let temp_dir = self
             .cache_dir
@@ -58,32 +326,34 @@ impl Installer {
         fs::create_dir_all(&temp_dir).await?;
 
         let temp_dir_clone = temp_dir.clone();
-        tokio::task::spawn_blocking(move || -> Result<()> {
+        let extract = move || -> Result<String> {
             let tar = GzDecoder::new(&bytes[..]);
             let mut archive = Archive::new(tar);
 
-            archive
-                .entries()?
-                .filter_map(|e| e.ok())
-                .for_each(|mut entry| {
-                    let path = entry.path().unwrap();
-                    let path_str = path.to_string_lossy();
-
-                    // npm packages are usually inside "package/" folder in tarball
-                    let dest_path = if path_str.starts_with("package/") {
-                        temp_dir_clone.join(path_str.trim_start_matches("package/"))
-                    } else {
-                        temp_dir_clone.join(path)
-                    };
-
-                    if let Some(parent) = dest_path.parent() {
-                        let _ = std::fs::create_dir_all(parent);
-                    }
-                    let _ = entry.unpack(&dest_path);
-                });
-            Ok(())
-        })
-        .await??;
+            for mut entry in archive.entries()?.filter_map(|e| e.ok()) {
+                let path = entry.path()?;
+                let path_str = path.to_string_lossy();
+
+                // npm packages are usually inside "package/" folder in tarball
+                let dest_path = if path_str.starts_with("package/") {
+                    temp_dir_clone.join(path_str.trim_start_matches("package/"))
+                } else {
+                    temp_dir_clone.join(&path)
+                };
+
+                if let Some(parent) = dest_path.parent() {
+                    let _ = std::fs::create_dir_all(parent);
+                }
+                let _ = entry.unpack(&dest_path);
+            }
+            Ok(Self::content_hash(&temp_dir_clone)?)
+        };
+        let content_hash = match &self.extract_pool {
+            Some(pool) => pool.run(extract).await??,
+            None => tokio::task::spawn_blocking(extract).await??,
+        };
+        let hashes = format!("{}\n{}\n", hash, content_hash);
+        fs::write(metadata_path(&cache_path, HASH_FILE), hashes).await?;
 
         // Move to final cache location
         // Create parent dir if needed
@@ -91,9 +361,30 @@ impl Installer {
             fs::create_dir_all(parent).await?;
         }
 
+        // The entry being replaced is only moved aside now, so projects symlinked to it
+        // miss it for as short as possible
+        let stale = if replace {
+            let stale = self
+                .cache_dir
+                .join("tmp")
+                .join(uuid::Uuid::new_v4().to_string());
+            fs::rename(&cache_path, &stale).await?;
+            Some(stale)
+        } else {
+            None
+        };
+
         // Rename can fail if cross-device, but here we are usually in same home drive
-        match fs::rename(&temp_dir, &cache_path).await {
-            Ok(_) => Ok(cache_path),
+        let renamed = fs::rename(&temp_dir, &cache_path).await;
+        add_elapsed(&self.extract_nanos, started);
+        if let Some(stale) = stale {
+            let _ = fs::remove_dir_all(&stale).await;
+        }
+        match renamed {
+            Ok(_) => {
+                Self::touch_last_used(&cache_path).await;
+                Ok(cache_path)
+            }
             Err(_) => {
                 // Fallback for cross-device move if tmp and cache are different mounts (unlikely for default ~/.rpm)
                 // But simple rename is best effort
@@ -113,41 +404,264 @@ impl Installer {
         name: &str,
         version: &str,
         tarball_url: &str,
+        integrity: Option<&str>,
+        auth_token: Option<&str>,
         target_dir: &Path,
     ) -> Result<()> {
-        let cache_path = self.ensure_cache_entry(name, version, tarball_url).await?;
+        let cache_path = self
+            .ensure_cache_entry(name, version, tarball_url, integrity, auth_token)
+            .await?;
         let install_path = target_dir.join("node_modules").join(name);
 
-        if install_path.exists() {
+        if install_path.is_symlink() {
+            fs::remove_file(&install_path).await?;
+        } else if install_path.exists() {
             fs::remove_dir_all(&install_path).await?;
         }
         if let Some(parent) = install_path.parent() {
             fs::create_dir_all(parent).await?;
         }
 
-        // Recursive hard-link from cache to install_path (much faster than copy)
-        link_dir_recursive(&cache_path, &install_path).await?;
+        let started = Instant::now();
+        // Packages with install scripts may modify their own files, so they get their own copy
+        // (with --prefer-symlinks that copy is only made while the scripts run)
+        let symlink = self.uses_symlinks()
+            && (self.symlink_scripted.load(Ordering::Relaxed) || !has_install_scripts(&cache_path));
+        if symlink {
+            self.register_project(&target_dir.join("node_modules"))
+                .await;
+            if let Err(e) = symlink_dir(&cache_path, &install_path).await {
+                // Windows only allows symlinks in Developer Mode or as administrator
+                if self.use_symlinks.swap(false, Ordering::Relaxed) {
+                    crate::output::warning(
+                        self.color_mode,
+                        &format!(
+                            "Could not symlink packages to the store ({}), hard-linking them \
+                             instead",
+                            e
+                        ),
+                    );
+                }
+                link_dir_recursive(&cache_path, &install_path, true).await?;
+            }
+        } else {
+            // Recursive hard-link from cache to install_path (much faster than copy)
+            link_dir_recursive(&cache_path, &install_path, true).await?;
+        }
+        add_elapsed(&self.extract_nanos, started);
+
+        Ok(())
+    }
+
+    /// Replace a store symlink by a real copy of the package, so scripts cannot modify
+    /// the store. Returns the store entry it pointed to, None if `path` is not a store link.
+    pub async fn materialize(&self, path: &Path) -> Result<Option<PathBuf>> {
+        if !self.is_store_link(path) {
+            return Ok(None);
+        }
+        let target = fs::read_link(path).await?;
+        fs::remove_file(path).await?;
+        link_dir_recursive(&target, path, false).await?;
+        Ok(Some(target))
+    }
 
+    /// Undo `materialize`: drop the copy and symlink to the store entry again (hard-link
+    /// it if symlinks cannot be created)
+    pub async fn relink(&self, path: &Path, target: &Path) -> Result<()> {
+        fs::remove_dir_all(path).await?;
+        if symlink_dir(target, path).await.is_err() {
+            link_dir_recursive(target, path, true).await?;
+        }
         Ok(())
     }
+
+    /// Record that `node_modules` symlinks packages to the store, so gc and
+    /// check-integrity leave the entries it uses alone
+    async fn register_project(&self, node_modules: &Path) {
+        let node_modules = node_modules
+            .canonicalize()
+            .unwrap_or_else(|_| node_modules.to_path_buf());
+        let mut registered = self.registered_projects.lock().await;
+        if !registered.insert(node_modules.clone()) {
+            return;
+        }
+        let path = self.cache_dir.join(PROJECTS_FILE);
+        let line = node_modules.to_string_lossy().to_string();
+        // Other rpm processes update the same file, hold its lock from read to write
+        let _ = tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+            use std::io::{Read, Write};
+            let mut file = std::fs::OpenOptions::new()
+                .read(true)
+                .append(true)
+                .create(true)
+                .open(&path)?;
+            file.lock()?;
+            let mut content = String::new();
+            file.read_to_string(&mut content)?;
+            if !content.lines().any(|existing| existing == line) {
+                file.write_all(format!("{}\n", line).as_bytes())?;
+            }
+            Ok(())
+        })
+        .await;
+    }
+
+    /// Store entries symlinked from the node_modules of recorded projects. Projects whose
+    /// node_modules is gone are dropped from PROJECTS_FILE.
+    pub fn referenced_entries(&self) -> HashSet<PathBuf> {
+        use std::io::{Read, Seek, Write};
+        let path = self.cache_dir.join(PROJECTS_FILE);
+        let Ok(mut file) = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+        else {
+            return HashSet::new();
+        };
+        // Held until the pruned list is written, so a concurrent install's line is not lost
+        let mut content = String::new();
+        if file.lock().is_err() || file.read_to_string(&mut content).is_err() {
+            return HashSet::new();
+        }
+
+        let mut referenced = HashSet::new();
+        let mut projects = Vec::new();
+        for project in content.lines() {
+            let Ok(dir) = std::fs::read_dir(project) else {
+                continue;
+            };
+            projects.push(project);
+            for entry in dir.filter_map(|e| e.ok()) {
+                let packages: Vec<PathBuf> = if entry.file_name().to_string_lossy().starts_with('@')
+                {
+                    std::fs::read_dir(entry.path())
+                        .map(|scope| scope.filter_map(|e| e.ok()).map(|e| e.path()).collect())
+                        .unwrap_or_default()
+                } else {
+                    vec![entry.path()]
+                };
+                referenced.extend(
+                    packages
+                        .iter()
+                        .filter_map(|package| std::fs::read_link(package).ok())
+                        .filter(|target| target.starts_with(&self.cache_dir)),
+                );
+            }
+        }
+        if projects.len() < content.lines().count() {
+            let kept: String = projects.iter().map(|p| format!("{}\n", p)).collect();
+            let _ = file
+                .set_len(0)
+                .and_then(|_| file.rewind())
+                .and_then(|_| file.write_all(kept.as_bytes()));
+        }
+        referenced
+    }
+
+    /// Whether `path` is a symlink into the store (as opposed to a workspace package link)
+    pub fn is_store_link(&self, path: &Path) -> bool {
+        std::fs::read_link(path)
+            .map(|target| target.starts_with(&self.cache_dir))
+            .unwrap_or(false)
+    }
+}
+
+/// Whether a package runs scripts on install (preinstall, install or postinstall)
+fn has_install_scripts(package_dir: &Path) -> bool {
+    let Ok(content) = std::fs::read_to_string(package_dir.join("package.json")) else {
+        return false;
+    };
+    let Ok(package_json) = serde_json::from_str::<serde_json::Value>(&content) else {
+        return false;
+    };
+    ["preinstall", "install", "postinstall"]
+        .iter()
+        .any(|script| package_json["scripts"].get(script).is_some())
+}
+
+/// Bookkeeping file of a store entry ("<entry>.hash"). It sits next to the entry rather
+/// than inside it, so it does not show up in packages symlinked to the store.
+fn metadata_path(entry: &Path, file: &str) -> PathBuf {
+    let mut name = entry.file_name().unwrap_or_default().to_os_string();
+    name.push(file);
+    entry.with_file_name(name)
+}
+
+/// Contents of a bookkeeping file, looked up inside the entry for entries extracted by
+/// older versions
+fn read_metadata(entry: &Path, file: &str) -> Option<String> {
+    std::fs::read_to_string(metadata_path(entry, file))
+        .or_else(|_| std::fs::read_to_string(entry.join(file)))
+        .ok()
+}
+
+#[cfg(unix)]
+async fn symlink_dir(src: &Path, dst: &Path) -> Result<()> {
+    fs::symlink(src, dst).await?;
+    Ok(())
+}
+
+#[cfg(windows)]
+async fn symlink_dir(src: &Path, dst: &Path) -> Result<()> {
+    fs::symlink_dir(src, dst).await?;
+    Ok(())
+}
+
+/// Add the time since `started` to a nanosecond counter
+pub fn add_elapsed(counter: &AtomicU64, started: Instant) {
+    counter.fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// Size and modification time of every file below `dir`, to detect changes
+pub fn dir_fingerprint(dir: &Path) -> BTreeMap<PathBuf, (u64, Option<SystemTime>)> {
+    let mut files = BTreeMap::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&current) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                let path = entry.path();
+                let relative = path.strip_prefix(dir).unwrap_or(&path).to_path_buf();
+                files.insert(relative, (metadata.len(), metadata.modified().ok()));
+            }
+        }
+    }
+    files
+}
+
+// Recursive hard-link helper (falls back to copy if hard link fails, copies when
+// `hard_link` is false)
+async fn link_dir_recursive(src: &Path, dst: &Path, hard_link: bool) -> Result<()> {
+    // Entries extracted by older versions keep their bookkeeping files at the top level,
+    // they are not part of the package (files with these names deeper down are)
+    link_dir(src, dst, hard_link, &[LAST_USED_FILE, HASH_FILE]).await
 }
 
-// Recursive hard-link helper (falls back to copy if hard link fails)
 #[async_recursion::async_recursion]
-async fn link_dir_recursive(src: &Path, dst: &Path) -> Result<()> {
+async fn link_dir(src: &Path, dst: &Path, hard_link: bool, skip: &[&str]) -> Result<()> {
     fs::create_dir_all(dst).await?;
     let mut entries = fs::read_dir(src).await?;
 
     while let Some(entry) = entries.next_entry().await? {
+        if skip.iter().any(|name| entry.file_name() == *name) {
+            continue;
+        }
         let file_type = entry.file_type().await?;
         let src_path = entry.path();
         let dst_path = dst.join(entry.file_name());
 
         if file_type.is_dir() {
-            link_dir_recursive(&src_path, &dst_path).await?;
+            link_dir(&src_path, &dst_path, hard_link, &[]).await?;
         } else {
             // Try hard link first (instant, no disk space), fall back to copy
-            if fs::hard_link(&src_path, &dst_path).await.is_err() {
+            if !hard_link || fs::hard_link(&src_path, &dst_path).await.is_err() {
                 fs::copy(&src_path, &dst_path).await?;
             }
         }
@@ -0,0 +1,222 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::path::{Path, PathBuf};
+
+/// Hash algorithms found in `integrity` fields, weakest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Algorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl Algorithm {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "md5" => Some(Self::Md5),
+            "sha1" => Some(Self::Sha1),
+            "sha256" => Some(Self::Sha256),
+            "sha384" => Some(Self::Sha384),
+            "sha512" => Some(Self::Sha512),
+            _ => None,
+        }
+    }
+
+    fn digest(self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Md5 => Md5::digest(bytes).to_vec(),
+            Self::Sha1 => Sha1::digest(bytes).to_vec(),
+            Self::Sha256 => Sha256::digest(bytes).to_vec(),
+            Self::Sha384 => Sha384::digest(bytes).to_vec(),
+            Self::Sha512 => Sha512::digest(bytes).to_vec(),
+        }
+    }
+}
+
+/// Check `bytes` against an SRI string such as "sha512-<base64>" (several hashes may be
+/// separated by whitespace). Only hashes of the strongest algorithm present are compared,
+/// and any one of them matching is enough.
+pub fn verify_integrity(bytes: &[u8], integrity: &str) -> bool {
+    let hashes: Vec<(Algorithm, Vec<u8>)> = integrity
+        .split_whitespace()
+        .filter_map(|entry| {
+            let (algorithm, hash) = entry.split_once('-')?;
+            // Strip SRI options ("sha512-abc?foo")
+            let hash = hash.split('?').next().unwrap_or(hash);
+            Some((Algorithm::parse(algorithm)?, STANDARD.decode(hash).ok()?))
+        })
+        .collect();
+
+    let Some(strongest) = hashes.iter().map(|(algorithm, _)| *algorithm).max() else {
+        return false;
+    };
+
+    let actual = strongest.digest(bytes);
+    hashes
+        .iter()
+        .any(|(algorithm, expected)| *algorithm == strongest && *expected == actual)
+}
+
+/// SRI string of the SHA-512 of `bytes` ("sha512-<base64>")
+pub fn sha512_integrity(bytes: &[u8]) -> String {
+    format!("sha512-{}", STANDARD.encode(Sha512::digest(bytes)))
+}
+
+/// SHA-512 over the files of an extracted package (relative path and contents, in path
+/// order), as an SRI string. Symlinks hash their target; top-level `skip` names are ignored.
+pub fn sha512_dir_integrity(dir: &Path, skip: &[&str]) -> std::io::Result<String> {
+    let mut files = Vec::new();
+    collect_files(dir, Path::new(""), skip, &mut files)?;
+    files.sort();
+
+    let mut hasher = Sha512::new();
+    for relative in files {
+        let path = dir.join(&relative);
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        if path.is_symlink() {
+            hasher.update(std::fs::read_link(&path)?.to_string_lossy().as_bytes());
+        } else {
+            hasher.update(std::fs::read(&path)?);
+        }
+        hasher.update([0]);
+    }
+    Ok(format!("sha512-{}", STANDARD.encode(hasher.finalize())))
+}
+
+fn collect_files(
+    dir: &Path,
+    relative: &Path,
+    skip: &[&str],
+    files: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if relative.as_os_str().is_empty() && skip.iter().any(|s| name == *s) {
+            continue;
+        }
+        let path = relative.join(&name);
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &path, skip, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA512: &str = concat!(
+        "sha512-m3HSJL1i83hdltRq0+o9czGb+8KJDKra4t/3JRlnPKcjI8PZm6XBHX",
+        "x6zG4UuMXaDEZjR1wuXDre9G9zvN7AQw=="
+    );
+
+    #[test]
+    fn test_verify_integrity() {
+        assert!(verify_integrity(b"hello", SHA512));
+        assert!(verify_integrity(
+            b"hello",
+            "sha1-qvTGHdzF6KLavt4PO0gs2a6pQ00="
+        ));
+        assert!(verify_integrity(b"hello", "md5-XUFAKrxLKna5cZ2REBfFkg=="));
+        assert!(!verify_integrity(b"hello!", SHA512));
+        assert!(!verify_integrity(b"hello", "sha3-abc"));
+        assert!(!verify_integrity(b"hello", ""));
+        assert_eq!(sha512_integrity(b"hello"), SHA512);
+    }
+
+    #[test]
+    fn test_verify_integrity_sha256() {
+        let sha256 = "sha256-LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=";
+        assert!(verify_integrity(b"hello", sha256));
+        assert!(!verify_integrity(b"hello!", sha256));
+    }
+
+    #[test]
+    fn test_verify_integrity_malformed() {
+        assert!(!verify_integrity(b"hello", "sha512"));
+        assert!(!verify_integrity(b"hello", "sha512-"));
+        assert!(!verify_integrity(b"hello", "sha256-not base64!"));
+        assert!(!verify_integrity(
+            b"hello",
+            "LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ="
+        ));
+        // A malformed entry next to a valid one is ignored
+        assert!(verify_integrity(
+            b"hello",
+            "sha256-%%% sha256-LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ="
+        ));
+    }
+
+    #[test]
+    fn test_verify_integrity_uses_strongest() {
+        // A wrong sha512 is not rescued by a correct sha1
+        let mixed = "sha1-qvTGHdzF6KLavt4PO0gs2a6pQ00= sha512-AAAA";
+        assert!(!verify_integrity(b"hello", mixed));
+        let mixed = format!("sha1-AAAA {}", SHA512);
+        assert!(verify_integrity(b"hello", &mixed));
+    }
+
+    #[test]
+    fn test_sha512_dir_integrity() {
+        let dir = std::env::temp_dir().join(format!("rpm-test-{}", uuid::Uuid::new_v4()));
+        let write = |path: &str, content: &str| {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        let hash = || sha512_dir_integrity(&dir, &[".hash"]).unwrap();
+
+        write("b.js", "b");
+        write("lib/a.js", "a");
+        let first = hash();
+        // Files are hashed in path order, whatever order they were written in
+        std::fs::remove_dir_all(&dir).unwrap();
+        write("lib/a.js", "a");
+        write("b.js", "b");
+        assert_eq!(hash(), first);
+
+        // Skipped names are only ignored at the top level
+        write(".hash", "anything");
+        assert_eq!(hash(), first);
+        write("lib/.hash", "anything");
+        assert_ne!(hash(), first);
+        std::fs::remove_file(dir.join("lib/.hash")).unwrap();
+
+        // Contents and names both count
+        write("b.js", "changed");
+        assert_ne!(hash(), first);
+        write("b.js", "b");
+        std::fs::rename(dir.join("b.js"), dir.join("c.js")).unwrap();
+        assert_ne!(hash(), first);
+        std::fs::rename(dir.join("c.js"), dir.join("b.js")).unwrap();
+        assert_eq!(hash(), first);
+
+        // A symlink is hashed by its target path, not the contents of what it points to
+        #[cfg(unix)]
+        {
+            let outside = dir.with_extension("target");
+            std::fs::write(&outside, "one").unwrap();
+            std::os::unix::fs::symlink(&outside, dir.join("link")).unwrap();
+            let linked = hash();
+            assert_ne!(linked, first);
+            std::fs::write(&outside, "two").unwrap();
+            assert_eq!(hash(), linked);
+            std::fs::remove_file(dir.join("link")).unwrap();
+            std::os::unix::fs::symlink("b.js", dir.join("link")).unwrap();
+            assert_ne!(hash(), linked);
+            std::fs::remove_file(&outside).unwrap();
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+use serde_json::Value;
+
+/// Read the license of a package.json, including the legacy `{"type": ...}` and
+/// `"licenses": [...]` forms. None if the package does not declare one.
+pub fn package_license(package_json: &Value) -> Option<String> {
+    match &package_json["license"] {
+        Value::String(license) if !license.trim().is_empty() => {
+            return Some(license.trim().to_string())
+        }
+        Value::Object(legacy) => {
+            if let Some(Value::String(license)) = legacy.get("type") {
+                return Some(license.clone());
+            }
+        }
+        _ => {}
+    }
+
+    let licenses: Vec<&str> = package_json["licenses"]
+        .as_array()?
+        .iter()
+        .filter_map(|l| l["type"].as_str().or(l.as_str()))
+        .collect();
+    match licenses.len() {
+        0 => None,
+        1 => Some(licenses[0].to_string()),
+        _ => Some(format!("({})", licenses.join(" OR "))),
+    }
+}
+
+/// Check an SPDX expression such as "MIT" or "(MIT OR Apache-2.0)" against a list of
+/// allowed license ids. OR needs one allowed side, AND needs both.
+pub fn license_allowed(expression: &str, allowed: &[String]) -> bool {
+    let expression = strip_parens(expression.trim());
+
+    if let Some(parts) = split_top_level(expression, "OR") {
+        return parts.iter().any(|part| license_allowed(part, allowed));
+    }
+    if let Some(parts) = split_top_level(expression, "AND") {
+        return parts.iter().all(|part| license_allowed(part, allowed));
+    }
+
+    // "GPL-2.0+ WITH Classpath-exception-2.0" is allowed when the license itself is
+    let id = expression
+        .split(" WITH ")
+        .next()
+        .unwrap_or(expression)
+        .trim()
+        .trim_end_matches('+');
+    allowed.iter().any(|a| a.eq_ignore_ascii_case(id))
+}
+
+/// Remove parentheses wrapping the whole expression
+fn strip_parens(mut expression: &str) -> &str {
+    while expression.starts_with('(')
+        && expression.ends_with(')')
+        && closing_paren(expression) == Some(expression.len() - 1)
+    {
+        expression = expression[1..expression.len() - 1].trim();
+    }
+    expression
+}
+
+/// Index of the parenthesis closing the one at the start
+fn closing_paren(expression: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in expression.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split on an operator outside of parentheses, None if it does not occur
+fn split_top_level<'a>(expression: &'a str, operator: &str) -> Option<Vec<&'a str>> {
+    let needle = format!(" {} ", operator);
+    let mut parts = Vec::new();
+    let mut start = 0;
+
+    for (i, _) in expression.match_indices(&needle) {
+        let prefix = &expression[..i];
+        if prefix.matches('(').count() == prefix.matches(')').count() {
+            parts.push(&expression[start..i]);
+            start = i + needle.len();
+        }
+    }
+
+    if parts.is_empty() {
+        return None;
+    }
+    parts.push(&expression[start..]);
+    Some(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_license_allowed() {
+        let list = allowed(&["MIT", "Apache-2.0", "ISC"]);
+        assert!(license_allowed("MIT", &list));
+        assert!(license_allowed("(MIT OR GPL-3.0)", &list));
+        assert!(license_allowed("GPL-3.0 OR (ISC AND MIT)", &list));
+        assert!(!license_allowed("MIT AND GPL-3.0", &list));
+        assert!(!license_allowed("BSD-2-Clause", &list));
+        assert!(license_allowed("Apache-2.0 WITH LLVM-exception", &list));
+    }
+
+    #[test]
+    fn test_package_license() {
+        let pkg: Value = serde_json::json!({"license": "MIT"});
+        assert_eq!(package_license(&pkg).as_deref(), Some("MIT"));
+        let pkg: Value = serde_json::json!({"license": {"type": "ISC"}});
+        assert_eq!(package_license(&pkg).as_deref(), Some("ISC"));
+        let pkg: Value = serde_json::json!({"licenses": [{"type": "MIT"}, {"type": "GPL-2.0"}]});
+        assert_eq!(package_license(&pkg).as_deref(), Some("(MIT OR GPL-2.0)"));
+        assert_eq!(package_license(&serde_json::json!({})), None);
+    }
+}
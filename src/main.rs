@@ -1,4 +1,8 @@
+mod config;
+mod imports;
 mod installer;
+mod integrity;
+mod license;
 mod manager;
 mod output;
 mod registry;
@@ -6,9 +10,15 @@ mod types;
 mod workspace;
 
 use clap::{CommandFactory, Parser, Subcommand};
-use manager::Manager;
-use output::{colors, format_duration};
-use std::time::Instant;
+use config::TrustPolicy;
+use installer::ConcurrencyModel;
+use manager::{
+    AddOptions, DependencySubset, InstallOptions, Manager, RunWorkspacesOptions, VersionRangeStyle,
+};
+use output::{colors, format_duration, maybe_strip_colors, ColorMode, Reporter};
+use registry::HttpOptions;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 #[derive(Parser)]
 #[command(name = "rpm")]
@@ -29,21 +39,163 @@ struct Cli {
     /// Skip postinstall scripts entirely
     #[arg(long, global = true)]
     ignore_scripts: bool,
+
+    /// Run postinstall scripts as root instead of dropping to "nobody"
+    #[arg(long, global = true)]
+    unsafe_perm: bool,
+
+    /// Symlink packages to the store instead of hard-linking their files (node, bundlers
+    /// and tsc must then preserve symlinks)
+    #[arg(long, global = true)]
+    symlinks: bool,
+
+    /// Progress output style
+    #[arg(long, global = true, value_enum, default_value_t = Reporter::Default)]
+    reporter: Reporter,
+
+    /// Always color output, even when it is not a terminal
+    #[arg(long, global = true, conflicts_with = "no_color")]
+    color: bool,
+
+    /// Never color output
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Idle HTTP connections kept open per registry host
+    #[arg(long, global = true, default_value_t = 64, value_name = "N")]
+    max_sockets: usize,
+
+    /// Seconds before an idle HTTP connection is closed
+    #[arg(long, global = true, default_value_t = 30, value_name = "SECONDS")]
+    pool_idle_timeout: u64,
+
+    /// Extract tarballs on tokio's blocking pool (async) or one thread per CPU (threaded)
+    #[arg(long, global = true, value_enum, default_value_t = ConcurrencyModel::Async)]
+    concurrency_model: ConcurrencyModel,
+
+    /// Registry for unscoped packages (default: $npm_config_registry, or
+    /// https://registry.npmjs.org)
+    #[arg(long, global = true, value_name = "URL")]
+    registry: Option<String>,
+
+    /// Kill postinstall scripts still running after this many seconds
+    /// (default: the postinstall-timeout config, or 60)
+    #[arg(long, global = true, value_name = "SECONDS")]
+    postinstall_timeout: Option<u64>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Install dependencies from package.json
-    Install,
+    Install {
+        /// Skip the engines check of package.json
+        #[arg(long)]
+        ignore_engines: bool,
+
+        /// Skip optional dependencies (remembered in rpm-lock.json)
+        #[arg(long, visible_alias = "ignore-optional")]
+        no_optional: bool,
+
+        /// Install optional dependencies again after --no-optional
+        #[arg(long, conflicts_with = "no_optional")]
+        include_optional: bool,
+
+        /// Print time spent resolving, downloading and extracting
+        #[arg(long)]
+        timings: bool,
+
+        /// Do not auto-install peer dependencies (npm 6 behavior)
+        #[arg(long)]
+        legacy_peer_deps: bool,
+
+        /// Install the versions in rpm-lock.json as-is, without checking package.json ranges
+        #[arg(long)]
+        exact_versions: bool,
+
+        /// Symlink packages to the store (like --symlinks), including packages with
+        /// install scripts (copied while their scripts run)
+        #[arg(long)]
+        prefer_symlinks: bool,
+
+        /// Install only production (dependencies) or development (devDependencies) deps
+        #[arg(long, value_enum, value_name = "SUBSET")]
+        only: Option<DependencySubset>,
+
+        /// Set up git hooks (husky, simple-git-hooks or lefthook) after installing
+        #[arg(long)]
+        git_hooks: bool,
+
+        /// Remove nested copies of packages that the top-level copy satisfies
+        #[arg(long)]
+        prefer_dedupe: bool,
+
+        /// Warn when the install takes longer than this many milliseconds
+        #[arg(long, value_name = "MS")]
+        timing_budget: Option<u64>,
+
+        /// Fail when the install exceeds --timing-budget
+        #[arg(long, requires = "timing_budget")]
+        strict_timing: bool,
+
+        /// Fail instead of warning when workspace packages declare different versions
+        /// of the same dependency
+        #[arg(long)]
+        strict_peer_deps: bool,
+
+        /// Check installed packages first and reinstall those with a missing or broken
+        /// package.json, or a version that differs from rpm-lock.json
+        #[arg(long)]
+        recover: bool,
+
+        /// Write the installed dependency graph as JSON ({nodes, edges}) to this file
+        #[arg(long, value_name = "FILE")]
+        graph_output: Option<PathBuf>,
+
+        /// Install in this directory (package.json, rpm-lock.json and node_modules)
+        /// instead of the current one
+        #[arg(long, value_name = "DIR")]
+        prefix: Option<PathBuf>,
+
+        /// Warn about dependencies that no source file imports (require/import, so
+        /// computed imports are missed)
+        #[arg(long)]
+        report_unused_deps: bool,
+
+        /// Source files scanned by --report-unused-deps, relative to the project
+        /// (default: .js, .ts, .jsx, .tsx, .mjs, .cjs, .vue and .svelte files)
+        #[arg(long, value_name = "GLOB", requires = "report_unused_deps")]
+        source_glob: Option<String>,
+    },
     /// List installed packages
     #[command(visible_alias = "ls")]
-    List,
+    List {
+        /// Print one "<path> <name>@<version>" line per installed package
+        #[arg(long)]
+        parseable: bool,
+
+        /// Show the installed dependencies of each package as a tree, and installed
+        /// packages package.json does not require
+        #[arg(long, conflicts_with = "parseable")]
+        include_transitive: bool,
+
+        /// How many levels of transitive dependencies to show (default: all)
+        #[arg(long, requires = "include_transitive")]
+        depth: Option<usize>,
+    },
     /// Show outdated packages
-    Outdated,
+    Outdated {
+        /// How deep to check transitive dependencies (0 = package.json only, -1 = unlimited)
+        #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+        depth: i32,
+    },
     /// Update packages to latest versions
     Update {
         /// Specific packages to update (updates all if none specified)
         packages: Vec<String>,
+
+        /// Choose which outdated packages to update
+        #[arg(short, long)]
+        interactive: bool,
     },
     /// Remove duplicate packages
     Dedupe,
@@ -52,6 +204,25 @@ enum Commands {
         /// Package name to check
         package: String,
     },
+    /// Show the dist-tags and versions of a package in the registry
+    Info {
+        /// Package name (e.g. react, @types/node)
+        package: String,
+
+        /// List every published version with its release date, newest first
+        #[arg(long)]
+        versions: bool,
+
+        /// Print the versions as JSON ([{version, time}])
+        #[arg(long, requires = "versions")]
+        json: bool,
+    },
+    /// Show the licenses of installed packages
+    Licenses {
+        /// Fail if a package uses a license outside this comma-separated SPDX list
+        #[arg(long, value_name = "ALLOWED")]
+        check: Option<String>,
+    },
     /// Add one or more packages
     Add {
         /// Packages to add (e.g. react, react@18.0.0)
@@ -66,6 +237,60 @@ enum Commands {
             visible_alias = "dev"
         )]
         dev: bool,
+
+        /// Add as peer dependency
+        #[arg(long = "peer", visible_alias = "save-peer", conflicts_with = "dev")]
+        peer: bool,
+
+        /// Save dev tools (@types/*, eslint-*, jest, ...) as dev dependencies.
+        /// Patterns can be replaced in ~/.rpm/dev-patterns.json
+        #[arg(long, conflicts_with_all = ["dev", "peer"])]
+        auto_dev: bool,
+
+        /// Add to the workspace root package.json, also from inside a member
+        #[arg(short = 'W', long, conflicts_with_all = ["workspace", "all"])]
+        workspace_root: bool,
+
+        /// Add to the package.json of this workspace member
+        #[arg(long)]
+        workspace: Option<String>,
+
+        /// Add to the package.json of every workspace member
+        #[arg(long, visible_alias = "all-workspaces", conflicts_with = "workspace")]
+        all: bool,
+
+        /// Install the latest version published before this date (e.g. 2023-01-01)
+        #[arg(long)]
+        before: Option<String>,
+
+        /// Dist-tag to install for packages given without a version (e.g. next)
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Install globally into ~/.rpm/global and link binaries into ~/.rpm/bin
+        #[arg(
+            short = 'g',
+            long,
+            conflicts_with_all = ["dev", "peer", "auto_dev", "workspace_root", "workspace", "all"]
+        )]
+        global: bool,
+
+        /// Save the exact resolved version ("1.2.3") instead of a caret range
+        #[arg(short = 'E', long, visible_alias = "save-exact")]
+        exact: bool,
+
+        /// Save a tilde range ("~1.2.3") instead of a caret range
+        #[arg(long, visible_alias = "save-tilde", conflicts_with = "exact")]
+        tilde: bool,
+
+        /// Choose the version of packages given without one from their dist-tags and
+        /// recent versions (the default is used when stdin is not a terminal)
+        #[arg(short, long, conflicts_with_all = ["before", "tag"])]
+        interactive: bool,
+
+        /// Which postinstall scripts may run, saved to ~/.rpm/config.json for later installs
+        #[arg(long, value_enum, value_name = "POLICY")]
+        trust_policy: Option<TrustPolicy>,
     },
     /// Remove one or more packages
     #[command(visible_aliases = ["rm", "uninstall", "un"])]
@@ -90,71 +315,459 @@ enum Commands {
         /// Filter workspaces by name pattern
         #[arg(long = "filter", short = 'f')]
         filter: Option<String>,
+
+        /// Also run the script of the root package.json (with --workspaces)
+        #[arg(long, requires = "workspaces")]
+        include_workspace_root: bool,
+
+        /// Run workspaces one at a time instead of in parallel (with --workspaces).
+        /// Stops at the first failing workspace unless --continue-on-error is set
+        #[arg(long, requires = "workspaces")]
+        serial: bool,
+
+        /// With --serial, keep running the remaining workspaces after a failure.
+        /// Parallel runs finish every workspace unless --bail is set. The exit code is
+        /// non-zero if any workspace failed either way
+        #[arg(long, requires = "workspaces")]
+        continue_on_error: bool,
+
+        /// Print output lines as they are written, prefixed with the workspace name
+        /// (with --workspaces)
+        #[arg(long, requires = "workspaces")]
+        stream: bool,
+
+        /// Stop the other running workspaces (SIGTERM on unix) at the first failure
+        /// (with --workspaces)
+        #[arg(long, requires = "workspaces", conflicts_with_all = ["serial", "continue_on_error"])]
+        bail: bool,
+
+        /// How many workspace scripts run at once (with --workspaces, default: the number
+        /// of workspaces, at most 10)
+        #[arg(
+            long,
+            value_name = "N",
+            requires = "workspaces",
+            conflicts_with = "serial",
+            value_parser = clap::value_parser!(u16).range(1..)
+        )]
+        workspace_concurrency: Option<u16>,
+
+        /// Node.js flags prepended to NODE_OPTIONS (e.g. "--max-old-space-size=4096").
+        /// --require, --import and --loader need --unsafe-perm
+        #[arg(long, value_name = "FLAGS", allow_hyphen_values = true)]
+        node_options: Option<String>,
+
+        /// Load environment variables for the script from a .env file (KEY=value lines)
+        #[arg(long, value_name = "FILE")]
+        env_file: Option<PathBuf>,
+
+        /// Let variables of --env-file replace variables already set in the environment
+        #[arg(long, requires = "env_file")]
+        overwrite_env: bool,
+
+        /// Discard the script's output, only report its exit code if it fails
+        #[arg(long, conflicts_with = "workspaces")]
+        silent: bool,
+
+        /// Open the Node.js debugger (adds --inspect to NODE_OPTIONS) and print its
+        /// DevTools URL
+        #[arg(long, conflicts_with_all = ["workspaces", "silent", "inspect_brk"])]
+        inspect: bool,
+
+        /// Like --inspect, but stop before the script's first line
+        #[arg(long, conflicts_with_all = ["workspaces", "silent"])]
+        inspect_brk: bool,
+    },
+    /// List and manage workspace packages (monorepo)
+    #[command(visible_alias = "workspace")]
+    Workspaces {
+        #[command(subcommand)]
+        command: Option<WorkspacesCommands>,
     },
-    /// List workspace packages (monorepo)
-    Workspaces,
     /// Execute a package binary (like npx)
     #[command(visible_alias = "exec")]
     X {
         /// Package to execute (e.g. prettier, eslint@8.0.0)
         package: String,
 
+        /// Reinstall the package and its dependencies for this run instead of reusing
+        /// the previous `rpm x` install (the package store is still used)
+        #[arg(long)]
+        no_cache: bool,
+
+        /// Node.js flags prepended to NODE_OPTIONS (e.g. "--max-old-space-size=4096").
+        /// --require, --import and --loader need --unsafe-perm
+        #[arg(long, value_name = "FLAGS", allow_hyphen_values = true)]
+        node_options: Option<String>,
+
         /// Arguments to pass to the package
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         args: Vec<String>,
     },
+    /// Show what `rpm install` would change, without writing anything
+    Diff {
+        /// Compare rpm-lock.json with a fresh resolution of package.json
+        #[arg(long)]
+        lockfile: bool,
+    },
+    /// Inspect the lockfile
+    Lock {
+        #[command(subcommand)]
+        command: LockCommands,
+    },
+    /// Show the user the registry auth token belongs to
+    Whoami {
+        /// Check the registry configured for this scope (e.g. @myorg)
+        #[arg(long)]
+        scope: Option<String>,
+    },
+    /// Manage registry auth tokens
+    Token {
+        #[command(subcommand)]
+        command: TokenCommands,
+    },
+    /// Get and set rpm configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
     /// Manage package cache
+    #[command(visible_alias = "store")]
     Cache {
         #[command(subcommand)]
         command: CacheCommands,
     },
 }
 
+#[derive(Subcommand)]
+enum LockCommands {
+    /// Check that node_modules matches rpm-lock.json
+    Verify,
+    /// Rebuild rpm-lock.json from the packages in node_modules
+    Fix,
+    /// Regenerate rpm-lock.json from the registry, ignoring the versions it locks
+    Update,
+}
+
+#[derive(Subcommand)]
+enum WorkspacesCommands {
+    /// List workspace packages
+    List,
+    /// Bump a member's version and update the ranges of members depending on it
+    Version {
+        /// Workspace package to bump
+        #[arg(required_unless_present = "changed", requires = "bump")]
+        package: Option<String>,
+        /// major, minor, patch or an explicit version
+        bump: Option<String>,
+        /// Bump every member changed since the last git tag (major, minor or patch),
+        /// committing and tagging each as <name>@<version>
+        #[arg(long, value_name = "BUMP", conflicts_with = "package")]
+        changed: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Set a config value (e.g. `rpm config set node-gyp /path/to/node-gyp`)
+    Set { key: String, value: String },
+    /// Print a config value
+    Get { key: String },
+    /// Remove a config value
+    Delete { key: String },
+    /// Show all config values
+    List,
+}
+
+#[derive(Subcommand)]
+enum TokenCommands {
+    /// Save the auth token of a registry (e.g. `rpm token add https://npm.myorg.com <token>`)
+    Add { registry: String, token: String },
+    /// Remove the auth token of a registry
+    #[command(visible_alias = "rm")]
+    Remove { registry: String },
+    /// Show registries with a saved token (masked)
+    #[command(visible_alias = "ls")]
+    List,
+}
+
 #[derive(Subcommand)]
 enum CacheCommands {
     /// Clear the global package cache
     Clean,
     /// Show cache location and size
     Info,
+    /// Print the cache directory, for scripts: `$(rpm cache path)`
+    Path,
+    /// Download packages into the cache without installing them (e.g. lodash@4 react@18)
+    Add {
+        /// Packages to cache, as <name> or <name>@<version or range>
+        #[arg(required = true)]
+        packages: Vec<String>,
+    },
+    /// List cached packages with their size and when they were last used
+    #[command(visible_alias = "list")]
+    Ls,
+    /// Remove packages not used for a while or no longer in the registry
+    Gc {
+        /// Remove packages not used in this many days
+        #[arg(long, default_value_t = 90)]
+        max_age_days: u64,
+    },
+    /// Re-hash cached packages and report any that were modified or damaged
+    CheckIntegrity {
+        /// Download corrupted packages again and re-extract them in place
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Bundle the cache into a .tar.gz archive for offline transport
+    Export {
+        /// Archive to write (e.g. rpm-store.tar.gz)
+        output: PathBuf,
+    },
+    /// Restore the cache from an archive created with `export`
+    Import {
+        /// Archive to read
+        input: PathBuf,
+    },
+}
+
+/// --node-options with the debugger flag of `rpm run --inspect`/`--inspect-brk` in front
+fn with_inspect(node_options: Option<String>, inspect: bool, inspect_brk: bool) -> Option<String> {
+    let flag = if inspect_brk {
+        "--inspect-brk"
+    } else if inspect {
+        "--inspect"
+    } else {
+        return node_options;
+    };
+    Some(match node_options {
+        Some(flags) => format!("{} {}", flag, flags),
+        None => flag.to_string(),
+    })
 }
 
 #[tokio::main]
 async fn main() {
     let start = Instant::now();
     let cli = Cli::parse();
-    let manager = Manager::new(cli.force_no_cache, cli.yes, cli.ignore_scripts);
+    let color_mode = if cli.color {
+        ColorMode::Always
+    } else if cli.no_color {
+        ColorMode::Never
+    } else {
+        ColorMode::Auto
+    };
+    let mut manager = Manager::new(
+        cli.force_no_cache,
+        cli.yes,
+        cli.ignore_scripts,
+        cli.unsafe_perm,
+        cli.symlinks,
+        cli.reporter,
+        color_mode,
+        HttpOptions {
+            max_sockets: cli.max_sockets,
+            pool_idle_timeout: Duration::from_secs(cli.pool_idle_timeout),
+        },
+    )
+    .with_concurrency_model(cli.concurrency_model)
+    .with_registry(cli.registry)
+    .with_postinstall_timeout(cli.postinstall_timeout);
 
-    println!(
-        "\x1b[1;36mrpm\x1b[0m \x1b[90mv{}\x1b[0m\n",
-        env!("CARGO_PKG_VERSION")
-    );
+    // Machine-readable output must not be mixed with the banner and timing lines
+    let quiet = matches!(
+        cli.command,
+        Some(Commands::List { parseable: true, .. })
+            | Some(Commands::Cache { command: CacheCommands::Path })
+            | Some(Commands::Run { silent: true, .. })
+            | Some(Commands::Info { json: true, .. })
+    )
+        || cli.reporter == Reporter::Silent;
+
+    if !quiet {
+        let banner = format!(
+            "\x1b[1;36mrpm\x1b[0m \x1b[90mv{}\x1b[0m\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        println!("{}", maybe_strip_colors(color_mode, &banner));
+    }
 
     let result = match cli.command {
-        Some(Commands::Add { packages, dev }) => manager.add_packages(packages, dev).await,
+        Some(Commands::Add {
+            packages,
+            dev,
+            peer,
+            auto_dev,
+            workspace_root,
+            workspace,
+            all,
+            before,
+            tag,
+            global,
+            exact,
+            tilde,
+            interactive,
+            trust_policy,
+        }) => {
+            let options = AddOptions {
+                dev,
+                peer,
+                auto_dev,
+                workspace_root,
+                workspace,
+                all_workspaces: all,
+                before,
+                tag,
+                global,
+                interactive,
+                range_style: if exact {
+                    VersionRangeStyle::Exact
+                } else if tilde {
+                    VersionRangeStyle::Tilde
+                } else {
+                    VersionRangeStyle::Caret
+                },
+            };
+            match manager.set_trust_policy(trust_policy) {
+                Ok(()) => manager.add_packages(packages, options).await,
+                Err(e) => Err(e),
+            }
+        }
         Some(Commands::Remove { packages }) => manager.remove_packages(packages).await,
         Some(Commands::Run {
             script,
             args,
             workspaces,
             filter,
-        }) => {
-            if workspaces {
-                manager
-                    .run_script_workspaces(&script, args, filter.as_deref())
-                    .await
-            } else {
-                manager.run_script(&script, args).await
+            include_workspace_root,
+            serial,
+            continue_on_error,
+            stream,
+            bail,
+            workspace_concurrency,
+            node_options,
+            env_file,
+            overwrite_env,
+            silent,
+            inspect,
+            inspect_brk,
+        }) => match manager
+            .set_node_options(with_inspect(node_options, inspect, inspect_brk))
+            .and_then(|()| manager.set_env_file(env_file.as_deref(), overwrite_env))
+        {
+            Err(e) => Err(e),
+            Ok(()) if workspaces => {
+                let options = RunWorkspacesOptions {
+                    filter,
+                    include_root: include_workspace_root,
+                    serial,
+                    continue_on_error,
+                    stream,
+                    bail,
+                    concurrency: workspace_concurrency.map(usize::from),
+                };
+                manager.run_script_workspaces(&script, args, options).await
             }
-        }
-        Some(Commands::X { package, args }) => manager.exec_package(&package, args).await,
+            Ok(()) => manager.run_script(&script, args, silent).await,
+        },
+        Some(Commands::X {
+            package,
+            no_cache,
+            node_options,
+            args,
+        }) => match manager.set_node_options(node_options) {
+            Err(e) => Err(e),
+            Ok(()) => manager.exec_package(&package, args, no_cache).await,
+        },
         Some(Commands::Cache { command }) => manager.handle_cache_command(command).await,
-        Some(Commands::Install) => manager.install().await,
-        Some(Commands::List) => manager.list_packages().await,
-        Some(Commands::Outdated) => manager.outdated_packages().await,
-        Some(Commands::Update { packages }) => manager.update_packages(packages).await,
+        Some(Commands::Lock { command }) => manager.handle_lock_command(command).await,
+        Some(Commands::Diff { lockfile: true }) => manager.diff_lockfile().await,
+        Some(Commands::Diff { lockfile: false }) => Err(output::RpmError::Other {
+            message: "Nothing to diff".to_string(),
+            hint: Some("Use `rpm diff --lockfile`".to_string()),
+        }
+        .into()),
+        Some(Commands::Config { command }) => manager.handle_config_command(command),
+        Some(Commands::Token { command }) => manager.handle_token_command(command),
+        Some(Commands::Whoami { scope }) => manager.whoami(scope.as_deref()).await,
+        Some(Commands::Install {
+            ignore_engines,
+            no_optional,
+            include_optional,
+            timings,
+            legacy_peer_deps,
+            exact_versions,
+            prefer_symlinks,
+            only,
+            git_hooks,
+            prefer_dedupe,
+            timing_budget,
+            strict_timing,
+            strict_peer_deps,
+            recover,
+            graph_output,
+            prefix,
+            report_unused_deps,
+            source_glob,
+        }) => {
+            let options = InstallOptions {
+                ignore_engines,
+                no_optional,
+                include_optional,
+                timings,
+                legacy_peer_deps,
+                exact_versions,
+                prefer_symlinks,
+                only,
+                git_hooks,
+                prefer_dedupe,
+                timing_budget,
+                strict_timing,
+                strict_peer_deps,
+                recover,
+                graph_output,
+                report_unused_deps,
+                source_glob,
+            };
+            manager.with_prefix(prefix).install(options).await
+        }
+        Some(Commands::List {
+            parseable,
+            include_transitive,
+            depth,
+        }) => {
+            manager
+                .list_packages(parseable, include_transitive, depth)
+                .await
+        }
+        Some(Commands::Outdated { depth }) => manager.outdated_packages(depth).await,
+        Some(Commands::Update {
+            packages,
+            interactive,
+        }) => manager.update_packages(packages, interactive).await,
         Some(Commands::Dedupe) => manager.dedupe_packages().await,
         Some(Commands::Why { package }) => manager.why_package(&package).await,
-        Some(Commands::Workspaces) => manager.list_workspaces().await,
+        Some(Commands::Info {
+            package,
+            versions,
+            json,
+        }) => manager.info(&package, versions, json).await,
+        Some(Commands::Licenses { check }) => manager.licenses(check.as_deref()).await,
+        Some(Commands::Workspaces { command }) => match command {
+            None | Some(WorkspacesCommands::List) => manager.list_workspaces().await,
+            Some(WorkspacesCommands::Version {
+                changed: Some(bump),
+                ..
+            }) => manager.workspace_version_changed(&bump).await,
+            Some(WorkspacesCommands::Version {
+                package: Some(package),
+                bump: Some(bump),
+                ..
+            }) => manager.workspace_version(&package, &bump).await,
+            // clap requires a package and a bump unless --changed is given
+            Some(WorkspacesCommands::Version { .. }) => unreachable!(),
+        },
         None => {
             Cli::command().print_help().unwrap();
             return;
@@ -162,19 +775,20 @@ async fn main() {
     };
 
     if let Err(e) = result {
-        eprintln!(
-            "{}error:{} {}",
-            colors::BOLD_RED,
-            colors::RESET,
-            e
-        );
+        let message = format!("{}error:{} {}", colors::BOLD_RED, colors::RESET, e);
+        eprintln!("{}", maybe_strip_colors(color_mode, &message));
         std::process::exit(1);
     }
 
-    println!(
+    if quiet {
+        return;
+    }
+
+    let done = format!(
         "\n{}Done{} in {}",
         colors::BOLD_GREEN,
         colors::RESET,
         format_duration(start.elapsed().as_secs_f64())
     );
+    println!("{}", maybe_strip_colors(color_mode, &done));
 }
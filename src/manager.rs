@@ -1,15 +1,27 @@
-use crate::installer::Installer;
-use crate::output::{colors, RpmError};
-use crate::registry::{parse_package_alias, Registry};
-use crate::types::{LockFile, LockPackage, PackageJson, RegistryVersion};
+use crate::config::{is_dev_package, mask_token, npm_config_env, Config, Tokens, TrustPolicy};
+use crate::installer::{
+    add_elapsed, dir_fingerprint, is_machine_local, ConcurrencyModel, Installer,
+};
+use crate::imports;
+use crate::license;
+use crate::output::{self, colors, ColorMode, Reporter, RpmError};
+use crate::registry::{
+    parse_package_alias, version_satisfies, HttpOptions, Registry, DEFAULT_REGISTRY,
+    MAX_PARALLEL_DOWNLOADS,
+};
+use crate::types::{
+    split_override_path, LockFile, LockPackage, PackageJson, RegistryPackage, RegistryVersion,
+    WorkspaceMember,
+};
 use crate::workspace::Workspace;
 use anyhow::{Context, Result};
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use futures::stream::{FuturesUnordered, StreamExt};
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use std::collections::BTreeMap;
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::fs;
 use tokio::io::{AsyncBufReadExt, BufReader};
@@ -82,59 +94,1032 @@ fn is_platform_compatible(os: &[String], cpu: &[String]) -> bool {
     os_ok && cpu_ok
 }
 
+/// Peer dependencies of the root package.json that are not also regular or dev
+/// dependencies (those ranges take precedence). npm 7+ installs them like dependencies.
+fn root_peer_dependencies(package_json: &PackageJson) -> impl Iterator<Item = (&String, &String)> {
+    package_json.peer_dependencies.iter().filter(|(name, _)| {
+        !package_json.dependencies.contains_key(*name)
+            && !package_json.dev_dependencies.contains_key(*name)
+    })
+}
+
+/// Whether a nested copy can be replaced by the top-level one: either the versions are
+/// identical or the top-level version satisfies the range the parent declared
+fn can_dedupe(root_version: &str, nested_version: &str, declared_range: Option<&str>) -> bool {
+    if root_version == nested_version {
+        return true;
+    }
+    declared_range.and_then(|range| version_satisfies(range, root_version)) == Some(true)
+}
+
+/// Variables of a .env file: `KEY=value` lines, `#` comments, an optional `export`
+/// prefix and quoted values, which may span several lines. Double-quoted values
+/// understand `\"`, `\\`, `\n`, `\r` and `\t`. `${VAR}` is replaced with an earlier
+/// variable of the file or `lookup(VAR)`, except in single-quoted values. Unless
+/// `overwrite` is set, a variable `lookup` knows keeps that value. A quote that is never
+/// closed is an error naming its line.
+fn parse_env_file(
+    content: &str,
+    overwrite: bool,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> std::result::Result<Vec<(String, String)>, String> {
+    let mut vars: Vec<(String, String)> = Vec::new();
+    let mut lines = content.lines().enumerate();
+    while let Some((index, line)) = lines.next() {
+        let line = line.trim_start();
+        if line.trim_end().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim();
+        if key.is_empty() {
+            continue;
+        }
+
+        let value = value.trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'');
+        let (value, substitute) = if let Some(quote) = quote {
+            // Read up to the closing quote, continuing on the next lines if needed;
+            // anything after it (such as a comment) is ignored
+            let mut quoted = String::new();
+            let mut rest = &value[1..];
+            loop {
+                let mut chars = rest.chars();
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    match c {
+                        c if c == quote => {
+                            closed = true;
+                            break;
+                        }
+                        '\\' if quote == '"' => match chars.next() {
+                            Some('n') => quoted.push('\n'),
+                            Some('r') => quoted.push('\r'),
+                            Some('t') => quoted.push('\t'),
+                            Some(c @ ('"' | '\\')) => quoted.push(c),
+                            Some(c) => {
+                                quoted.push('\\');
+                                quoted.push(c);
+                            }
+                            None => quoted.push('\\'),
+                        },
+                        c => quoted.push(c),
+                    }
+                }
+                if closed {
+                    break;
+                }
+                let Some((_, next)) = lines.next() else {
+                    return Err(format!(
+                        "line {}: the value of {} is missing its closing {} quote",
+                        index + 1,
+                        key,
+                        quote
+                    ));
+                };
+                quoted.push('\n');
+                rest = next;
+            }
+            (quoted, quote == '"')
+        } else {
+            // Unquoted values end at a " #" comment
+            let value = value.split(" #").next().unwrap_or(value).trim_end();
+            (value.to_string(), true)
+        };
+
+        if !overwrite {
+            if let Some(existing) = lookup(key) {
+                vars.push((key.to_string(), existing));
+                continue;
+            }
+        }
+
+        let value = if substitute {
+            let mut result = String::new();
+            let mut rest = value.as_str();
+            while let Some(start) = rest.find("${") {
+                let Some(end) = rest[start..].find('}') else {
+                    break;
+                };
+                let name = &rest[start + 2..start + end];
+                result.push_str(&rest[..start]);
+                let known = vars.iter().rev().find(|(key, _)| key == name);
+                match known {
+                    Some((_, value)) => result.push_str(value),
+                    None => result.push_str(&lookup(name).unwrap_or_default()),
+                }
+                rest = &rest[start + end + 1..];
+            }
+            result.push_str(rest);
+            result
+        } else {
+            value
+        };
+        vars.push((key.to_string(), value));
+    }
+    Ok(vars)
+}
+
+/// npm_package_* environment variables for scripts of `package_json` (located at `path`)
+fn package_env(package_json: &PackageJson, path: &Path) -> Vec<(String, String)> {
+    let mut env = vec![(
+        "npm_package_json".to_string(),
+        path.to_string_lossy().to_string(),
+    )];
+    for (key, value) in &package_json.config {
+        env.push((format!("npm_package_config_{}", key), value.clone()));
+    }
+    env
+}
+
+/// Binaries declared by a package's `bin` field (name -> path in the package)
+fn bin_entries(package_name: &str, bin: &serde_json::Value) -> BTreeMap<String, String> {
+    match bin {
+        serde_json::Value::String(s) => {
+            // A single bin is named after the package, without its scope
+            let bin_name = package_name.rsplit('/').next().unwrap_or(package_name);
+            let mut map = BTreeMap::new();
+            map.insert(bin_name.to_string(), s.clone());
+            map
+        }
+        serde_json::Value::Object(o) => {
+            let mut map = BTreeMap::new();
+            for (k, v) in o {
+                if let Some(s) = v.as_str() {
+                    map.insert(k.clone(), s.to_string());
+                }
+            }
+            map
+        }
+        _ => BTreeMap::new(),
+    }
+}
+
+/// Path of a package binary relative to the directory of its shim, with Windows
+/// separators. `node_modules` is where node_modules is from there (".." for
+/// node_modules/.bin); scoped packages contain a '/' in their name too.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn windows_bin_path(node_modules: &str, package_name: &str, bin_path: &str) -> String {
+    let bin_path = bin_path.trim_start_matches("./");
+    format!("{}\\{}\\{}", node_modules, package_name, bin_path).replace('/', "\\")
+}
+
+/// Program (with its arguments) a Windows shim runs a package binary with, from the
+/// binary's shebang ("#!/usr/bin/env node" -> "node", "#!/bin/sh -e" -> "sh -e").
+/// JavaScript files without a shebang run with node, other files are run directly (None).
+#[cfg_attr(not(windows), allow(dead_code))]
+fn shim_program(script: &[u8], bin_path: &str) -> Option<String> {
+    let first_line = script.split(|b| *b == b'\n').next().unwrap_or_default();
+    let first_line = String::from_utf8_lossy(first_line);
+    let Some(shebang) = first_line.trim_end().strip_prefix("#!") else {
+        let is_js = [".js", ".cjs", ".mjs"].iter().any(|ext| bin_path.ends_with(ext));
+        return is_js.then(|| "node".to_string());
+    };
+    let mut words = shebang.split_whitespace().peekable();
+    let mut program = words.next()?;
+    if program == "env" || program.ends_with("/env") {
+        // "#!/usr/bin/env -S node --flag"
+        words.next_if_eq(&"-S");
+        program = words.next()?;
+    }
+    let program = program.rsplit('/').next().unwrap_or(program);
+    Some(std::iter::once(program).chain(words).collect::<Vec<_>>().join(" "))
+}
+
+/// .cmd shim running a package binary (a path from `windows_bin_path`) with `program`
+/// (see `shim_program`)
+#[cfg_attr(not(windows), allow(dead_code))]
+fn cmd_shim(target: &str, program: Option<&str>) -> String {
+    match program {
+        Some(program) => format!("@ECHO off\r\n{} \"%~dp0\\{}\" %*\r\n", program, target),
+        None => format!("@ECHO off\r\n\"%~dp0\\{}\" %*\r\n", target),
+    }
+}
+
+/// PowerShell shim running a package binary with `program`, for powershell.exe 5 and pwsh
+#[cfg_attr(not(windows), allow(dead_code))]
+fn ps1_shim(target: &str, program: Option<&str>) -> String {
+    let command = match program {
+        Some(program) => format!("& {} \"$basedir\\{}\" $args", program, target),
+        None => format!("& \"$basedir\\{}\" $args", target),
+    };
+    format!(
+        "#!/usr/bin/env pwsh\r\n\
+         $basedir = Split-Path $MyInvocation.MyCommand.Definition -Parent\r\n\
+         {}\r\n\
+         exit $LASTEXITCODE\r\n",
+        command
+    )
+}
+
+/// Installed packages for `rpm list --include-transitive`: name -> (version, names of
+/// its dependencies)
+type InstalledTree = BTreeMap<String, (String, Vec<String>)>;
+
+/// Tree lines of the installed dependencies of `name`, children at `level` (1 for the
+/// dependencies of a direct dependency) down to `depth` (None for no limit). Cycles are
+/// cut with "(circular)", subtrees printed before with "(deduped)".
+fn push_dependency_lines(
+    name: &str,
+    installed: &InstalledTree,
+    level: usize,
+    depth: Option<usize>,
+    ancestors: &mut Vec<String>,
+    expanded: &mut HashSet<String>,
+    lines: &mut Vec<String>,
+) {
+    if depth.is_some_and(|depth| level > depth) {
+        return;
+    }
+    let Some((_, deps)) = installed.get(name) else {
+        return;
+    };
+    for dep in deps {
+        let Some((version, dep_deps)) = installed.get(dep) else {
+            continue;
+        };
+        let has_children = dep_deps.iter().any(|d| installed.contains_key(d));
+        let marker = if ancestors.contains(dep) {
+            " \x1b[33m(circular)\x1b[0m"
+        } else if has_children && expanded.contains(dep) {
+            " \x1b[90m(deduped)\x1b[0m"
+        } else {
+            ""
+        };
+        lines.push(format!(
+            "  {}\x1b[90m├─\x1b[0m {}@\x1b[90m{}\x1b[0m{}",
+            "   ".repeat(level),
+            dep,
+            version,
+            marker
+        ));
+        if marker.is_empty() {
+            expanded.insert(dep.clone());
+            ancestors.push(dep.clone());
+            push_dependency_lines(dep, installed, level + 1, depth, ancestors, expanded, lines);
+            ancestors.pop();
+        }
+    }
+}
+
+/// Recent versions `rpm add --interactive` offers besides the dist-tags
+const INTERACTIVE_VERSIONS: usize = 10;
+
+/// Ask which version of a package to add: its dist-tags, then the most recent
+/// versions. None when the prompt is cancelled.
+fn select_version(name: &str, package: &RegistryPackage) -> Result<Option<String>> {
+    let mut tags: Vec<(&String, &String)> = package.dist_tags.iter().collect();
+    tags.sort();
+    let mut versions: Vec<semver::Version> = package
+        .versions
+        .keys()
+        .filter_map(|v| semver::Version::parse(v).ok())
+        .collect();
+    versions.sort_by(|a, b| b.cmp(a));
+
+    let mut choices: Vec<(String, String)> = tags
+        .iter()
+        .map(|(tag, version)| (format!("{} ({})", tag, version), version.to_string()))
+        .collect();
+    choices.extend(versions.iter().take(INTERACTIVE_VERSIONS).map(|version| {
+        let date = package
+            .time
+            .get(&version.to_string())
+            .and_then(|t| t.get(..10))
+            .map(|date| format!(" ({})", date))
+            .unwrap_or_default();
+        (format!("{}{}", version, date), version.to_string())
+    }));
+    let items: Vec<&String> = choices.iter().map(|(label, _)| label).collect();
+
+    let selection = dialoguer::Select::new()
+        .with_prompt(format!("Version of {}", name))
+        .items(&items)
+        .default(0)
+        .interact_opt()?;
+    Ok(selection.map(|i| choices[i].1.clone()))
+}
+
+/// Versions `rpm info --versions` prints before asking to show more
+const VERSIONS_PAGE_SIZE: usize = 40;
+
+/// Most workspace scripts `rpm run --workspaces` runs at once without --workspace-concurrency
+const DEFAULT_WORKSPACE_CONCURRENCY: usize = 10;
+
+/// Colors of the workspace prefixes of `rpm run --workspaces --stream`, in turn
+const WORKSPACE_COLORS: [&str; 5] = [
+    colors::CYAN,
+    colors::MAGENTA,
+    colors::YELLOW,
+    colors::GREEN,
+    colors::BLUE,
+];
+
+/// Print every line of a script's output as soon as it is written, after `prefix`
+async fn stream_lines<R: tokio::io::AsyncRead + Unpin>(
+    reader: Option<R>,
+    prefix: &str,
+    multi_progress: &MultiProgress,
+) {
+    let Some(reader) = reader else {
+        return;
+    };
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let _ = multi_progress.println(format!("{} {}", prefix, line));
+    }
+}
+
+/// Git hook managers `rpm install --git-hooks` knows how to set up
+const HOOK_MANAGERS: [&str; 3] = ["husky", "simple-git-hooks", "lefthook"];
+
+/// The hook manager and the lifecycle script (prepare or postinstall) setting it up
+fn git_hooks_script(scripts: &HashMap<String, String>) -> Option<(&'static str, &str)> {
+    ["prepare", "postinstall"].iter().find_map(|lifecycle| {
+        let script = scripts.get(*lifecycle)?;
+        let hook_manager = HOOK_MANAGERS.iter().find(|m| script.contains(*m))?;
+        Some((*hook_manager, script.as_str()))
+    })
+}
+
+/// Look up an executable in PATH, like `which`
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path).find_map(|dir| {
+        let candidates = if cfg!(windows) {
+            vec![dir.join(format!("{}.exe", program)), dir.join(format!("{}.cmd", program))]
+        } else {
+            vec![dir.join(program)]
+        };
+        candidates.into_iter().find(|candidate| candidate.is_file())
+    })
+}
+
+/// Build a command running `script` through the configured script shell
+fn shell_command(shell: &[String], script: &str) -> Command {
+    let mut command = Command::new(&shell[0]);
+    command.args(&shell[1..]).arg(script);
+    command
+}
+
+/// Check that a package.json (at `path`) has a name and a semver version, they end up
+/// in rpm-lock.json
+fn validate_package_json(package_json: &PackageJson, path: &Path) -> Result<()> {
+    let problem = if package_json.name.trim().is_empty() {
+        "has an empty \"name\"".to_string()
+    } else if semver::Version::parse(&package_json.version).is_err() {
+        format!("has an invalid \"version\" '{}'", package_json.version)
+    } else {
+        return Ok(());
+    };
+    Err(RpmError::Other {
+        message: format!("{} {}", path.display(), problem),
+        hint: Some("Use a non-empty name and a semver version like 1.0.0".to_string()),
+    }
+    .into())
+}
+
+/// Parse the --before date, either RFC 3339 or a plain date (midnight UTC)
+fn parse_before_date(date: &str) -> Result<chrono::DateTime<chrono::FixedOffset>> {
+    chrono::DateTime::parse_from_rfc3339(date)
+        .or_else(|_| chrono::DateTime::parse_from_rfc3339(&format!("{}T00:00:00Z", date)))
+        .map_err(|_| {
+            RpmError::Other {
+                message: format!("Invalid date '{}' for --before", date),
+                hint: Some(
+                    "Use an ISO 8601 date like 2023-01-01 or 2023-01-01T12:00:00Z".to_string(),
+                ),
+            }
+            .into()
+        })
+}
+
+/// List the package directories directly under node_modules (sorted)
+/// Scoped packages are included as node_modules/@scope/name
+async fn scan_node_modules(node_modules: &std::path::Path) -> Vec<PathBuf> {
+    let mut package_dirs: Vec<PathBuf> = Vec::new();
+
+    if let Ok(mut entries) = fs::read_dir(node_modules).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+
+            if name.starts_with('.') || !path.is_dir() {
+                continue;
+            }
+
+            // Scoped packages live one level deeper (@scope/name)
+            if name.starts_with('@') {
+                if let Ok(mut scoped_entries) = fs::read_dir(&path).await {
+                    while let Ok(Some(scoped_entry)) = scoped_entries.next_entry().await {
+                        package_dirs.push(scoped_entry.path());
+                    }
+                }
+                continue;
+            }
+
+            package_dirs.push(path);
+        }
+    }
+
+    package_dirs.sort();
+    package_dirs
+}
+
 /// Check if a RegistryVersion is compatible with current platform
 fn is_version_platform_compatible(version: &RegistryVersion) -> bool {
     is_platform_compatible(&version.os, &version.cpu)
 }
 
+/// Options for `rpm add`
+#[derive(Debug, Clone, Default)]
+pub struct AddOptions {
+    /// Save to devDependencies instead of dependencies
+    pub dev: bool,
+    /// Save to peerDependencies instead of dependencies
+    pub peer: bool,
+    /// Save packages matching the dev patterns to devDependencies
+    pub auto_dev: bool,
+    /// Add to the workspace root package.json, wherever rpm runs inside the workspace
+    pub workspace_root: bool,
+    /// Add to this workspace member's package.json
+    pub workspace: Option<String>,
+    /// Add to every workspace member's package.json
+    pub all_workspaces: bool,
+    /// Only consider versions published before this date (RFC 3339 or YYYY-MM-DD)
+    pub before: Option<String>,
+    /// Dist-tag to install for packages given without a version
+    pub tag: Option<String>,
+    /// Install into ~/.rpm/global and link the binaries into ~/.rpm/bin
+    pub global: bool,
+    /// Ask for the version of packages given without one
+    pub interactive: bool,
+    /// How the resolved version is saved to package.json
+    pub range_style: VersionRangeStyle,
+}
+
+/// Range `rpm add` saves for the resolved version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VersionRangeStyle {
+    /// "^1.2.3"
+    #[default]
+    Caret,
+    /// "~1.2.3" (--tilde)
+    Tilde,
+    /// "1.2.3" (--exact)
+    Exact,
+}
+
+impl VersionRangeStyle {
+    pub fn range(self, version: &str) -> String {
+        match self {
+            Self::Caret => format!("^{}", version),
+            Self::Tilde => format!("~{}", version),
+            Self::Exact => version.to_string(),
+        }
+    }
+}
+
+/// A peer dependency of a package being added that the installed version does not satisfy
+struct PeerConflict {
+    package: String,
+    version: String,
+    peer: String,
+    range: String,
+    installed: String,
+}
+
+/// Options for `rpm install`
+#[derive(Debug, Clone, Default)]
+pub struct InstallOptions {
+    /// Skip the engines check of package.json
+    pub ignore_engines: bool,
+    /// Skip optional dependencies (remembered in rpm-lock.json)
+    pub no_optional: bool,
+    /// Install optional dependencies again after a --no-optional install
+    pub include_optional: bool,
+    /// Print where the install time was spent
+    pub timings: bool,
+    /// Do not auto-install peer dependencies of packages
+    pub legacy_peer_deps: bool,
+    /// Install locked versions even when they do not satisfy the declared ranges
+    pub exact_versions: bool,
+    /// Symlink every package to the store, including packages with install scripts
+    pub prefer_symlinks: bool,
+    /// Install only production or only development dependencies of package.json
+    pub only: Option<DependencySubset>,
+    /// Run the git hook manager's setup script (husky, simple-git-hooks, lefthook)
+    pub git_hooks: bool,
+    /// Remove nested copies of packages the top-level copy can replace, as part of
+    /// the install
+    pub prefer_dedupe: bool,
+    /// Warn when the install takes longer than this many milliseconds
+    pub timing_budget: Option<u64>,
+    /// Fail instead of warning when the timing budget is exceeded
+    pub strict_timing: bool,
+    /// Fail on dependencies declared with different versions across a workspace
+    pub strict_peer_deps: bool,
+    /// Remove broken packages from node_modules before the incremental check
+    pub recover: bool,
+    /// Write the dependency graph of rpm-lock.json to this file after installing
+    pub graph_output: Option<PathBuf>,
+    /// Warn about `dependencies` no source file imports
+    pub report_unused_deps: bool,
+    /// Which files --report-unused-deps scans, instead of JS/TS files by extension
+    pub source_glob: Option<String>,
+}
+
+/// Dependencies selected by `rpm install --only`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DependencySubset {
+    /// `dependencies` (and root peer dependencies), no devDependencies
+    #[value(alias = "prod")]
+    Production,
+    /// Only `devDependencies`
+    #[value(alias = "dev")]
+    Development,
+}
+
+/// Options for `rpm run --workspaces`
+#[derive(Debug, Clone, Default)]
+pub struct RunWorkspacesOptions {
+    /// Only run in workspaces whose name contains this pattern
+    pub filter: Option<String>,
+    /// Also run the script of the root package.json
+    pub include_root: bool,
+    /// Run one workspace at a time and stop at the first failure
+    pub serial: bool,
+    /// Keep running the remaining workspaces after a failure in serial mode
+    pub continue_on_error: bool,
+    /// Print output lines as they come, prefixed with the workspace name
+    pub stream: bool,
+    /// Stop the other running workspaces as soon as one fails
+    pub bail: bool,
+    /// Most workspace scripts running at once in parallel mode
+    pub concurrency: Option<usize>,
+}
+
+/// Read a JSON file as a value, keeping every field and their order
+async fn read_json_file(path: &Path) -> Result<serde_json::Value> {
+    let content = fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Run git in `dir`, returns its trimmed output or fails with its error message
+async fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .await
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        return Err(RpmError::Other {
+            message: format!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            hint: None,
+        }
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Resolves on Ctrl+C, or SIGTERM on unix
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// DevTools URL for Node.js's "Debugger listening on ws://host:port/id" line
+fn devtools_url(line: &str) -> Option<String> {
+    let socket = line.strip_prefix("Debugger listening on ws://")?.trim();
+    Some(format!(
+        "devtools://devtools/bundled/js_app.html?experiments=true&v8only=true&ws={}",
+        socket
+    ))
+}
+
+/// Node.js flags that load a module before the script (`--require`, `--import`, loaders)
+const PRELOAD_FLAGS: &[&str] = &["--require", "--import", "--loader", "--experimental-loader"];
+
+/// Whether Node.js flags preload a module (see `PRELOAD_FLAGS`, `-r`)
+fn requires_module(flags: &str) -> bool {
+    flags.split_whitespace().any(|flag| {
+        let name = flag.split('=').next().unwrap_or(flag);
+        name == "-r" || PRELOAD_FLAGS.contains(&name)
+    })
+}
+
+/// Scope of the project in `root`, from its package.json name
+async fn project_scope(root: &Path) -> Option<String> {
+    let content = fs::read_to_string(root.join("package.json")).await.ok()?;
+    let pkg = serde_json::from_str::<PackageJson>(&content).ok()?;
+    package_scope(&pkg.name).map(str::to_string)
+}
+
+/// "@scope" of a scoped package name
+fn package_scope(name: &str) -> Option<&str> {
+    name.starts_with('@').then(|| name.split('/').next()).flatten()
+}
+
+/// Stop the scripts still running in `running` and what they started: SIGTERM to their
+/// process groups on unix, `taskkill /T` on Windows. Returns how many there were.
+fn terminate_running(running: &DashMap<String, u32>) -> usize {
+    let pids: Vec<u32> = running.iter().map(|entry| *entry.value()).collect();
+    #[cfg(unix)]
+    for pid in &pids {
+        unsafe {
+            libc::kill(-(*pid as libc::pid_t), libc::SIGTERM);
+        }
+    }
+    #[cfg(windows)]
+    for pid in &pids {
+        let _ = std::process::Command::new("taskkill")
+            .args(["/T", "/F", "/PID", &pid.to_string()])
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status();
+    }
+    pids.len()
+}
+
+/// A dependency checked by `rpm outdated`
+struct OutdatedCandidate {
+    name: String,
+    range: String,
+    is_dev: bool,
+    /// 0 for dependencies declared in package.json
+    depth: usize,
+    /// Package that brought in a transitive dependency
+    parent: Option<String>,
+}
+
 #[derive(Clone)]
 pub struct Manager {
     registry: Registry,
     installer: Installer,
     installed: Arc<DashMap<String, String>>,
     semaphore: Arc<Semaphore>,
+    // Permits the semaphore currently hands out (adapted to registry latency)
+    concurrency_limit: Arc<std::sync::Mutex<usize>>,
     multi_progress: MultiProgress,
     lockfile: Arc<tokio::sync::Mutex<LockFile>>,
     postinstalls: Arc<DashMap<String, (PathBuf, String)>>,
+    // Process ids of the postinstall scripts currently running, by package name
+    running_postinstalls: Arc<DashMap<String, u32>>,
+    // Version overrides from the root package.json (name or "parent/name" -> version)
+    overrides: Arc<DashMap<String, String>>,
+    // Packages a scoped override was found to conflict with (warned once)
+    override_conflicts: Arc<DashSet<String>>,
+    // Warnings already printed this run (see warn_once)
+    warnings_shown: Arc<DashSet<String>>,
+    // Platform compatibility of optional dependencies ("name@range" -> compatible)
+    optional_compat: Arc<DashMap<String, bool>>,
+    config: Config,
     auto_confirm: bool,
     ignore_scripts: bool,
+    trust_policy: Option<TrustPolicy>,
+    postinstall_timeout: std::time::Duration,
+    unsafe_perm: bool,
+    // Node.js flags prepended to NODE_OPTIONS for scripts and binaries (see --node-options)
+    node_options: Option<String>,
+    // Variables loaded from --env-file for scripts
+    env_file: Vec<(String, String)>,
+    reporter: Reporter,
+    // Whether optional dependencies are installed (see --no-optional)
+    install_optional: Arc<AtomicBool>,
+    // Whether peer dependencies of packages are installed (see --legacy-peer-deps)
+    install_peers: Arc<AtomicBool>,
+    // Use locked versions without checking them against ranges (see --exact-versions)
+    exact_versions: Arc<AtomicBool>,
+    // Which package.json dependency maps are installed (see --only)
+    install_prod: Arc<AtomicBool>,
+    install_dev: Arc<AtomicBool>,
+    // `node --version` output, detected once on first use
+    node_version: Arc<tokio::sync::OnceCell<Option<String>>>,
+    // Cumulative time spent per install phase in nanoseconds (see --timings)
+    resolve_nanos: Arc<AtomicU64>,
+    download_nanos: Arc<AtomicU64>,
+    extract_nanos: Arc<AtomicU64>,
     // Progress tracking
     packages_installed: Arc<AtomicUsize>,
     packages_resolved: Arc<AtomicUsize>,
     packages_cached: Arc<AtomicUsize>,
+    // Unpacked bytes of the packages being installed and of those done, when the
+    // registry reports sizes
+    bytes_total: Arc<AtomicU64>,
+    bytes_done: Arc<AtomicU64>,
     progress_bar: Arc<tokio::sync::Mutex<Option<ProgressBar>>>,
     // Track currently processing packages for better progress display
     current_packages: Arc<DashMap<String, String>>, // name -> status ("resolving", "installing")
     install_start_time: Arc<tokio::sync::Mutex<Option<std::time::Instant>>>,
+    // Whether output is colored (see --color / --no-color)
+    color_mode: ColorMode,
+    // Directory holding package.json, rpm-lock.json and node_modules (see --prefix),
+    // the current directory when None
+    prefix: Option<PathBuf>,
 }
 
 impl Manager {
-    pub fn new(force_no_cache: bool, auto_confirm: bool, ignore_scripts: bool) -> Self {
+    // The color mode is needed here already, loading the config may warn
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        force_no_cache: bool,
+        auto_confirm: bool,
+        ignore_scripts: bool,
+        unsafe_perm: bool,
+        symlinks: bool,
+        reporter: Reporter,
+        color_mode: ColorMode,
+        http: HttpOptions,
+    ) -> Self {
+        // Only the default reporter draws progress bars
+        let multi_progress = match reporter {
+            Reporter::Default => MultiProgress::new(),
+            Reporter::Silent | Reporter::Dot => {
+                MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+            }
+        };
+
+        let config = Config::load().unwrap_or_else(|e| {
+            output::warning(color_mode, &format!("ignoring config: {:#}", e));
+            Config::default()
+        });
+        // One client for metadata and tarballs, so both share the connection pool
+        let client = http.build_client();
+        let installer = Installer::new(client.clone(), force_no_cache, symlinks, color_mode);
+        let download_nanos = installer.download_nanos.clone();
+        let extract_nanos = installer.extract_nanos.clone();
+        let trust_policy = config.trust_policy();
+        let postinstall_timeout = config.postinstall_timeout();
+
+        // npm_config_registry (set by many CI images) replaces the default registry,
+        // the mirror only stands in for registry.npmjs.org
+        let registry_url = npm_config_env("registry");
+        let registry = match &registry_url {
+            Some(url) => Registry::with_client(client)
+                .with_base_url(url)
+                .with_token(config.auth_token(url)),
+            None => Registry::with_client(client)
+                .with_token(config.auth_token(DEFAULT_REGISTRY))
+                .with_mirror(config.mirror_registry()),
+        };
+
         Self {
-            registry: Registry::new(),
-            installer: Installer::new(force_no_cache),
+            registry: registry.with_scope_registries(config.scope_registries()),
+            installer,
             installed: Arc::new(DashMap::new()),
-            semaphore: Arc::new(Semaphore::new(50)), // Limit concurrency
-            multi_progress: MultiProgress::new(),
-            lockfile: Arc::new(tokio::sync::Mutex::new(LockFile {
-                name: "".to_string(),
-                version: "".to_string(),
-                lockfile_version: 3,
-                packages: BTreeMap::new(),
-            })),
+            semaphore: Arc::new(Semaphore::new(MAX_PARALLEL_DOWNLOADS)), // Limit concurrency
+            concurrency_limit: Arc::new(std::sync::Mutex::new(MAX_PARALLEL_DOWNLOADS)),
+            multi_progress,
+            lockfile: Arc::new(tokio::sync::Mutex::new(LockFile::empty())),
             postinstalls: Arc::new(DashMap::new()),
+            running_postinstalls: Arc::new(DashMap::new()),
+            overrides: Arc::new(DashMap::new()),
+            override_conflicts: Arc::new(DashSet::new()),
+            warnings_shown: Arc::new(DashSet::new()),
+            optional_compat: Arc::new(DashMap::new()),
+            config,
             auto_confirm,
             ignore_scripts,
+            trust_policy,
+            postinstall_timeout,
+            unsafe_perm,
+            node_options: None,
+            env_file: Vec::new(),
+            reporter,
+            install_optional: Arc::new(AtomicBool::new(true)),
+            install_peers: Arc::new(AtomicBool::new(true)),
+            exact_versions: Arc::new(AtomicBool::new(false)),
+            install_prod: Arc::new(AtomicBool::new(true)),
+            install_dev: Arc::new(AtomicBool::new(true)),
+            node_version: Arc::new(tokio::sync::OnceCell::new()),
+            resolve_nanos: Arc::new(AtomicU64::new(0)),
+            download_nanos,
+            extract_nanos,
             packages_installed: Arc::new(AtomicUsize::new(0)),
             packages_resolved: Arc::new(AtomicUsize::new(0)),
             packages_cached: Arc::new(AtomicUsize::new(0)),
+            bytes_total: Arc::new(AtomicU64::new(0)),
+            bytes_done: Arc::new(AtomicU64::new(0)),
             progress_bar: Arc::new(tokio::sync::Mutex::new(None)),
             current_packages: Arc::new(DashMap::new()),
             install_start_time: Arc::new(tokio::sync::Mutex::new(None)),
+            color_mode,
+            prefix: None,
+        }
+    }
+
+    /// Install into this directory instead of the current one (see --prefix)
+    pub fn with_prefix(mut self, prefix: Option<PathBuf>) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    /// Directory holding the project's package.json, rpm-lock.json and node_modules
+    fn project_dir(&self) -> Result<PathBuf> {
+        match &self.prefix {
+            Some(prefix) => Ok(std::env::current_dir()?.join(prefix)),
+            None => Ok(std::env::current_dir()?),
+        }
+    }
+
+    /// The installed Node.js version (e.g. "20.11.0"), None if node is not available.
+    /// `node --version` is only spawned on the first call.
+    async fn get_node_version(&self) -> Option<String> {
+        self.node_version
+            .get_or_init(|| async {
+                let output = Command::new("node").arg("--version").output().await.ok()?;
+                if !output.status.success() {
+                    return None;
+                }
+                let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                Some(version.trim_start_matches('v').to_string())
+            })
+            .await
+            .clone()
+    }
+
+    /// Environment for processes that run node. Packages symlinked to the store must
+    /// resolve their dependencies from the project's node_modules, not the store.
+    /// npm_execpath lets tools like npm-run-all call back into rpm, npm_config_* tells
+    /// tools reading npm's config (node-pre-gyp) where the cache is.
+    fn node_env(&self) -> Vec<(String, String)> {
+        let mut env = vec![
+            (
+                "npm_config_cache".to_string(),
+                self.installer.cache_dir.to_string_lossy().to_string(),
+            ),
+            ("npm_config_global".to_string(), "false".to_string()),
+        ];
+        if self.installer.uses_symlinks() {
+            // _MAIN covers the entry script too, e.g. a bin run from node_modules/.bin
+            env.push(("NODE_PRESERVE_SYMLINKS".to_string(), "1".to_string()));
+            env.push(("NODE_PRESERVE_SYMLINKS_MAIN".to_string(), "1".to_string()));
+        }
+        if let Ok(exe) = std::env::current_exe() {
+            env.push(("npm_execpath".to_string(), exe.to_string_lossy().to_string()));
+        }
+        if let Some(node) = find_in_path("node") {
+            env.push(("npm_node_execpath".to_string(), node.to_string_lossy().to_string()));
+        }
+        env
+    }
+
+    /// Extract tarballs according to `model` (see --concurrency-model)
+    pub fn with_concurrency_model(mut self, model: ConcurrencyModel) -> Self {
+        if let Err(e) = self.installer.set_concurrency_model(model) {
+            output::warning(
+                self.color_mode,
+                &format!(
+                    "extracting on the async pool, no extraction threads: {:#}",
+                    e
+                ),
+            );
+        }
+        self
+    }
+
+    /// Fetch unscoped packages from this registry, over npm_config_registry
+    pub fn with_registry(mut self, url: Option<String>) -> Self {
+        if let Some(url) = url {
+            self.registry = self
+                .registry
+                .with_base_url(&url)
+                .with_token(self.config.auth_token(&url))
+                .with_mirror(None);
+        }
+        self
+    }
+
+    /// Kill postinstall scripts after this many seconds instead of the configured timeout
+    pub fn with_postinstall_timeout(mut self, secs: Option<u64>) -> Self {
+        if let Some(secs) = secs {
+            self.postinstall_timeout = std::time::Duration::from_secs(secs);
+        }
+        self
+    }
+
+    /// Set the Node.js flags passed to scripts and binaries via NODE_OPTIONS. Preloading
+    /// modules (--require, --import, --loader) is only allowed with --unsafe-perm.
+    pub fn set_node_options(&mut self, flags: Option<String>) -> Result<()> {
+        if let Some(flags) = &flags {
+            if !self.unsafe_perm && requires_module(flags) {
+                return Err(RpmError::Other {
+                    message: format!("--node-options may not preload modules: {}", flags),
+                    hint: Some(
+                        "Pass --unsafe-perm to allow --require, --import and --loader".to_string(),
+                    ),
+                }
+                .into());
+            }
+        }
+        self.node_options = flags;
+        Ok(())
+    }
+
+    /// Use a trust policy for postinstall scripts and save it to ~/.rpm/config.json
+    pub fn set_trust_policy(&mut self, policy: Option<TrustPolicy>) -> Result<()> {
+        let Some(policy) = policy else {
+            return Ok(());
+        };
+        let mut config = Config::load()?;
+        config.set("trust-policy", policy.as_str())?;
+        config.save()?;
+        self.trust_policy = Some(policy);
+        Ok(())
+    }
+
+    /// Load the variables of a .env file for scripts. Variables already set in the
+    /// environment are kept unless `overwrite` is set.
+    pub fn set_env_file(&mut self, path: Option<&Path>, overwrite: bool) -> Result<()> {
+        let Some(path) = path else {
+            return Ok(());
+        };
+        let content = std::fs::read_to_string(path).map_err(|e| RpmError::Other {
+            message: format!("Could not read env file {}: {}", path.display(), e),
+            hint: None,
+        })?;
+        self.env_file = parse_env_file(&content, overwrite, |name| std::env::var(name).ok())
+            .map_err(|e| RpmError::Other {
+                message: format!("Invalid env file {}: {}", path.display(), e),
+                hint: Some("Close the quote, or escape it with a backslash".to_string()),
+            })?;
+        Ok(())
+    }
+
+    /// NODE_OPTIONS with the --node-options flags prepended to the inherited value
+    fn node_options_env(&self) -> Option<(String, String)> {
+        let flags = self.node_options.as_deref()?;
+        let value = match std::env::var("NODE_OPTIONS") {
+            Ok(existing) if !existing.trim().is_empty() => format!("{} {}", flags, existing),
+            _ => flags.to_string(),
+        };
+        Some(("NODE_OPTIONS".to_string(), value))
+    }
+
+    /// Print a line unless the silent reporter is active
+    fn report(&self, line: &str) {
+        if self.reporter != Reporter::Silent {
+            output::print_line(self.color_mode, line);
+        }
+    }
+
+    /// Print an error line, above the progress bars when they are drawn
+    fn report_error(&self, line: String) {
+        if self.reporter == Reporter::Default && !self.multi_progress.is_hidden() {
+            let _ = self
+                .multi_progress
+                .println(output::maybe_strip_colors(self.color_mode, &line));
+        } else {
+            output::eprint_line(self.color_mode, &line);
+        }
+    }
+
+    /// Print a warning unless the same one was already printed this run
+    fn warn_once(&self, message: &str) {
+        if self.warnings_shown.insert(message.to_string()) {
+            output::warning(self.color_mode, message);
+        }
+    }
+
+    /// Print a dot for an installed package (dot reporter)
+    fn report_dot(&self) {
+        if self.reporter == Reporter::Dot {
+            print!(".");
+            let _ = std::io::Write::flush(&mut std::io::stdout());
+        }
+    }
+
+    /// End the line of dots once an install is done (dot reporter)
+    fn finish_dots(&self) {
+        if self.reporter == Reporter::Dot && self.packages_installed.load(Ordering::Relaxed) > 0 {
+            println!();
         }
     }
 
     fn create_spinner(&self) -> ProgressBar {
+        if self.reporter != Reporter::Default {
+            return ProgressBar::with_draw_target(None, ProgressDrawTarget::hidden());
+        }
         let spinner = self.multi_progress.add(ProgressBar::new_spinner());
         spinner.set_style(
             ProgressStyle::default_spinner()
@@ -147,6 +1132,9 @@ impl Manager {
     }
 
     fn create_install_progress(&self) -> ProgressBar {
+        if self.reporter != Reporter::Default {
+            return ProgressBar::with_draw_target(None, ProgressDrawTarget::hidden());
+        }
         let pb = self.multi_progress.add(ProgressBar::new_spinner());
         pb.set_style(
             ProgressStyle::default_spinner()
@@ -163,12 +1151,21 @@ impl Manager {
         let resolved = self.packages_resolved.load(Ordering::Relaxed);
         let cached = self.packages_cached.load(Ordering::Relaxed);
 
-        // Get current package being processed (most recent one)
-        let current_pkg: Option<String> = self
-            .current_packages
-            .iter()
-            .next()
-            .map(|e| format!("{} {}", e.value(), e.key()));
+        // Name the package being processed, or how many when several run at once
+        // (installs are shown before resolutions)
+        let current_pkg: Option<String> = ["installing", "resolving"].iter().find_map(|status| {
+            let names: Vec<String> = self
+                .current_packages
+                .iter()
+                .filter(|e| e.value() == status)
+                .map(|e| e.key().clone())
+                .collect();
+            match names.as_slice() {
+                [] => None,
+                [name] => Some(format!("{} {}", status, name)),
+                _ => Some(format!("{} {} packages...", status, names.len())),
+            }
+        });
 
         let mut msg = format!(
             "{}Resolved{} {}{}{} {}│{}  {}Installed{} {}{}{}",
@@ -213,14 +1210,47 @@ impl Manager {
 
         if let Ok(guard) = self.progress_bar.try_lock() {
             if let Some(pb) = guard.as_ref() {
-                pb.set_message(msg);
+                // Turn the spinner into a bar once package sizes are known
+                let total = self.bytes_total.load(Ordering::Relaxed);
+                if total > 0 {
+                    if pb.length().is_none() {
+                        pb.set_style(
+                            ProgressStyle::default_bar()
+                                .template(
+                                    "{bar:20.cyan/blue} {percent:>3}% {binary_bytes_per_sec} {msg}",
+                                )
+                                .unwrap()
+                                .progress_chars(output::PROGRESS_CHARS),
+                        );
+                    }
+                    pb.set_length(total);
+                    pb.set_position(self.bytes_done.load(Ordering::Relaxed));
+                }
+                pb.set_message(output::maybe_strip_colors(self.color_mode, &msg));
             }
         }
     }
 
+    /// Grow or shrink the semaphore to the concurrency the registry latency allows
+    fn adjust_concurrency(&self) {
+        let target = self.registry.adaptive_concurrency();
+        let Ok(mut limit) = self.concurrency_limit.lock() else {
+            return;
+        };
+
+        if target < *limit {
+            // Only idle permits can be forgotten, the rest follow on later calls
+            *limit -= self.semaphore.forget_permits(*limit - target);
+        } else if target > *limit {
+            self.semaphore.add_permits(target - *limit);
+            *limit = target;
+        }
+    }
+
     /// Mark a package as currently being processed
     fn set_current_package(&self, name: &str, status: &str) {
         self.current_packages.insert(name.to_string(), status.to_string());
+        self.update_progress();
     }
 
     /// Remove a package from the current processing list
@@ -232,88 +1262,353 @@ impl Manager {
         self.packages_installed.store(0, Ordering::Relaxed);
         self.packages_resolved.store(0, Ordering::Relaxed);
         self.packages_cached.store(0, Ordering::Relaxed);
+        self.bytes_total.store(0, Ordering::Relaxed);
+        self.bytes_done.store(0, Ordering::Relaxed);
+    }
+
+    /// Load the `overrides` of the root package.json so they apply to the whole tree
+    fn load_overrides(&self, package_json: &PackageJson) {
+        self.overrides.clear();
+
+        let (overrides, from_resolutions) = package_json.effective_overrides();
+        if from_resolutions {
+            self.warn_once(
+                "the \"resolutions\" field is deprecated in rpm, use \"overrides\" instead",
+            );
+        }
+
+        for (name, version) in &overrides {
+            // "$name" references the version of a direct dependency (npm behaviour)
+            let version = match version.strip_prefix('$') {
+                Some(reference) => match package_json
+                    .dependencies
+                    .get(reference)
+                    .or(package_json.dev_dependencies.get(reference))
+                {
+                    Some(v) => v.clone(),
+                    None => continue,
+                },
+                None => version.clone(),
+            };
+            let key = match split_override_path(name) {
+                // node_modules is flat, only the direct parent of a package is known
+                (Some(parent), _) if split_override_path(parent).0.is_some() => {
+                    self.warn_once(&format!(
+                        "ignoring override \"{}\", only \"parent/package\" paths are supported",
+                        name
+                    ));
+                    continue;
+                }
+                (Some(parent), package) => format!("{}/{}", parent, package),
+                (None, package) => package.to_string(),
+            };
+            self.overrides.insert(key, version);
+        }
+    }
+
+    /// Record the loaded overrides in the lockfile, dropping the entries they changed.
+    /// Returns whether they differ from the ones of the previous install.
+    async fn record_overrides(&self) -> bool {
+        let overrides = self
+            .overrides
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        self.lockfile.lock().await.update_overrides(overrides)
+    }
+
+    /// Range to install for a dependency of `parent`: an override scoped to the parent
+    /// ("parent/name") wins over a global one, either replaces the declared range
+    fn override_range(&self, name: &str, parent: Option<&str>, range: String) -> String {
+        parent
+            .and_then(|parent| self.overrides.get(&format!("{}/{}", parent, name)))
+            .or_else(|| self.overrides.get(name))
+            .map_or(range, |forced| forced.value().clone())
+    }
+
+    /// Warn when a scoped override ("parent/name") disagrees with the installed copy of
+    /// `name`. node_modules is flat, so whichever dependent resolves `name` first decides
+    /// its version for all of them. Warns once per package.
+    fn warn_override_conflict(&self, name: &str, parent: Option<&str>, range: &str, version: &str) {
+        let scoped = self.overrides.iter().find_map(|entry| match split_override_path(entry.key()) {
+            (Some(_), package) if package == name => Some(entry.key().clone()),
+            _ => None,
+        });
+        let Some(key) = scoped else {
+            return;
+        };
+        if version_satisfies(range, version) != Some(false)
+            || !self.override_conflicts.insert(name.to_string())
+        {
+            return;
+        }
+        self.report_error(format!(
+            "\x1b[33mwarn:\x1b[0m {} needs {}@{} but {}@{} is installed, the override \"{}\" \
+             cannot apply to only some dependents of {} (use a global override instead)",
+            parent.unwrap_or("the project"),
+            name,
+            range,
+            name,
+            version,
+            key,
+            name
+        ));
     }
 
     async fn load_lockfile(&self) -> Result<()> {
-        if let Ok(content) = fs::read_to_string("rpm-lock.json").await {
-            let lock: LockFile = serde_json::from_str(&content).unwrap_or_else(|_| LockFile {
-                name: "".to_string(),
-                version: "".to_string(),
-                lockfile_version: 3,
-                packages: BTreeMap::new(),
-            });
+        if let Ok(content) = fs::read_to_string(self.project_dir()?.join("rpm-lock.json")).await {
+            let lock = LockFile::parse(&content).unwrap_or_else(|_| LockFile::empty());
+            // Dependency types omitted by a previous install stay omitted by default
+            self.install_optional
+                .store(!lock.omit.iter().any(|o| o == "optional"), Ordering::Relaxed);
             *self.lockfile.lock().await = lock;
         }
         Ok(())
     }
 
-    async fn save_lockfile(&self, package_name: &str, package_version: &str) -> Result<()> {
+    /// Apply install flags that override what the lockfile remembers
+    async fn apply_install_options(&self, options: &InstallOptions) {
         let mut lock = self.lockfile.lock().await;
-        lock.name = package_name.to_string();
+        if options.no_optional {
+            self.install_optional.store(false, Ordering::Relaxed);
+            if !lock.omit.iter().any(|o| o == "optional") {
+                lock.omit.push("optional".to_string());
+            }
+        } else if options.include_optional {
+            self.install_optional.store(true, Ordering::Relaxed);
+            lock.omit.retain(|o| o != "optional");
+        }
+        self.install_peers
+            .store(!options.legacy_peer_deps, Ordering::Relaxed);
+        self.exact_versions
+            .store(options.exact_versions, Ordering::Relaxed);
+        self.install_prod.store(
+            options.only != Some(DependencySubset::Development),
+            Ordering::Relaxed,
+        );
+        self.install_dev.store(
+            options.only != Some(DependencySubset::Production),
+            Ordering::Relaxed,
+        );
+        if options.prefer_symlinks {
+            output::warning(
+                self.color_mode,
+                "symlinked packages may behave differently from copied ones when scripts \
+                 modify their own files (--prefer-symlinks)",
+            );
+            self.installer.prefer_symlinks();
+        }
+    }
+
+    async fn save_lockfile(&self, package_name: &str, package_version: &str) -> Result<()> {
+        let mut lock = self.lockfile.lock().await;
+        lock.name = package_name.to_string();
         lock.version = package_version.to_string();
         let content = serde_json::to_string_pretty(&*lock)?;
-        fs::write("rpm-lock.json", content).await?;
+
+        // Write a temp file and rename it over the lockfile, so an interrupted install
+        // never leaves a half-written rpm-lock.json behind
+        let lock_path = self.project_dir()?.join("rpm-lock.json");
+        let tmp_path = lock_path.with_extension("json.tmp");
+        let written = match fs::write(&tmp_path, content).await {
+            Ok(()) => fs::rename(&tmp_path, &lock_path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            let _ = fs::remove_file(&tmp_path).await;
+            return Err(anyhow::Error::new(e).context("Failed to write rpm-lock.json"));
+        }
         Ok(())
     }
 
-    pub async fn list_packages(&self) -> Result<()> {
-        let package_json_content = fs::read_to_string("package.json")
+    /// Save the packages an interrupted install completed, the next install resumes
+    /// from them
+    async fn save_partial_lockfile(&self) -> Result<()> {
+        self.lockfile.lock().await.retain_complete();
+        let content = fs::read_to_string(self.project_dir()?.join("package.json")).await?;
+        let package_json: PackageJson = serde_json::from_str(&content)?;
+        self.save_lockfile(&package_json.name, &package_json.version)
+            .await
+    }
+
+    pub async fn list_packages(
+        &self,
+        parseable: bool,
+        include_transitive: bool,
+        depth: Option<usize>,
+    ) -> Result<()> {
+        if parseable {
+            return self.list_packages_parseable().await;
+        }
+
+        let package_json_content = fs::read_to_string(self.project_dir()?.join("package.json"))
             .await
             .context("Could not find package.json in current directory")?;
         let package_json: PackageJson = serde_json::from_str(&package_json_content)?;
 
-        println!(
-            "\x1b[1m{}@{}\x1b[0m",
-            package_json.name, package_json.version
+        output::print_line(
+            self.color_mode,
+            &format!(
+                "\x1b[1m{}@{}\x1b[0m",
+                package_json.name, package_json.version
+            ),
         );
 
         let has_deps = !package_json.dependencies.is_empty();
         let has_dev_deps = !package_json.dev_dependencies.is_empty();
 
         if !has_deps && !has_dev_deps {
-            println!("\x1b[90m(no dependencies)\x1b[0m");
+            output::print_line(self.color_mode, "\x1b[90m(no dependencies)\x1b[0m");
             return Ok(());
         }
 
+        let tree = if include_transitive {
+            self.installed_tree().await?
+        } else {
+            InstalledTree::new()
+        };
+        let mut expanded = HashSet::new();
+        let print_children = |name: &str, expanded: &mut HashSet<String>| {
+            let mut lines = Vec::new();
+            let mut ancestors = vec![name.to_string()];
+            expanded.insert(name.to_string());
+            push_dependency_lines(name, &tree, 1, depth, &mut ancestors, expanded, &mut lines);
+            for line in lines {
+                output::print_line(self.color_mode, &line);
+            }
+        };
+
         if has_deps {
-            println!("\n\x1b[1;36mDependencies:\x1b[0m");
+            output::print_line(self.color_mode, "\n\x1b[1;36mDependencies:\x1b[0m");
             for (name, version) in &package_json.dependencies {
                 let installed = self.get_installed_version(name).await;
                 match installed {
-                    Some(v) => println!(
-                        "  \x1b[32m├─\x1b[0m {}@\x1b[90m{}\x1b[0m (installed: \x1b[36m{}\x1b[0m)",
-                        name, version, v
+                    Some(v) => output::print_line(
+                        self.color_mode,
+                        &format!(
+                            "  \x1b[32m├─\x1b[0m {}@\x1b[90m{}\x1b[0m (installed: \x1b[36m{}\x1b[0m)",
+                            name, version, v
+                        ),
                     ),
-                    None => println!(
-                        "  \x1b[33m├─\x1b[0m {}@\x1b[90m{}\x1b[0m \x1b[33m(not installed)\x1b[0m",
-                        name, version
+                    None => output::print_line(
+                        self.color_mode,
+                        &format!(
+                            "  \x1b[33m├─\x1b[0m {}@\x1b[90m{}\x1b[0m \x1b[33m(not installed)\x1b[0m",
+                            name, version
+                        ),
                     ),
                 }
+                if include_transitive {
+                    print_children(name, &mut expanded);
+                }
             }
         }
 
         if has_dev_deps {
-            println!("\n\x1b[1;35mDev Dependencies:\x1b[0m");
+            output::print_line(self.color_mode, "\n\x1b[1;35mDev Dependencies:\x1b[0m");
             for (name, version) in &package_json.dev_dependencies {
                 let installed = self.get_installed_version(name).await;
                 match installed {
-                    Some(v) => println!(
-                        "  \x1b[32m├─\x1b[0m {}@\x1b[90m{}\x1b[0m (installed: \x1b[36m{}\x1b[0m)",
-                        name, version, v
+                    Some(v) => output::print_line(
+                        self.color_mode,
+                        &format!(
+                            "  \x1b[32m├─\x1b[0m {}@\x1b[90m{}\x1b[0m (installed: \x1b[36m{}\x1b[0m)",
+                            name, version, v
+                        ),
                     ),
-                    None => println!(
-                        "  \x1b[33m├─\x1b[0m {}@\x1b[90m{}\x1b[0m \x1b[33m(not installed)\x1b[0m",
-                        name, version
+                    None => output::print_line(
+                        self.color_mode,
+                        &format!(
+                            "  \x1b[33m├─\x1b[0m {}@\x1b[90m{}\x1b[0m \x1b[33m(not installed)\x1b[0m",
+                            name, version
+                        ),
                     ),
                 }
+                if include_transitive {
+                    print_children(name, &mut expanded);
+                }
+            }
+        }
+
+        if include_transitive {
+            let direct: Vec<String> = package_json
+                .dependencies
+                .keys()
+                .chain(package_json.dev_dependencies.keys())
+                .cloned()
+                .collect();
+            let mut required: HashSet<String> = HashSet::new();
+            let mut pending = direct;
+            while let Some(name) = pending.pop() {
+                if let Some((_, deps)) = tree.get(&name) {
+                    if required.insert(name) {
+                        pending.extend(deps.iter().cloned());
+                    }
+                }
+            }
+            let extraneous: Vec<_> = tree
+                .iter()
+                .filter(|(name, _)| !required.contains(*name))
+                .collect();
+            if !extraneous.is_empty() {
+                output::print_line(
+                    self.color_mode,
+                    "\n\x1b[1;33mNot required by package.json:\x1b[0m",
+                );
+                for (name, (version, _)) in extraneous {
+                    output::print_line(
+                        self.color_mode,
+                        &format!("  \x1b[33m├─\x1b[0m {}@\x1b[90m{}\x1b[0m", name, version),
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Name, version and dependencies of every package in node_modules
+    async fn installed_tree(&self) -> Result<InstalledTree> {
+        let node_modules = self.project_dir()?.join("node_modules");
+        let mut tree = InstalledTree::new();
+        for path in scan_node_modules(&node_modules).await {
+            let Ok(content) = fs::read_to_string(path.join("package.json")).await else {
+                continue;
+            };
+            let Ok(pkg) = serde_json::from_str::<PackageJson>(&content) else {
+                continue;
+            };
+            let deps = pkg
+                .dependencies
+                .keys()
+                .chain(pkg.optional_dependencies.keys())
+                .chain(pkg.peer_dependencies.keys())
+                .cloned()
+                .collect();
+            tree.insert(pkg.name, (pkg.version, deps));
+        }
+        Ok(tree)
+    }
+
+    /// Print every package in node_modules as "<path> <name>@<version>" (no colors, no tree)
+    async fn list_packages_parseable(&self) -> Result<()> {
+        let node_modules = self.project_dir()?.join("node_modules");
+
+        for path in scan_node_modules(&node_modules).await {
+            let content = match fs::read_to_string(path.join("package.json")).await {
+                Ok(c) => c,
+                Err(_) => continue,
+            };
+            if let Ok(pkg) = serde_json::from_str::<PackageJson>(&content) {
+                println!("{} {}@{}", path.display(), pkg.name, pkg.version);
             }
         }
 
         Ok(())
     }
 
-    pub async fn outdated_packages(&self) -> Result<()> {
-        let package_json_content = fs::read_to_string("package.json")
+    pub async fn outdated_packages(&self, depth: i32) -> Result<()> {
+        let package_json_content = fs::read_to_string(self.project_dir()?.join("package.json"))
             .await
             .context("Could not find package.json in current directory")?;
         let package_json: PackageJson = serde_json::from_str(&package_json_content)?;
@@ -322,63 +1617,93 @@ impl Manager {
         let has_dev_deps = !package_json.dev_dependencies.is_empty();
 
         if !has_deps && !has_dev_deps {
-            println!("\x1b[90m(no dependencies)\x1b[0m");
+            output::print_line(self.color_mode, "\x1b[90m(no dependencies)\x1b[0m");
             return Ok(());
         }
 
         let spinner = self.create_spinner();
-        spinner.set_message("\x1b[1mChecking\x1b[0m for updates...");
+        spinner.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            "\x1b[1mChecking\x1b[0m for updates...",
+        ));
 
-        let mut outdated: Vec<(String, String, String, String, bool)> = Vec::new(); // (name, current, wanted, latest, is_dev)
+        let mut outdated: Vec<(OutdatedCandidate, String, String, String)> = Vec::new(); // (dep, current, wanted, latest)
 
-        // Collect all deps to check
-        let deps_to_check: Vec<(String, String, bool)> = package_json
-            .dependencies
-            .iter()
-            .map(|(n, v)| (n.clone(), v.clone(), false))
-            .chain(
-                package_json
-                    .dev_dependencies
-                    .iter()
-                    .map(|(n, v)| (n.clone(), v.clone(), true)),
-            )
-            .collect();
+        // Collect all deps to check (transitive ones too when --depth is given)
+        let deps_to_check = self
+            .collect_outdated_candidates(&package_json, depth)
+            .await;
 
         // Check all dependencies in parallel
         let mut tasks = FuturesUnordered::new();
-        for (name, version_range, is_dev) in deps_to_check {
+        for dep in deps_to_check {
             let manager = self.clone();
             tasks.push(async move {
-                let result = manager.check_outdated(&name, &version_range).await;
-                (name, result, is_dev)
+                let result = manager.check_outdated(&dep.name, &dep.range).await;
+                (dep, result)
             });
         }
 
-        while let Some((name, result, is_dev)) = tasks.next().await {
+        while let Some((dep, result)) = tasks.next().await {
             if let Some((current, wanted, latest)) = result {
-                outdated.push((name, current, wanted, latest, is_dev));
+                outdated.push((dep, current, wanted, latest));
             }
         }
 
         spinner.finish_and_clear();
 
         if outdated.is_empty() {
-            println!("\x1b[32m✓\x1b[0m All packages are up to date!");
+            output::print_line(
+                self.color_mode,
+                "\x1b[32m✓\x1b[0m All packages are up to date!",
+            );
             return Ok(());
         }
 
+        outdated.sort_by(|(a, ..), (b, ..)| a.depth.cmp(&b.depth).then(a.name.cmp(&b.name)));
+        let show_depth = depth != 0;
+
         // Print header
-        println!(
-            "\x1b[1m{:<30} {:>12} {:>12} {:>12}  {}\x1b[0m",
-            "Package", "Current", "Wanted", "Latest", "Type"
-        );
-        println!("{}", "─".repeat(78));
+        if show_depth {
+            output::print_line(
+                self.color_mode,
+                &format!(
+                    "\x1b[1m{:<30} {:>12} {:>12} {:>12}  {:<4}  {:>5}  Parent\x1b[0m",
+                    "Package", "Current", "Wanted", "Latest", "Type", "Depth"
+                ),
+            );
+            println!("{}", "─".repeat(100));
+        } else {
+            output::print_line(
+                self.color_mode,
+                &format!(
+                    "\x1b[1m{:<30} {:>12} {:>12} {:>12}  {}\x1b[0m",
+                    "Package", "Current", "Wanted", "Latest", "Type"
+                ),
+            );
+            println!("{}", "─".repeat(78));
+        }
+
+        let mut previous_depth = None;
+        for (dep, current, wanted, latest) in &outdated {
+            // Rows are sorted by depth, start a group whenever it changes
+            if show_depth && previous_depth != Some(dep.depth) {
+                if previous_depth.is_some() {
+                    println!();
+                }
+                let label = if dep.depth == 0 {
+                    "direct dependencies".to_string()
+                } else {
+                    format!("depth {}", dep.depth)
+                };
+                output::print_line(self.color_mode, &format!("\x1b[90m{}\x1b[0m", label));
+                previous_depth = Some(dep.depth);
+            }
 
-        for (name, current, wanted, latest, is_dev) in &outdated {
-            let type_label = if *is_dev {
-                "\x1b[35mdev\x1b[0m"
+            let type_label = if dep.is_dev {
+                "\x1b[35mdev\x1b[0m "
             } else {
-                "\x1b[36mdep\x1b[0m"
+                "\x1b[36mdep\x1b[0m "
             };
 
             let wanted_color = if wanted != current {
@@ -392,25 +1717,103 @@ impl Manager {
                 "\x1b[90m"
             };
 
-            println!(
+            let mut line = format!(
                 "{:<30} \x1b[90m{:>12}\x1b[0m {:>12} {:>12}  {}",
-                name,
+                dep.name,
                 current,
                 format!("{}{}\x1b[0m", wanted_color, wanted),
                 format!("{}{}\x1b[0m", latest_color, latest),
                 type_label
             );
+            if show_depth {
+                line.push_str(&format!(
+                    " {:>5}  \x1b[90m{}\x1b[0m",
+                    dep.depth,
+                    dep.parent.as_deref().unwrap_or("-")
+                ));
+            }
+            output::print_line(self.color_mode, line.trim_end());
         }
 
         println!();
-        println!(
-            "\x1b[90m{} package(s) can be updated\x1b[0m",
-            outdated.len()
+        output::print_line(
+            self.color_mode,
+            &format!(
+                "\x1b[90m{} package(s) can be updated\x1b[0m",
+                outdated.len()
+            ),
         );
 
         Ok(())
     }
 
+    /// Collect the dependencies to check for `rpm outdated`
+    /// Follows the dependencies of installed packages up to `max_depth` levels below
+    /// package.json (a negative depth means unlimited)
+    async fn collect_outdated_candidates(
+        &self,
+        package_json: &PackageJson,
+        max_depth: i32,
+    ) -> Vec<OutdatedCandidate> {
+        let mut candidates: Vec<OutdatedCandidate> = package_json
+            .dependencies
+            .iter()
+            .map(|(n, v)| (n, v, false))
+            .chain(package_json.dev_dependencies.iter().map(|(n, v)| (n, v, true)))
+            .map(|(name, range, is_dev)| OutdatedCandidate {
+                name: name.clone(),
+                range: range.clone(),
+                is_dev,
+                depth: 0,
+                parent: None,
+            })
+            .collect();
+
+        let Ok(node_modules) = self.project_dir().map(|dir| dir.join("node_modules")) else {
+            return candidates;
+        };
+        let mut seen: std::collections::HashSet<String> =
+            candidates.iter().map(|c| c.name.clone()).collect();
+        let mut level_start = 0;
+        let mut depth = 0;
+
+        while max_depth < 0 || depth < max_depth {
+            let level_end = candidates.len();
+            if level_start == level_end {
+                break;
+            }
+
+            for i in level_start..level_end {
+                let parent = candidates[i].name.clone();
+                let is_dev = candidates[i].is_dev;
+                let pkg_json_path = node_modules.join(&parent).join("package.json");
+                let Ok(content) = fs::read_to_string(&pkg_json_path).await else {
+                    continue;
+                };
+                let Ok(pkg) = serde_json::from_str::<PackageJson>(&content) else {
+                    continue;
+                };
+
+                for (name, range) in pkg.dependencies {
+                    if seen.insert(name.clone()) {
+                        candidates.push(OutdatedCandidate {
+                            name,
+                            range,
+                            is_dev,
+                            depth: depth as usize + 1,
+                            parent: Some(parent.clone()),
+                        });
+                    }
+                }
+            }
+
+            level_start = level_end;
+            depth += 1;
+        }
+
+        candidates
+    }
+
     async fn check_outdated(
         &self,
         name: &str,
@@ -439,15 +1842,49 @@ impl Manager {
         }
     }
 
-    pub async fn update_packages(&self, packages: Vec<String>) -> Result<()> {
+    /// Let the user pick which outdated packages to upgrade
+    async fn select_updates(
+        mut to_update: Vec<(String, String, String, bool)>,
+    ) -> Result<Vec<(String, String, String, bool)>> {
+        to_update.sort_by(|a, b| a.0.cmp(&b.0));
+        let items: Vec<String> = to_update
+            .iter()
+            .map(|(name, old_version, new_version, is_dev)| {
+                let dev = if *is_dev { " (dev)" } else { "" };
+                format!("{} {} → {}{}", name, old_version, new_version, dev)
+            })
+            .collect();
+
+        let selection = tokio::task::spawn_blocking(move || {
+            dialoguer::MultiSelect::new()
+                .with_prompt("Select packages to update (space to toggle, enter to confirm)")
+                .items(&items)
+                .interact_opt()
+        })
+        .await??;
+
+        // Esc / q cancels the update entirely
+        let selected = selection.unwrap_or_default();
+        Ok(to_update
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| selected.contains(i))
+            .map(|(_, update)| update)
+            .collect())
+    }
+
+    pub async fn update_packages(&self, packages: Vec<String>, interactive: bool) -> Result<()> {
         self.load_lockfile().await?;
-        let package_json_content = fs::read_to_string("package.json")
+        let package_json_content = fs::read_to_string(self.project_dir()?.join("package.json"))
             .await
             .context("Could not find package.json in current directory")?;
         let mut package_json: PackageJson = serde_json::from_str(&package_json_content)?;
 
         let spinner = self.create_spinner();
-        spinner.set_message("\x1b[1mChecking\x1b[0m for updates...");
+        spinner.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            "\x1b[1mChecking\x1b[0m for updates...",
+        ));
 
         let mut to_update: Vec<(String, String, String, bool)> = Vec::new(); // (name, old_version, new_version, is_dev)
 
@@ -490,16 +1927,24 @@ impl Manager {
         spinner.finish_and_clear();
 
         if to_update.is_empty() {
-            println!("\x1b[32m✓\x1b[0m All packages are up to date!");
+            self.report("\x1b[32m✓\x1b[0m All packages are up to date!");
             return Ok(());
         }
 
+        if interactive {
+            to_update = Self::select_updates(to_update).await?;
+            if to_update.is_empty() {
+                self.report("\x1b[90mNo packages selected\x1b[0m");
+                return Ok(());
+            }
+        }
+
         // Update package.json with new versions
         for (name, old_version, new_version, is_dev) in &to_update {
-            println!(
+            self.report(&format!(
                 "\x1b[36m↑\x1b[0m \x1b[1m{}\x1b[0m \x1b[90m{}\x1b[0m → \x1b[32m{}\x1b[0m",
                 name, old_version, new_version
-            );
+            ));
 
             if *is_dev {
                 package_json
@@ -519,7 +1964,7 @@ impl Manager {
             }
 
             // Remove from node_modules
-            let pkg_path = PathBuf::from("node_modules").join(name);
+            let pkg_path = self.project_dir()?.join("node_modules").join(name);
             if pkg_path.exists() {
                 let _ = fs::remove_dir_all(&pkg_path).await;
             }
@@ -527,14 +1972,17 @@ impl Manager {
 
         // Save updated package.json
         let new_content = serde_json::to_string_pretty(&package_json)?;
-        fs::write("package.json", new_content).await?;
+        fs::write(self.project_dir()?.join("package.json"), new_content).await?;
 
-        println!();
+        self.report("");
 
         // Reset and setup progress tracking
         self.reset_progress();
         let pb = self.create_install_progress();
-        pb.set_message("\x1b[1mInstalling\x1b[0m updates...");
+        pb.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            "\x1b[1mInstalling\x1b[0m updates...",
+        ));
         *self.progress_bar.lock().await = Some(pb.clone());
 
         self.install_deps(&package_json).await?;
@@ -544,6 +1992,7 @@ impl Manager {
 
         pb.finish_and_clear();
         *self.progress_bar.lock().await = None;
+        self.finish_dots();
 
         // Print summary
         if installed > 0 || cached > 0 {
@@ -554,33 +2003,82 @@ impl Manager {
             if cached > 0 {
                 parts.push(format!("\x1b[33m{}\x1b[0m cached", cached));
             }
-            println!("{}", parts.join("  \x1b[90m│\x1b[0m  "));
+            self.report(&parts.join("  \x1b[90m│\x1b[0m  "));
         }
 
         self.run_postinstalls().await?;
         self.save_lockfile(&package_json.name, &package_json.version)
             .await?;
 
-        println!("\n\x1b[32m✓\x1b[0m Updated {} package(s)", to_update.len());
+        self.report(&format!(
+            "\n\x1b[32m✓\x1b[0m Updated {} package(s)",
+            to_update.len()
+        ));
 
         Ok(())
     }
 
     pub async fn dedupe_packages(&self) -> Result<()> {
-        let package_json_content = fs::read_to_string("package.json")
+        let package_json_content = fs::read_to_string(self.project_dir()?.join("package.json"))
             .await
             .context("Could not find package.json in current directory")?;
         let package_json: PackageJson = serde_json::from_str(&package_json_content)?;
 
-        let node_modules = std::env::current_dir()?.join("node_modules");
+        let node_modules = self.project_dir()?.join("node_modules");
         if !node_modules.exists() {
-            println!("\x1b[33m!\x1b[0m No node_modules found. Run 'rpm install' first.");
+            output::print_line(
+                self.color_mode,
+                "\x1b[33m!\x1b[0m No node_modules found. Run 'rpm install' first.",
+            );
             return Ok(());
         }
 
         let spinner = self.create_spinner();
-        spinner.set_message("\x1b[1mAnalyzing\x1b[0m dependencies...");
+        spinner.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            "\x1b[1mAnalyzing\x1b[0m dependencies...",
+        ));
+
+        let (duplicates_found, bytes_saved) = self.remove_nested_duplicates(&node_modules).await;
+
+        spinner.finish_and_clear();
+
+        if duplicates_found == 0 {
+            output::print_line(
+                self.color_mode,
+                "\x1b[32m✓\x1b[0m No duplicates found. Dependencies are already optimized.",
+            );
+        } else {
+            output::print_line(self.color_mode, &format!(
+                "\x1b[32m✓\x1b[0m Removed \x1b[1m{}\x1b[0m duplicate(s), saved \x1b[36m{:.2} MB\x1b[0m",
+                duplicates_found,
+                bytes_saved as f64 / 1024.0 / 1024.0
+            ));
+        }
+
+        // Rebuild lockfile
+        self.save_lockfile(&package_json.name, &package_json.version)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Inline dedupe of `rpm install --prefer-dedupe`
+    async fn dedupe_after_install(&self, node_modules: &PathBuf) {
+        let (duplicates_found, bytes_saved) = self.remove_nested_duplicates(node_modules).await;
+        if duplicates_found > 0 {
+            self.report(&format!(
+                "\x1b[32m✓\x1b[0m Deduped \x1b[1m{}\x1b[0m nested package(s), \
+                 saved \x1b[36m{:.2} MB\x1b[0m",
+                duplicates_found,
+                bytes_saved as f64 / 1024.0 / 1024.0
+            ));
+        }
+    }
 
+    /// Remove nested copies of packages whose top-level copy satisfies the parent,
+    /// returns how many were removed and the bytes saved
+    async fn remove_nested_duplicates(&self, node_modules: &PathBuf) -> (usize, u64) {
         let mut duplicates_found = 0;
         let mut bytes_saved: u64 = 0;
 
@@ -607,6 +2105,11 @@ impl Manager {
                     continue;
                 }
 
+                // Nested node_modules of a store link belong to the shared store entry
+                if self.installer.is_store_link(&path) {
+                    continue;
+                }
+
                 // Check for nested node_modules
                 let nested_nm = path.join("node_modules");
                 if nested_nm.exists() {
@@ -637,7 +2140,7 @@ impl Manager {
 
                                         if let Some(saved) = self
                                             .try_dedupe_package(
-                                                &node_modules,
+                                                node_modules,
                                                 &scoped_path,
                                                 &scoped_pkg_name,
                                             )
@@ -653,7 +2156,7 @@ impl Manager {
 
                             // Check if this package exists at the top level with compatible version
                             if let Some(saved) = self
-                                .try_dedupe_package(&node_modules, &nested_path, &nested_name)
+                                .try_dedupe_package(node_modules, &nested_path, &nested_name)
                                 .await
                             {
                                 duplicates_found += 1;
@@ -666,23 +2169,7 @@ impl Manager {
             }
         }
 
-        spinner.finish_and_clear();
-
-        if duplicates_found == 0 {
-            println!("\x1b[32m✓\x1b[0m No duplicates found. Dependencies are already optimized.");
-        } else {
-            println!(
-                "\x1b[32m✓\x1b[0m Removed \x1b[1m{}\x1b[0m duplicate(s), saved \x1b[36m{:.2} MB\x1b[0m",
-                duplicates_found,
-                bytes_saved as f64 / 1024.0 / 1024.0
-            );
-        }
-
-        // Rebuild lockfile
-        self.save_lockfile(&package_json.name, &package_json.version)
-            .await?;
-
-        Ok(())
+        (duplicates_found, bytes_saved)
     }
 
     async fn try_dedupe_package(
@@ -708,16 +2195,44 @@ impl Manager {
         let root_pkg: PackageJson = serde_json::from_str(&root_content).ok()?;
         let root_version = &root_pkg.version;
 
-        // If versions match, we can dedupe
-        if nested_version == root_version {
+        // The range the parent package declared for the nested copy
+        // (<parent>/node_modules/<name>, or <parent>/node_modules/@scope/name)
+        let parent_dir = nested_path
+            .ancestors()
+            .find(|p| p.file_name().is_some_and(|n| n == "node_modules"))?
+            .parent()?;
+        let declared_range = match fs::read_to_string(parent_dir.join("package.json")).await {
+            Ok(content) => serde_json::from_str::<PackageJson>(&content)
+                .ok()
+                .and_then(|parent| {
+                    parent
+                        .dependencies
+                        .get(pkg_name)
+                        .or(parent.optional_dependencies.get(pkg_name))
+                        .or(parent.peer_dependencies.get(pkg_name))
+                        .cloned()
+                }),
+            Err(_) => None,
+        };
+
+        // If the root copy is acceptable to the parent, we can dedupe
+        if can_dedupe(root_version, nested_version, declared_range.as_deref()) {
             // Calculate size before removing
             let size = fs_extra::dir::get_size(nested_path).unwrap_or(0);
 
-            // Remove the nested duplicate
+            // Remove the nested duplicate, and its lockfile entry
             if fs::remove_dir_all(nested_path).await.is_ok() {
-                let _ = self.multi_progress.println(format!(
-                    "\x1b[33m-\x1b[0m \x1b[1m{}\x1b[0m@{} (duplicate)",
-                    pkg_name, nested_version
+                let project = root_nm.parent().unwrap_or(root_nm);
+                if let Ok(relative) = nested_path.strip_prefix(project) {
+                    let key = relative.to_string_lossy().replace('\\', "/");
+                    self.lockfile.lock().await.packages.remove(&key);
+                }
+                let _ = self.multi_progress.println(output::maybe_strip_colors(
+                    self.color_mode,
+                    &format!(
+                        "\x1b[33m-\x1b[0m \x1b[1m{}\x1b[0m@{} (duplicate)",
+                        pkg_name, nested_version
+                    ),
                 ));
                 return Some(size);
             }
@@ -734,7 +2249,8 @@ impl Manager {
     }
 
     async fn get_installed_version(&self, name: &str) -> Option<String> {
-        let pkg_json_path = std::env::current_dir()
+        let pkg_json_path = self
+            .project_dir()
             .ok()?
             .join("node_modules")
             .join(name)
@@ -748,8 +2264,185 @@ impl Manager {
         None
     }
 
+    /// List the licenses of installed packages, or check them against an allow list
+    pub async fn licenses(&self, check: Option<&str>) -> Result<()> {
+        let node_modules = self.project_dir()?.join("node_modules");
+        if !node_modules.exists() {
+            output::print_line(self.color_mode, "\x1b[90mNo packages installed\x1b[0m");
+            return Ok(());
+        }
+
+        // (name@version, license)
+        let mut packages: Vec<(String, Option<String>)> = Vec::new();
+        for path in scan_node_modules(&node_modules).await {
+            // Local workspace packages are not third-party code
+            if path.is_symlink() && !self.installer.is_store_link(&path) {
+                continue;
+            }
+            let Ok(content) = fs::read_to_string(path.join("package.json")).await else {
+                continue;
+            };
+            let Ok(pkg) = serde_json::from_str::<serde_json::Value>(&content) else {
+                continue;
+            };
+            let name = pkg["name"].as_str().unwrap_or_default();
+            let version = pkg["version"].as_str().unwrap_or_default();
+            packages.push((format!("{}@{}", name, version), license::package_license(&pkg)));
+        }
+
+        let Some(check) = check else {
+            let mut by_license: BTreeMap<String, usize> = BTreeMap::new();
+            for (_, license) in &packages {
+                let license = license.clone().unwrap_or_else(|| "UNKNOWN".to_string());
+                *by_license.entry(license).or_default() += 1;
+            }
+            let mut by_license: Vec<_> = by_license.into_iter().collect();
+            by_license.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+            for (license, count) in by_license {
+                output::print_line(
+                    self.color_mode,
+                    &format!("\x1b[1m{:<30}\x1b[0m \x1b[90m{}\x1b[0m", license, count),
+                );
+            }
+            return Ok(());
+        };
+
+        let allowed: Vec<String> = check
+            .split(',')
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect();
+
+        let violations: Vec<_> = packages
+            .iter()
+            .filter(|(_, license)| match license {
+                Some(license) => !license::license_allowed(license, &allowed),
+                None => true,
+            })
+            .collect();
+
+        if violations.is_empty() {
+            output::print_line(
+                self.color_mode,
+                &format!(
+                    "\x1b[32m✓\x1b[0m All {} packages use allowed licenses",
+                    packages.len()
+                ),
+            );
+            return Ok(());
+        }
+
+        for (package, license) in &violations {
+            output::print_line(
+                self.color_mode,
+                &format!(
+                    "  \x1b[31m✗\x1b[0m \x1b[1m{}\x1b[0m \x1b[90m{}\x1b[0m",
+                    package,
+                    license.as_deref().unwrap_or("UNKNOWN")
+                ),
+            );
+        }
+        Err(RpmError::Other {
+            message: format!("{} package(s) use a disallowed license", violations.len()),
+            hint: Some(format!("Allowed: {}", allowed.join(", "))),
+        }
+        .into())
+    }
+
+    /// Show the dist-tags of a package, or every published version with its release
+    /// date (newest first) with `versions`
+    pub async fn info(&self, name: &str, versions: bool, json: bool) -> Result<()> {
+        let package = self.registry.get_package(name).await?;
+        let mut tags: Vec<(&String, &String)> = package.dist_tags.iter().collect();
+        tags.sort();
+
+        if !versions {
+            output::print_line(self.color_mode, &format!("\x1b[1m{}\x1b[0m", name));
+            for (tag, version) in &tags {
+                output::print_line(
+                    self.color_mode,
+                    &format!("  \x1b[36m{}\x1b[0m: {}", tag, version),
+                );
+            }
+            output::print_line(
+                self.color_mode,
+                &format!("\x1b[90m{} versions\x1b[0m", package.versions.len()),
+            );
+            return Ok(());
+        }
+
+        let mut list: Vec<(&String, Option<semver::Version>)> = package
+            .versions
+            .keys()
+            .map(|v| (v, semver::Version::parse(v).ok()))
+            .collect();
+        // Newest first, versions that are not valid semver last
+        list.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        if json {
+            let entries: Vec<serde_json::Value> = list
+                .iter()
+                .map(|(version, _)| {
+                    serde_json::json!({
+                        "version": version,
+                        "time": package.time.get(*version),
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+            return Ok(());
+        }
+
+        let lines: Vec<String> = list
+            .iter()
+            .map(|(version, _)| {
+                let date = package
+                    .time
+                    .get(*version)
+                    .and_then(|t| t.get(..10))
+                    .unwrap_or("unknown");
+                let tagged: Vec<&str> = tags
+                    .iter()
+                    .filter(|(_, v)| v == version)
+                    .map(|(tag, _)| tag.as_str())
+                    .collect();
+                let mut line = format!("{:<24} \x1b[90m{}\x1b[0m", version, date);
+                if !tagged.is_empty() {
+                    line.push_str(&format!(" \x1b[36m{}\x1b[0m", tagged.join(", ")));
+                }
+                line
+            })
+            .collect();
+
+        // Page through long lists when both ends are a terminal
+        let paginate = std::io::stdout().is_terminal() && std::io::stdin().is_terminal();
+        let mut stdin = BufReader::new(tokio::io::stdin());
+        for (i, page) in lines.chunks(VERSIONS_PAGE_SIZE).enumerate() {
+            if i > 0 && paginate {
+                output::print_line(
+                    self.color_mode,
+                    &format!(
+                        "\x1b[90m-- {}/{} shown, Enter for more, q to quit --\x1b[0m",
+                        i * VERSIONS_PAGE_SIZE,
+                        lines.len()
+                    ),
+                );
+                let mut line = String::new();
+                stdin.read_line(&mut line).await?;
+                if line.trim().eq_ignore_ascii_case("q") {
+                    break;
+                }
+            }
+            for line in page {
+                output::print_line(self.color_mode, line);
+            }
+        }
+        Ok(())
+    }
+
     pub async fn why_package(&self, name: &str) -> Result<()> {
-        let package_json_content = fs::read_to_string("package.json")
+        let package_json_content = fs::read_to_string(self.project_dir()?.join("package.json"))
             .await
             .context("Could not find package.json in current directory")?;
         let package_json: PackageJson = serde_json::from_str(&package_json_content)?;
@@ -759,10 +2452,16 @@ impl Manager {
 
         // Check if it's a direct dependency
         if let Some(version) = package_json.dependencies.get(name) {
-            println!("\x1b[1m{}\x1b[0m@\x1b[90m{}\x1b[0m", name, version);
-            println!(
-                "  \x1b[32m├─\x1b[0m Direct dependency in \x1b[1m{}\x1b[0m",
-                package_json.name
+            output::print_line(
+                self.color_mode,
+                &format!("\x1b[1m{}\x1b[0m@\x1b[90m{}\x1b[0m", name, version),
+            );
+            output::print_line(
+                self.color_mode,
+                &format!(
+                    "  \x1b[32m├─\x1b[0m Direct dependency in \x1b[1m{}\x1b[0m",
+                    package_json.name
+                ),
             );
             found = true;
         }
@@ -770,17 +2469,23 @@ impl Manager {
         // Check if it's a direct dev dependency
         if let Some(version) = package_json.dev_dependencies.get(name) {
             if !found {
-                println!("\x1b[1m{}\x1b[0m@\x1b[90m{}\x1b[0m", name, version);
+                output::print_line(
+                    self.color_mode,
+                    &format!("\x1b[1m{}\x1b[0m@\x1b[90m{}\x1b[0m", name, version),
+                );
             }
-            println!(
-                "  \x1b[35m├─\x1b[0m Dev dependency in \x1b[1m{}\x1b[0m",
-                package_json.name
+            output::print_line(
+                self.color_mode,
+                &format!(
+                    "  \x1b[35m├─\x1b[0m Dev dependency in \x1b[1m{}\x1b[0m",
+                    package_json.name
+                ),
             );
             found = true;
         }
 
         // Check transitive dependencies by scanning node_modules
-        let node_modules = std::env::current_dir()?.join("node_modules");
+        let node_modules = self.project_dir()?.join("node_modules");
         if node_modules.exists() {
             if let Ok(mut entries) = tokio::fs::read_dir(&node_modules).await {
                 while let Ok(Some(entry)) = entries.next_entry().await {
@@ -832,26 +2537,35 @@ impl Manager {
                     .get_installed_version(name)
                     .await
                     .unwrap_or_else(|| "?".to_string());
-                println!(
-                    "\x1b[1m{}\x1b[0m@\x1b[90m{}\x1b[0m",
-                    name, installed_version
+                output::print_line(
+                    self.color_mode,
+                    &format!(
+                        "\x1b[1m{}\x1b[0m@\x1b[90m{}\x1b[0m",
+                        name, installed_version
+                    ),
                 );
             }
-            println!("\n\x1b[1;36mRequired by:\x1b[0m");
+            output::print_line(self.color_mode, "\n\x1b[1;36mRequired by:\x1b[0m");
             for (dep_name, version_req, is_dev) in &dependents {
                 let marker = if *is_dev { "\x1b[35m" } else { "\x1b[32m" };
-                println!(
-                    "  {}├─\x1b[0m \x1b[1m{}\x1b[0m requires \x1b[90m{}\x1b[0m",
-                    marker, dep_name, version_req
+                output::print_line(
+                    self.color_mode,
+                    &format!(
+                        "  {}├─\x1b[0m \x1b[1m{}\x1b[0m requires \x1b[90m{}\x1b[0m",
+                        marker, dep_name, version_req
+                    ),
                 );
             }
             found = true;
         }
 
         if !found {
-            println!(
-                "\x1b[33mPackage '{}' is not installed or not a dependency\x1b[0m",
-                name
+            output::print_line(
+                self.color_mode,
+                &format!(
+                    "\x1b[33mPackage '{}' is not installed or not a dependency\x1b[0m",
+                    name
+                ),
             );
         }
 
@@ -880,100 +2594,1467 @@ impl Manager {
             crate::CacheCommands::Clean => {
                 if self.installer.cache_dir.exists() {
                     fs::remove_dir_all(&self.installer.cache_dir).await?;
-                    println!("\x1b[32mCache cleared\x1b[0m");
+                    output::print_line(self.color_mode, "\x1b[32mCache cleared\x1b[0m");
                 } else {
-                    println!("\x1b[90mCache is already empty\x1b[0m");
+                    output::print_line(self.color_mode, "\x1b[90mCache is already empty\x1b[0m");
                 }
             }
+            crate::CacheCommands::Path => {
+                // Plain path without a newline or colors, meant for `$(rpm cache path)`
+                print!("{}", self.installer.cache_dir.display());
+            }
             crate::CacheCommands::Info => {
                 let path = &self.installer.cache_dir;
-                println!("\x1b[1mLocation:\x1b[0m  {}", path.display());
+                output::print_line(
+                    self.color_mode,
+                    &format!("\x1b[1mLocation:\x1b[0m  {}", path.display()),
+                );
 
                 if path.exists() {
-                    let size = fs_extra::dir::get_size(path).unwrap_or(0);
-                    println!(
-                        "\x1b[1mSize:\x1b[0m      \x1b[36m{:.2} MB\x1b[0m",
-                        size as f64 / 1024.0 / 1024.0
+                    // Only package entries count, not tmp/ extractions or the exec cache
+                    let installer = self.installer.clone();
+                    let (size, count) = tokio::task::spawn_blocking(move || {
+                        let entries = installer.package_entries();
+                        let size: u64 = entries
+                            .iter()
+                            .map(|entry| fs_extra::dir::get_size(entry).unwrap_or(0))
+                            .sum();
+                        (size, entries.len())
+                    })
+                    .await?;
+                    output::print_line(
+                        self.color_mode,
+                        &format!(
+                            "\x1b[1mSize:\x1b[0m      \x1b[36m{:.2} MB\x1b[0m",
+                            size as f64 / 1024.0 / 1024.0
+                        ),
                     );
 
-                    let count = std::fs::read_dir(path)?.count();
-                    println!("\x1b[1mPackages:\x1b[0m  \x1b[36m{}\x1b[0m", count);
+                    output::print_line(
+                        self.color_mode,
+                        &format!("\x1b[1mPackages:\x1b[0m  \x1b[36m{}\x1b[0m", count),
+                    );
                 } else {
-                    println!("\x1b[1mSize:\x1b[0m      \x1b[90m0 MB\x1b[0m");
-                    println!("\x1b[1mPackages:\x1b[0m  \x1b[90m0\x1b[0m");
+                    output::print_line(
+                        self.color_mode,
+                        "\x1b[1mSize:\x1b[0m      \x1b[90m0 MB\x1b[0m",
+                    );
+                    output::print_line(
+                        self.color_mode,
+                        "\x1b[1mPackages:\x1b[0m  \x1b[90m0\x1b[0m",
+                    );
                 }
             }
-        }
-        Ok(())
-    }
-
-    pub async fn add_packages(&self, packages: Vec<String>, dev: bool) -> Result<()> {
-        self.load_lockfile().await?;
-        let package_json_content = fs::read_to_string("package.json").await?;
-        let mut package_json: PackageJson = serde_json::from_str(&package_json_content)?;
-
-        let spinner = self.create_spinner();
-        let mut added_packages: Vec<(String, String)> = Vec::new();
+            crate::CacheCommands::Ls => {
+                let installer = self.installer.clone();
+                let entries = tokio::task::spawn_blocking(move || {
+                    installer
+                        .package_entries()
+                        .into_iter()
+                        .map(|entry| {
+                            let size = fs_extra::dir::get_size(&entry).unwrap_or(0);
+                            let last_used = Installer::last_used(&entry);
+                            (entry, size, last_used)
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .await?;
+                if entries.is_empty() {
+                    output::print_line(self.color_mode, "\x1b[90mCache is empty\x1b[0m");
+                    return Ok(());
+                }
 
-        for pkg_input in packages {
-            let (name, range) = if let Some(idx) = pkg_input.rfind('@') {
-                if idx == 0 {
-                    (pkg_input.as_str(), "latest")
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs());
+                for (entry, size, last_used) in &entries {
+                    let name = entry
+                        .file_name()
+                        .map(|n| n.to_string_lossy().replace('+', "/"))
+                        .unwrap_or_default();
+                    let age = last_used
+                        .map(|used| output::format_age(now.saturating_sub(used)))
+                        .unwrap_or_else(|| "unknown".to_string());
+                    output::print_line(
+                        self.color_mode,
+                        &format!(
+                            "{:<50} \x1b[36m{:>10}\x1b[0m  \x1b[90m{}\x1b[0m",
+                            name,
+                            output::format_bytes(*size),
+                            age
+                        ),
+                    );
+                }
+                output::print_line(
+                    self.color_mode,
+                    &format!("\n\x1b[90m{} packages\x1b[0m", entries.len()),
+                );
+            }
+            crate::CacheCommands::Gc { max_age_days } => self.gc_cache(max_age_days).await?,
+            crate::CacheCommands::CheckIntegrity { fix } => self.check_store_integrity(fix).await?,
+            crate::CacheCommands::Add { packages } => self.cache_add(&packages).await?,
+            crate::CacheCommands::Export { output: archive } => {
+                let cache_dir = self.installer.cache_dir.clone();
+                if !cache_dir.exists() {
+                    output::print_line(
+                        self.color_mode,
+                        "\x1b[90mCache is empty, nothing to export\x1b[0m",
+                    );
+                    return Ok(());
+                }
+
+                let spinner = self.create_spinner();
+                spinner.set_message(output::maybe_strip_colors(
+                    self.color_mode,
+                    &format!("\x1b[1mExporting\x1b[0m cache to {}...", archive.display()),
+                ));
+
+                let archive_path = archive.clone();
+                tokio::task::spawn_blocking(move || -> Result<()> {
+                    let file = std::fs::File::create(&archive_path)
+                        .with_context(|| format!("Failed to create {}", archive_path.display()))?;
+                    let encoder =
+                        flate2::write::GzEncoder::new(file, flate2::Compression::default());
+                    let mut builder = tar::Builder::new(encoder);
+                    builder.follow_symlinks(false);
+
+                    for entry in std::fs::read_dir(&cache_dir)? {
+                        let entry = entry?;
+                        let name = entry.file_name();
+                        // Skip in-progress extractions and this machine's projects list
+                        if is_machine_local(&name) {
+                            continue;
+                        }
+                        if entry.file_type()?.is_dir() {
+                            builder.append_dir_all(&name, entry.path())?;
+                        } else {
+                            builder.append_path_with_name(entry.path(), &name)?;
+                        }
+                    }
+
+                    builder.into_inner()?.finish()?;
+                    Ok(())
+                })
+                .await??;
+
+                spinner.finish_and_clear();
+                let size = fs::metadata(&archive).await.map(|m| m.len()).unwrap_or(0);
+                output::print_line(
+                    self.color_mode,
+                    &format!(
+                        "\x1b[32m✓\x1b[0m Exported cache to \x1b[1m{}\x1b[0m \x1b[90m({})\x1b[0m",
+                        archive.display(),
+                        output::format_bytes(size)
+                    ),
+                );
+            }
+            crate::CacheCommands::Import { input: archive } => {
+                let size = fs::metadata(&archive)
+                    .await
+                    .with_context(|| format!("Could not find {}", archive.display()))?
+                    .len();
+
+                let spinner = self.create_spinner();
+                spinner.set_message(output::maybe_strip_colors(
+                    self.color_mode,
+                    &format!(
+                        "\x1b[1mImporting\x1b[0m cache from {}...",
+                        archive.display()
+                    ),
+                ));
+
+                let installer = self.installer.clone();
+                let cache_dir = self.installer.cache_dir.clone();
+                let archive_path = archive.clone();
+                let count = tokio::task::spawn_blocking(move || -> Result<usize> {
+                    std::fs::create_dir_all(&cache_dir)?;
+                    let file = std::fs::File::open(&archive_path)?;
+                    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+                    // Archives from older versions carry the exporter's projects list, which
+                    // would replace ours and let gc delete entries our projects still use
+                    for entry in archive.entries()? {
+                        let mut entry = entry?;
+                        let path = entry.path()?.into_owned();
+                        if path
+                            .components()
+                            .next()
+                            .is_some_and(|first| is_machine_local(first.as_os_str()))
+                        {
+                            continue;
+                        }
+                        entry.unpack_in(&cache_dir)?;
+                    }
+                    Ok(installer.package_entries().len())
+                })
+                .await??;
+
+                spinner.finish_and_clear();
+                output::print_line(self.color_mode, &format!(
+                    "\x1b[32m✓\x1b[0m Imported \x1b[1m{}\x1b[0m \x1b[90m({})\x1b[0m, cache now holds \x1b[36m{}\x1b[0m packages",
+                    archive.display(),
+                    output::format_bytes(size),
+                    count
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Download packages into the cache, without touching node_modules (`rpm cache add`)
+    async fn cache_add(&self, packages: &[String]) -> Result<()> {
+        let mut tasks: FuturesUnordered<_> = packages
+            .iter()
+            .map(|input| async move {
+                let (name, range) = match input.rfind('@') {
+                    Some(idx) if idx > 0 => (&input[..idx], &input[idx + 1..]),
+                    _ => (input.as_str(), "latest"),
+                };
+                let result = async {
+                    let package = self.registry.get_package(name).await?;
+                    let resolved = self.registry.resolve_version(&package, range)?;
+                    let path = self
+                        .installer
+                        .ensure_cache_entry(
+                            name,
+                            &resolved.version,
+                            &resolved.dist.tarball,
+                            resolved.dist.integrity.as_deref(),
+                            self.registry.tarball_token(name, &resolved.dist.tarball),
+                        )
+                        .await?;
+                    anyhow::Ok((resolved.version.clone(), path))
+                }
+                .await;
+                (name, result)
+            })
+            .collect();
+
+        let mut failed = 0;
+        while let Some((name, result)) = tasks.next().await {
+            match result {
+                Ok((version, path)) => output::print_line(
+                    self.color_mode,
+                    &format!(
+                        "\x1b[32m✓\x1b[0m \x1b[1m{}@{}\x1b[0m  \x1b[36m{}\x1b[0m  \x1b[90m{}\x1b[0m",
+                        name,
+                        version,
+                        output::format_bytes(fs_extra::dir::get_size(&path).unwrap_or(0)),
+                        path.display()
+                    ),
+                ),
+                Err(e) => {
+                    output::eprint_line(
+                        self.color_mode,
+                        &format!("{}✗{} {}: {:#}", colors::RED, colors::RESET, name, e),
+                    );
+                    failed += 1;
+                }
+            }
+        }
+
+        if failed > 0 {
+            return Err(RpmError::Other {
+                message: format!("{} of {} packages could not be cached", failed, packages.len()),
+                hint: None,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Remove cache entries unused for `max_age_days`, and entries whose package or
+    /// tarball is gone from the registry. Registry errors other than 404 keep the entry.
+    async fn gc_cache(&self, max_age_days: u64) -> Result<()> {
+        let entries = self.installer.package_entries();
+        if entries.is_empty() {
+            output::print_line(self.color_mode, "\x1b[90mCache is empty\x1b[0m");
+            return Ok(());
+        }
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let max_age = max_age_days.saturating_mul(24 * 60 * 60);
+
+        // (entry, reason)
+        let mut to_remove: Vec<(PathBuf, String)> = Vec::new();
+        let mut to_check: Vec<(PathBuf, String, String)> = Vec::new();
+        for entry in entries {
+            let Some((name, version)) = Installer::parse_entry(&entry) else {
+                continue;
+            };
+            match Installer::last_used(&entry) {
+                Some(used) if now.saturating_sub(used) > max_age => {
+                    let reason = format!("last used {}", output::format_age(now - used));
+                    to_remove.push((entry, reason));
+                }
+                _ => to_check.push((entry, name, version)),
+            }
+        }
+
+        let spinner = self.create_spinner();
+        spinner.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            &format!(
+                "\x1b[1mChecking\x1b[0m {} packages against the registry...",
+                to_check.len()
+            ),
+        ));
+        let mut tasks = FuturesUnordered::new();
+        for (entry, name, version) in to_check {
+            tasks.push(async move {
+                let _permit = self.semaphore.acquire().await;
+                let gone = match self.registry.get_package(&name).await {
+                    Ok(package) => match package.versions.get(&version) {
+                        Some(v) => !self
+                            .registry
+                            .tarball_exists(&name, &v.dist.tarball)
+                            .await
+                            .unwrap_or(true),
+                        None => true,
+                    },
+                    Err(e) => matches!(
+                        e.downcast_ref::<RpmError>(),
+                        Some(RpmError::PackageNotFound { .. })
+                    ),
+                };
+                (entry, gone)
+            });
+        }
+        while let Some((entry, gone)) = tasks.next().await {
+            if gone {
+                to_remove.push((entry, "no longer in the registry".to_string()));
+            }
+        }
+        spinner.finish_and_clear();
+
+        // Entries projects still symlink to stay, removing them would break those projects
+        let installer = self.installer.clone();
+        let referenced =
+            tokio::task::spawn_blocking(move || installer.referenced_entries()).await?;
+        let candidates = to_remove.len();
+        to_remove.retain(|(entry, _)| !referenced.contains(entry));
+        let in_use = candidates - to_remove.len();
+        if in_use > 0 {
+            output::print_line(
+                self.color_mode,
+                &format!(
+                    "\x1b[90mKept {} entries still linked from a project's node_modules\x1b[0m",
+                    in_use
+                ),
+            );
+        }
+
+        if to_remove.is_empty() {
+            output::print_line(self.color_mode, "\x1b[32m✓\x1b[0m Nothing to collect");
+            return Ok(());
+        }
+
+        to_remove.sort();
+        let mut freed = 0;
+        for (entry, reason) in &to_remove {
+            freed += fs_extra::dir::get_size(entry).unwrap_or(0);
+            Installer::remove_entry(entry).await?;
+            let (name, version) = Installer::parse_entry(entry).unwrap_or_default();
+            output::print_line(
+                self.color_mode,
+                &format!(
+                    "\x1b[31m-\x1b[0m {}@{} \x1b[90m({})\x1b[0m",
+                    name, version, reason
+                ),
+            );
+        }
+        output::print_line(
+            self.color_mode,
+            &format!(
+                "\n\x1b[32m✓\x1b[0m Removed {} entries, freed \x1b[36m{}\x1b[0m",
+                to_remove.len(),
+                output::format_bytes(freed)
+            ),
+        );
+        Ok(())
+    }
+
+    /// Re-hash every cache entry and compare it with the content hash recorded when it was
+    /// extracted. Corrupted entries are listed, and re-extracted with `fix`.
+    async fn check_store_integrity(&self, fix: bool) -> Result<()> {
+        let entries = self.installer.package_entries();
+        if entries.is_empty() {
+            output::print_line(self.color_mode, "\x1b[90mCache is empty\x1b[0m");
+            return Ok(());
+        }
+
+        let spinner = self.create_spinner();
+        spinner.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            &format!("\x1b[1mChecking\x1b[0m {} packages...", entries.len()),
+        ));
+        let mut tasks: FuturesUnordered<_> = entries
+            .into_iter()
+            .map(|entry| {
+                tokio::task::spawn_blocking(move || {
+                    // None: extracted before content hashes were recorded
+                    let ok = Installer::stored_content_hash(&entry).map(|stored| {
+                        Installer::content_hash(&entry).is_ok_and(|hash| hash == stored)
+                    });
+                    (entry, ok)
+                })
+            })
+            .collect();
+
+        let (mut ok, mut unverified) = (0, 0);
+        let mut corrupted = Vec::new();
+        while let Some(result) = tasks.next().await {
+            match result? {
+                (_, Some(true)) => ok += 1,
+                (entry, Some(false)) => corrupted.push(entry),
+                (_, None) => unverified += 1,
+            }
+        }
+        spinner.finish_and_clear();
+
+        corrupted.sort();
+        let mut unfixed = 0;
+        for entry in &corrupted {
+            let (name, version) = Installer::parse_entry(entry).unwrap_or_default();
+            if !fix {
+                output::print_line(
+                    self.color_mode,
+                    &format!(
+                        "\x1b[31m✗\x1b[0m {}@{} \x1b[90m({})\x1b[0m",
+                        name,
+                        version,
+                        entry.display()
+                    ),
+                );
+                continue;
+            }
+            match self.reextract_entry(&name, &version).await {
+                Ok(()) => output::print_line(
+                    self.color_mode,
+                    &format!(
+                        "\x1b[32m↻\x1b[0m {}@{} \x1b[90m(re-extracted)\x1b[0m",
+                        name, version
+                    ),
+                ),
+                Err(e) => {
+                    output::eprint_line(
+                        self.color_mode,
+                        &format!(
+                            "{}✗{} {}@{}: {:#}",
+                            colors::RED,
+                            colors::RESET,
+                            name,
+                            version,
+                            e
+                        ),
+                    );
+                    unfixed += 1;
+                }
+            }
+        }
+
+        let mut summary = format!("{} packages OK, {} corrupted", ok, corrupted.len());
+        if fix && !corrupted.is_empty() {
+            summary.push_str(&format!(" ({} re-extracted)", corrupted.len() - unfixed));
+        }
+        if unverified > 0 {
+            summary.push_str(&format!(", {} without a recorded hash", unverified));
+        }
+        let remaining = if fix { unfixed } else { corrupted.len() };
+        if remaining == 0 {
+            output::print_line(self.color_mode, &format!("\n\x1b[32m✓\x1b[0m {}", summary));
+            return Ok(());
+        }
+        println!();
+        Err(RpmError::Other {
+            message: summary,
+            hint: (!fix).then(|| {
+                "Run `rpm cache check-integrity --fix` to re-extract them".to_string()
+            }),
+        }
+        .into())
+    }
+
+    /// Download a cached package version again and extract it over its store entry, which
+    /// stays in place for the projects linking to it
+    async fn reextract_entry(&self, name: &str, version: &str) -> Result<()> {
+        let package = self.registry.get_package(name).await?;
+        let resolved = package
+            .versions
+            .get(version)
+            .with_context(|| format!("{}@{} is no longer in the registry", name, version))?;
+        let tarball = &resolved.dist.tarball;
+        self.installer
+            .fetch_cache_entry(
+                name,
+                version,
+                tarball,
+                resolved.dist.integrity.as_deref(),
+                self.registry.tarball_token(name, tarball),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Show how `rpm install` would change rpm-lock.json, without writing anything
+    pub async fn diff_lockfile(&self) -> Result<()> {
+        let root = self.project_dir()?;
+        let package_json_content = fs::read_to_string(root.join("package.json"))
+            .await
+            .context("Could not find package.json in current directory")?;
+        let package_json: PackageJson = serde_json::from_str(&package_json_content)?;
+
+        self.load_lockfile().await?;
+        let root_deps = self.lock_root_dependencies(&root, &package_json).await?;
+
+        let spinner = self.create_spinner();
+        spinner.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            "\x1b[1mResolving\x1b[0m dependencies...",
+        ));
+        let resolved = self.resolve_tree(root_deps).await;
+        spinner.finish_and_clear();
+        let resolved: BTreeMap<String, String> = resolved?
+            .into_iter()
+            .map(|(name, entry)| (name, entry.version))
+            .collect();
+
+        // Only top-level entries, nested copies are left alone by install
+        let current: BTreeMap<String, String> = self
+            .lockfile
+            .lock()
+            .await
+            .packages
+            .iter()
+            .filter_map(|(key, entry)| {
+                let name = key.strip_prefix("node_modules/")?;
+                (!name.contains("/node_modules/"))
+                    .then(|| (name.to_string(), entry.version.clone()))
+            })
+            .collect();
+
+        let mut changes = 0;
+        for (name, version) in &resolved {
+            match current.get(name) {
+                None => output::print_line(
+                    self.color_mode,
+                    &format!("\x1b[32m+ {}@{}\x1b[0m", name, version),
+                ),
+                Some(old) if old != version => output::print_line(
+                    self.color_mode,
+                    &format!(
+                        "\x1b[33m~ {}\x1b[0m \x1b[90m{}\x1b[0m → \x1b[1m{}\x1b[0m",
+                        name, old, version
+                    ),
+                ),
+                Some(_) => continue,
+            }
+            changes += 1;
+        }
+        for (name, version) in &current {
+            if !resolved.contains_key(name) {
+                output::print_line(
+                    self.color_mode,
+                    &format!("\x1b[31m- {}@{}\x1b[0m", name, version),
+                );
+                changes += 1;
+            }
+        }
+
+        if changes == 0 {
+            output::print_line(
+                self.color_mode,
+                "\x1b[32m✓\x1b[0m rpm-lock.json is up to date",
+            );
+        } else {
+            println!();
+            output::print_line(
+                self.color_mode,
+                &format!("\x1b[90m{} lockfile change(s)\x1b[0m", changes),
+            );
+        }
+        Ok(())
+    }
+
+    /// Top-level dependencies of the project (hoisted for a workspace), with the
+    /// overrides of the root package.json loaded
+    async fn lock_root_dependencies(
+        &self,
+        root: &Path,
+        package_json: &PackageJson,
+    ) -> Result<Vec<(String, String)>> {
+        Ok(match Workspace::discover(root).await? {
+            Some(workspace) => {
+                self.load_overrides(&workspace.root_package);
+                workspace.get_hoisted_dependencies().into_iter().collect()
+            }
+            None => {
+                self.load_overrides(package_json);
+                package_json
+                    .dependencies
+                    .iter()
+                    .chain(&package_json.dev_dependencies)
+                    .chain(root_peer_dependencies(package_json))
+                    .map(|(n, v)| (n.clone(), v.clone()))
+                    .collect()
+            }
+        })
+    }
+
+    /// Regenerate rpm-lock.json from scratch: every package is resolved again from the
+    /// registry, ignoring the versions locked so far. node_modules is left as it is.
+    async fn update_lockfile(&self) -> Result<()> {
+        let root = self.project_dir()?;
+        let package_json_content = fs::read_to_string(root.join("package.json"))
+            .await
+            .context("Could not find package.json in current directory")?;
+        let package_json: PackageJson = serde_json::from_str(&package_json_content)?;
+
+        // Keep the settings of the old lockfile, but none of its packages
+        self.load_lockfile().await?;
+        let previous = std::mem::take(&mut self.lockfile.lock().await.packages);
+        let root_deps = self.lock_root_dependencies(&root, &package_json).await?;
+
+        let spinner = self.create_spinner();
+        spinner.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            "\x1b[1mResolving\x1b[0m dependencies...",
+        ));
+        let resolved = self.resolve_tree(root_deps).await;
+        spinner.finish_and_clear();
+        let packages: BTreeMap<String, LockPackage> = resolved?
+            .into_iter()
+            .map(|(name, entry)| (format!("node_modules/{}", name), entry))
+            .collect();
+
+        let changed = packages
+            .iter()
+            .filter(|(key, entry)| {
+                previous.get(*key).map(|old| &old.version) != Some(&entry.version)
+            })
+            .count()
+            + previous.keys().filter(|key| !packages.contains_key(*key)).count();
+        let count = packages.len();
+        self.lockfile.lock().await.packages = packages;
+        self.save_lockfile(&package_json.name, &package_json.version)
+            .await?;
+
+        output::print_line(
+            self.color_mode,
+            &format!(
+                "\x1b[32m✓\x1b[0m Regenerated rpm-lock.json with \x1b[1m{}\x1b[0m packages \
+             \x1b[90m({} changed)\x1b[0m",
+                count, changed
+            ),
+        );
+        if changed > 0 {
+            output::info(self.color_mode, "Run `rpm install` to update node_modules");
+        }
+        Ok(())
+    }
+
+    /// Resolve the flat dependency tree an install would produce (name -> lock entry),
+    /// level by level and without downloading or linking anything
+    async fn resolve_tree(
+        &self,
+        root_deps: Vec<(String, String)>,
+    ) -> Result<BTreeMap<String, LockPackage>> {
+        let mut resolved: BTreeMap<String, LockPackage> = BTreeMap::new();
+        let mut seen: HashSet<String> = HashSet::new();
+        // (name, range, optional, parent)
+        let mut pending: Vec<(String, String, bool, Option<String>)> = root_deps
+            .into_iter()
+            .map(|(name, range)| (name, range, false, None))
+            .collect();
+
+        while !pending.is_empty() {
+            let mut tasks = FuturesUnordered::new();
+            for (name, range, optional, parent) in pending.drain(..) {
+                if !seen.insert(name.clone()) {
+                    continue;
+                }
+                let range = self.override_range(&name, parent.as_deref(), range);
+                tasks.push(async move {
+                    if optional
+                        && !self
+                            .check_optional_dep_compatible(&name, &range)
+                            .await
+                            .unwrap_or(false)
+                    {
+                        return (name, optional, Ok(None));
+                    }
+                    let entry = self.resolve_lock_package(&name, &range).await;
+                    (name, optional, entry.map(Some))
+                });
+            }
+
+            let mut next = Vec::new();
+            while let Some((name, optional, result)) = tasks.next().await {
+                let entry = match result {
+                    Ok(Some(entry)) => entry,
+                    Ok(None) => continue,
+                    // Failing optional dependencies are skipped by install too
+                    Err(_) if optional => continue,
+                    Err(e) => return Err(e),
+                };
+                let parent = || Some(name.clone());
+                let deps = |deps: &BTreeMap<String, String>, optional: bool| {
+                    deps.iter()
+                        .map(|(n, v)| (n.clone(), v.clone(), optional, parent()))
+                        .collect::<Vec<_>>()
+                };
+                next.extend(deps(&entry.dependencies, false));
+                if self.install_peers.load(Ordering::Relaxed) {
+                    next.extend(deps(&entry.peer_dependencies, false));
+                }
+                if self.install_optional.load(Ordering::Relaxed) {
+                    next.extend(deps(&entry.optional_dependencies, true));
+                }
+                resolved.insert(name, entry);
+            }
+            pending = next;
+        }
+
+        Ok(resolved)
+    }
+
+    pub async fn handle_lock_command(&self, command: crate::LockCommands) -> Result<()> {
+        match command {
+            crate::LockCommands::Verify => self.verify_lockfile().await,
+            crate::LockCommands::Fix => self.rebuild_lockfile().await,
+            crate::LockCommands::Update => self.update_lockfile().await,
+        }
+    }
+
+    pub fn handle_config_command(&self, command: crate::ConfigCommands) -> Result<()> {
+        let mut config = self.config.clone();
+        match command {
+            crate::ConfigCommands::Set { key, value } => {
+                config.set(&key, &value)?;
+                config.save()?;
+                output::print_line(
+                    self.color_mode,
+                    &format!(
+                        "\x1b[32m✓\x1b[0m Set \x1b[1m{}\x1b[0m = {}",
+                        key,
+                        Config::display_value(&key, &value)
+                    ),
+                );
+            }
+            crate::ConfigCommands::Get { key } => {
+                if let Some(value) = config.get(&key) {
+                    println!("{}", value);
+                }
+            }
+            crate::ConfigCommands::Delete { key } => {
+                if config.delete(&key) {
+                    config.save()?;
+                    output::print_line(
+                        self.color_mode,
+                        &format!("\x1b[32m✓\x1b[0m Removed \x1b[1m{}\x1b[0m", key),
+                    );
+                } else {
+                    output::print_line(
+                        self.color_mode,
+                        &format!("\x1b[90m{} is not set\x1b[0m", key),
+                    );
+                }
+            }
+            crate::ConfigCommands::List => {
+                let mut entries = config.entries().peekable();
+                if entries.peek().is_none() {
+                    output::print_line(self.color_mode, "\x1b[90mNo config values set\x1b[0m");
+                }
+                for (key, value) in entries {
+                    output::print_line(
+                        self.color_mode,
+                        &format!(
+                            "\x1b[1m{}\x1b[0m = {}",
+                            key,
+                            Config::display_value(key, value)
+                        ),
+                    );
+                }
+                output::print_line(
+                    self.color_mode,
+                    &format!("\x1b[90m{}\x1b[0m", Config::path().display()),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn handle_token_command(&self, command: crate::TokenCommands) -> Result<()> {
+        let mut tokens = Tokens::load()?;
+        match command {
+            crate::TokenCommands::Add { registry, token } => {
+                tokens.add(&registry, &token);
+                tokens.save()?;
+                output::warning(
+                    self.color_mode,
+                    &format!(
+                        "tokens are stored in plain text in {}",
+                        Tokens::path().display()
+                    ),
+                );
+                output::print_line(
+                    self.color_mode,
+                    &format!(
+                        "\x1b[32m✓\x1b[0m Saved token {} for \x1b[1m{}\x1b[0m",
+                        mask_token(&token),
+                        registry
+                    ),
+                );
+            }
+            crate::TokenCommands::Remove { registry } => {
+                if tokens.remove(&registry) {
+                    tokens.save()?;
+                    output::print_line(
+                        self.color_mode,
+                        &format!(
+                            "\x1b[32m✓\x1b[0m Removed token for \x1b[1m{}\x1b[0m",
+                            registry
+                        ),
+                    );
                 } else {
-                    (&pkg_input[..idx], &pkg_input[idx + 1..])
+                    output::print_line(
+                        self.color_mode,
+                        &format!("\x1b[90mNo token saved for {}\x1b[0m", registry),
+                    );
+                }
+            }
+            crate::TokenCommands::List => {
+                let mut entries = tokens.entries().peekable();
+                if entries.peek().is_none() {
+                    output::print_line(self.color_mode, "\x1b[90mNo tokens saved\x1b[0m");
+                }
+                for (registry, token) in entries {
+                    output::print_line(
+                        self.color_mode,
+                        &format!("\x1b[1m{}\x1b[0m {}", registry, mask_token(token)),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Print the user the configured registry token belongs to
+    pub async fn whoami(&self, scope: Option<&str>) -> Result<()> {
+        let (registry, username) = self.registry.whoami(scope).await?;
+        let Some(username) = username else {
+            let hint = match scope {
+                Some(scope) => format!("Run `rpm config set {}:token <token>`", scope),
+                None => format!("Run `rpm token add {} <token>`", registry),
+            };
+            return Err(RpmError::Other {
+                message: format!("Not authenticated with {}", registry),
+                hint: Some(hint),
+            }
+            .into());
+        };
+        println!("{}", username);
+        Ok(())
+    }
+
+    /// Rebuild rpm-lock.json from scratch from what is installed in node_modules
+    async fn rebuild_lockfile(&self) -> Result<()> {
+        let package_json_content = fs::read_to_string(self.project_dir()?.join("package.json"))
+            .await
+            .context("Could not find package.json in current directory")?;
+        let package_json: PackageJson = serde_json::from_str(&package_json_content)?;
+        let node_modules = self.project_dir()?.join("node_modules");
+
+        // Keep the settings of the old lockfile if it can still be read
+        self.load_lockfile().await?;
+
+        let spinner = self.create_spinner();
+        spinner.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            "\x1b[1mScanning\x1b[0m node_modules...",
+        ));
+
+        let mut installed: Vec<PackageJson> = Vec::new();
+        for path in scan_node_modules(&node_modules).await {
+            // Symlinks outside the store are local workspace packages, which are not locked
+            if path.is_symlink() && !self.installer.is_store_link(&path) {
+                continue;
+            }
+            if let Ok(content) = fs::read_to_string(path.join("package.json")).await {
+                if let Ok(pkg) = serde_json::from_str::<PackageJson>(&content) {
+                    installed.push(pkg);
+                }
+            }
+        }
+
+        spinner.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            &format!(
+                "\x1b[1mFetching\x1b[0m tarball URLs for {} packages...",
+                installed.len()
+            ),
+        ));
+
+        // The tarball URL is not stored in the package directory, ask the registry
+        let mut tasks = FuturesUnordered::new();
+        for pkg in installed {
+            let registry = self.registry.clone();
+            tasks.push(async move {
+                let dist = registry
+                    .get_package(&pkg.name)
+                    .await
+                    .ok()
+                    .and_then(|p| p.versions.get(&pkg.version).map(|v| v.dist.clone()));
+                (pkg, dist)
+            });
+        }
+
+        let mut packages: BTreeMap<String, LockPackage> = BTreeMap::new();
+        let mut skipped: Vec<String> = Vec::new();
+        while let Some((pkg, dist)) = tasks.next().await {
+            let Some(dist) = dist else {
+                skipped.push(format!("{}@{}", pkg.name, pkg.version));
+                continue;
+            };
+            let postinstall = pkg
+                .scripts
+                .get("postinstall")
+                .or(pkg.scripts.get("install"))
+                .cloned();
+            packages.insert(
+                format!("node_modules/{}", pkg.name),
+                LockPackage {
+                    version: pkg.version,
+                    resolved: dist.tarball,
+                    integrity: dist.integrity,
+                    dependencies: pkg.dependencies,
+                    peer_dependencies: pkg.peer_dependencies,
+                    optional_dependencies: pkg.optional_dependencies,
+                    postinstall,
+                    bin: pkg.bin,
+                },
+            );
+        }
+
+        spinner.finish_and_clear();
+
+        for name in &skipped {
+            output::warning(
+                self.color_mode,
+                &format!(
+                    "could not find {} in the registry, leaving it out of rpm-lock.json",
+                    name
+                ),
+            );
+        }
+
+        let count = packages.len();
+        self.lockfile.lock().await.packages = packages;
+        self.save_lockfile(&package_json.name, &package_json.version)
+            .await?;
+
+        output::print_line(
+            self.color_mode,
+            &format!(
+                "\x1b[32m✓\x1b[0m Rebuilt rpm-lock.json with \x1b[1m{}\x1b[0m packages",
+                count
+            ),
+        );
+        Ok(())
+    }
+
+    /// Check that node_modules matches rpm-lock.json
+    async fn verify_lockfile(&self) -> Result<()> {
+        let content = fs::read_to_string(self.project_dir()?.join("rpm-lock.json"))
+            .await
+            .context("Could not find rpm-lock.json in current directory")?;
+        let lock = LockFile::parse(&content).context("Failed to parse rpm-lock.json")?;
+        let node_modules = self.project_dir()?.join("node_modules");
+
+        let mut problems = 0;
+
+        // Every lockfile entry must be installed with the locked version
+        for (key, entry) in &lock.packages {
+            let name = key.trim_start_matches("node_modules/");
+            let pkg_json_path = node_modules.join(name).join("package.json");
+            let installed = match fs::read_to_string(&pkg_json_path).await {
+                Ok(c) => serde_json::from_str::<PackageJson>(&c).ok(),
+                Err(_) => None,
+            };
+
+            match installed {
+                None => {
+                    output::warning(
+                        self.color_mode,
+                        &format!(
+                            "{}@{} is in rpm-lock.json but not installed",
+                            name, entry.version
+                        ),
+                    );
+                    problems += 1;
+                }
+                Some(pkg) if pkg.version != entry.version => {
+                    output::warning(
+                        self.color_mode,
+                        &format!(
+                            "{} is locked at {} but {} is installed",
+                            name, entry.version, pkg.version
+                        ),
+                    );
+                    problems += 1;
                 }
+                Some(_) => {}
+            }
+        }
+
+        // Every installed package must have a lockfile entry
+        // (symlinks outside the store are local workspace packages, which are not locked)
+        for path in scan_node_modules(&node_modules).await {
+            if path.is_symlink() && !self.installer.is_store_link(&path) {
+                continue;
+            }
+            let name = path
+                .strip_prefix(&node_modules)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .replace('\\', "/");
+            if !lock
+                .packages
+                .contains_key(&format!("node_modules/{}", name))
+            {
+                output::warning(
+                    self.color_mode,
+                    &format!("{} is installed but missing from rpm-lock.json", name),
+                );
+                problems += 1;
+            }
+        }
+
+        if problems > 0 {
+            return Err(RpmError::Other {
+                message: format!(
+                    "Found {} mismatch(es) between rpm-lock.json and node_modules",
+                    problems
+                ),
+                hint: Some("Run 'rpm install' to bring node_modules in sync".to_string()),
+            }
+            .into());
+        }
+
+        output::print_line(
+            self.color_mode,
+            &format!(
+                "\x1b[32m✓\x1b[0m node_modules matches rpm-lock.json \x1b[90m({} packages)\x1b[0m",
+                lock.packages.len()
+            ),
+        );
+        Ok(())
+    }
+
+    /// Installed version of a package `rpm add` can skip: every target package.json
+    /// declares it in the section the add would write to, and both that range and the
+    /// requested one match the version installed in node_modules
+    async fn installed_as_requested(
+        &self,
+        name: &str,
+        range: &str,
+        targets: &[PathBuf],
+        options: &AddOptions,
+    ) -> Option<String> {
+        let package_dir = self.project_dir().ok()?.join("node_modules").join(name);
+        let content = fs::read_to_string(package_dir.join("package.json"))
+            .await
+            .ok()?;
+        let installed = serde_json::from_str::<PackageJson>(&content).ok()?.version;
+        if version_satisfies(range, &installed) != Some(true) {
+            return None;
+        }
+
+        for path in targets {
+            let content = fs::read_to_string(path).await.ok()?;
+            let target_json: PackageJson = serde_json::from_str(&content).ok()?;
+            let declared = if options.peer {
+                target_json.peer_dependencies.get(name)
+            } else if options.dev {
+                target_json.dev_dependencies.get(name)
+            } else if options.auto_dev {
+                target_json
+                    .dependencies
+                    .get(name)
+                    .or_else(|| target_json.dev_dependencies.get(name))
             } else {
-                (pkg_input.as_str(), "latest")
+                target_json.dependencies.get(name)
             };
+            if version_satisfies(declared?, &installed) != Some(true) {
+                return None;
+            }
+        }
+        Some(installed)
+    }
+
+    pub async fn add_packages(&self, packages: Vec<String>, options: AddOptions) -> Result<()> {
+        // Everything below works relative to the current directory, so move to the root
+        if options.global {
+            let global_dir = Config::path().with_file_name("global");
+            let package_json = global_dir.join("package.json");
+            if !package_json.exists() {
+                fs::create_dir_all(&global_dir).await?;
+                fs::write(
+                    &package_json,
+                    "{\n  \"name\": \"rpm-global\",\n  \"version\": \"0.0.0\"\n}",
+                )
+                .await?;
+            }
+            std::env::set_current_dir(&global_dir)?;
+            // Global binaries run without NODE_PRESERVE_SYMLINKS, a package symlinked to
+            // the store would not find its dependencies
+            self.installer.copy_packages();
+        }
+        if options.workspace_root {
+            let cwd = self.project_dir()?;
+            let workspace = Workspace::find_root(&cwd)
+                .await?
+                .ok_or_else(|| RpmError::WorkspaceError {
+                    message: "No workspace root found above the current directory".to_string(),
+                    hint: None,
+                })?;
+            std::env::set_current_dir(&workspace.root)?;
+        }
+
+        self.load_lockfile().await?;
+
+        // In workspace mode, member package.json files are edited but packages are
+        // installed (hoisted) at the workspace root
+        let root_workspace = if options.workspace.is_some() || options.all_workspaces {
+            let root = self.project_dir()?;
+            Some(
+                Workspace::discover(&root)
+                    .await?
+                    .ok_or_else(|| RpmError::WorkspaceError {
+                        message: "Not in a workspace root, cannot add to workspace members"
+                            .to_string(),
+                        hint: None,
+                    })?,
+            )
+        } else {
+            None
+        };
+
+        let targets: Vec<PathBuf> = match (&root_workspace, &options.workspace) {
+            (Some(ws), _) if options.all_workspaces => ws
+                .members
+                .iter()
+                .map(|m| m.path.join("package.json"))
+                .collect(),
+            (Some(ws), Some(member_name)) => match ws.find_member(member_name) {
+                Some(member) => vec![member.path.join("package.json")],
+                None => {
+                    return Err(RpmError::WorkspaceError {
+                        message: format!(
+                            "Workspace '{}' not found (available: {})",
+                            member_name,
+                            ws.get_workspace_package_names().join(", ")
+                        ),
+                        hint: None,
+                    }
+                    .into());
+                }
+            },
+            _ => vec![PathBuf::from("package.json")],
+        };
+
+        let before = match &options.before {
+            Some(date) => Some(parse_before_date(date)?),
+            None => None,
+        };
+
+        let default_range = options.tag.as_deref().unwrap_or("latest");
+        // Packages given without a version, whose version is asked for with --interactive
+        let choose: HashSet<&str> = if options.interactive && std::io::stdin().is_terminal() {
+            packages
+                .iter()
+                .filter(|pkg_input| !matches!(pkg_input.rfind('@'), Some(idx) if idx > 0))
+                .map(String::as_str)
+                .collect()
+        } else {
+            HashSet::new()
+        };
+        let requested: Vec<(&str, &str)> = packages
+            .iter()
+            .map(|pkg_input| match pkg_input.rfind('@') {
+                Some(idx) if idx > 0 => (&pkg_input[..idx], &pkg_input[idx + 1..]),
+                _ => (pkg_input.as_str(), default_range),
+            })
+            .collect();
+
+        // Packages already declared and installed at a version matching an explicit range
+        // need no registry lookup (a dist-tag, --before, --exact or --tilde always resolves
+        // again, so the saved range is rewritten)
+        let mut requested = requested;
+        if before.is_none() && options.range_style == VersionRangeStyle::Caret {
+            let mut pending = Vec::new();
+            for (name, range) in requested {
+                match self
+                    .installed_as_requested(name, range, &targets, &options)
+                    .await
+                {
+                    Some(version) => output::info(
+                        self.color_mode,
+                        &format!("{}@{} already installed", name, version),
+                    ),
+                    None => pending.push((name, range)),
+                }
+            }
+            if pending.is_empty() {
+                return Ok(());
+            }
+            requested = pending;
+        }
+
+        // Check that every package exists before resolving anything, so a typo in any
+        // of them is reported up front and package.json is left untouched
+        let spinner = self.create_spinner();
+        spinner.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            &format!("\x1b[1mChecking\x1b[0m {} package(s)...", requested.len()),
+        ));
+        let mut lookups: FuturesUnordered<_> = requested
+            .iter()
+            .map(|(name, _)| async move { (*name, self.registry.get_package(name).await) })
+            .collect();
+        let mut failed = Vec::new();
+        while let Some((name, result)) = lookups.next().await {
+            if let Err(e) = result {
+                failed.push((name, e));
+            }
+        }
+        drop(lookups);
+        if !failed.is_empty() {
+            spinner.finish_and_clear();
+            failed.sort_by_key(|(name, _)| *name);
+            if failed.len() == 1 {
+                let (name, e) = failed.remove(0);
+                return Err(e.context(format!("Failed to fetch metadata for {}", name)));
+            }
+            for (name, e) in &failed {
+                output::eprint_line(
+                    self.color_mode,
+                    &format!("{}✗{} {}: {}", colors::RED, colors::RESET, name, e),
+                );
+            }
+            return Err(RpmError::Other {
+                message: format!(
+                    "{} of {} packages could not be fetched",
+                    failed.len(),
+                    requested.len()
+                ),
+                hint: Some("package.json was not changed".to_string()),
+            }
+            .into());
+        }
+
+        // Resolve every package once, the result is reused for all targets
+        let mut added_packages: Vec<(String, String)> = Vec::new();
+        // (name, version, peer dependencies) of the added packages
+        let mut added_peers: Vec<(String, String, BTreeMap<String, String>)> = Vec::new();
 
-            spinner.set_message(format!("\x1b[1mResolving\x1b[0m {}...", name));
+        for (name, range) in requested {
+            spinner.set_message(output::maybe_strip_colors(
+                self.color_mode,
+                &format!("\x1b[1mResolving\x1b[0m {}...", name),
+            ));
             let package = self
                 .registry
                 .get_package(name)
                 .await
                 .with_context(|| format!("Failed to fetch metadata for {}", name))?;
-            let resolved = self
-                .registry
-                .resolve_version(&package, range)
-                .with_context(|| format!("Failed to resolve version for {}", name))?;
 
-            if dev {
-                package_json
-                    .dev_dependencies
-                    .insert(name.to_string(), format!("^{}", resolved.version));
+            if let Some(tag) = &options.tag {
+                if range == tag && !package.dist_tags.contains_key(tag) {
+                    let mut available: Vec<String> = package
+                        .dist_tags
+                        .iter()
+                        .map(|(tag, version)| format!("{} ({})", tag, version))
+                        .collect();
+                    available.sort();
+                    spinner.finish_and_clear();
+                    return Err(RpmError::VersionNotFound {
+                        name: name.to_string(),
+                        requested: tag.clone(),
+                        available,
+                    }
+                    .into());
+                }
+            }
+            let chosen;
+            let range = if choose.contains(name) {
+                let selection = tokio::task::block_in_place(|| {
+                    spinner.suspend(|| select_version(name, &package))
+                })?;
+                match selection {
+                    // A bare version would be read as a caret range
+                    Some(version) => {
+                        chosen = format!("={}", version);
+                        chosen.as_str()
+                    }
+                    // Esc / q cancels the add entirely
+                    None => {
+                        spinner.finish_and_clear();
+                        self.report("\x1b[90mNo version selected\x1b[0m");
+                        return Ok(());
+                    }
+                }
             } else {
-                package_json
-                    .dependencies
-                    .insert(name.to_string(), format!("^{}", resolved.version));
+                range
+            };
+            let resolved = match &before {
+                Some(date) => self.registry.resolve_version_before(&package, range, date),
+                None => self.registry.resolve_version(&package, range),
             }
+            .with_context(|| format!("Failed to resolve version for {}", name))?;
+
             added_packages.push((name.to_string(), resolved.version.clone()));
+            added_peers.push((
+                name.to_string(),
+                resolved.version.clone(),
+                resolved.peer_dependencies.clone(),
+            ));
         }
         spinner.finish_and_clear();
 
+        let auto_dev = if options.auto_dev {
+            self.auto_dev_packages(&added_packages).await?
+        } else {
+            HashSet::new()
+        };
+
+        let conflicts = self.find_peer_conflicts(&added_peers).await;
+        let peer_updates = if conflicts.is_empty() {
+            Vec::new()
+        } else {
+            self.resolve_peer_conflicts(&conflicts).await?
+        };
+
         // Print added packages
         for (name, version) in &added_packages {
-            println!(
+            self.report(&format!(
                 "\x1b[32m+\x1b[0m \x1b[1m{}\x1b[0m@\x1b[90m{}\x1b[0m",
                 name, version
-            );
+            ));
         }
 
-        let new_content = serde_json::to_string_pretty(&package_json)?;
-        fs::write("package.json", new_content).await?;
+        let mut package_json: Option<PackageJson> = None;
+        let mut modified = 0;
+
+        for path in &targets {
+            let content = fs::read_to_string(path).await?;
+            let mut target_json: PackageJson = serde_json::from_str(&content)?;
+            validate_package_json(&target_json, path)?;
+            let mut changed = false;
+
+            for (name, version) in &added_packages {
+                // Packages already declared at the root are shared by every member
+                if options.all_workspaces {
+                    if let Some(ws) = &root_workspace {
+                        if ws.root_package.dependencies.contains_key(name)
+                            || ws.root_package.dev_dependencies.contains_key(name)
+                        {
+                            continue;
+                        }
+                    }
+                }
+
+                let range = options.range_style.range(version);
+                let deps = if options.peer {
+                    &mut target_json.peer_dependencies
+                } else if options.dev || auto_dev.contains(name) {
+                    &mut target_json.dev_dependencies
+                } else {
+                    &mut target_json.dependencies
+                };
+                if deps.get(name) != Some(&range) {
+                    deps.insert(name.clone(), range);
+                    changed = true;
+                }
+            }
+
+            // Peers updated to resolve a conflict stay in the section that declares them
+            for (name, version) in &peer_updates {
+                let range = format!("^{}", version);
+                let deps = if target_json.dev_dependencies.contains_key(name) {
+                    &mut target_json.dev_dependencies
+                } else if target_json.peer_dependencies.contains_key(name) {
+                    &mut target_json.peer_dependencies
+                } else if target_json.dependencies.contains_key(name) || targets.len() == 1 {
+                    &mut target_json.dependencies
+                } else {
+                    continue;
+                };
+                if deps.get(name) != Some(&range) {
+                    deps.insert(name.clone(), range);
+                    changed = true;
+                }
+            }
+
+            if changed {
+                let new_content = serde_json::to_string_pretty(&target_json)?;
+                fs::write(path, new_content).await?;
+                modified += 1;
+            }
+            package_json = Some(target_json);
+        }
+
+        if options.all_workspaces {
+            self.report(&format!(
+                "\x1b[90mUpdated\x1b[0m \x1b[1m{}\x1b[0m \x1b[90mpackage.json file(s)\x1b[0m",
+                modified
+            ));
+        } else if let Some(member_name) = &options.workspace {
+            self.report(&format!(
+                "\x1b[90mAdded to workspace\x1b[0m \x1b[1m{}\x1b[0m \x1b[90m({})\x1b[0m",
+                member_name,
+                targets[0].display()
+            ));
+        }
 
         // Reset and setup progress tracking for dependencies
         self.reset_progress();
         let pb = self.create_install_progress();
-        pb.set_message("\x1b[1mInstalling\x1b[0m dependencies...");
+        pb.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            "\x1b[1mInstalling\x1b[0m dependencies...",
+        ));
         *self.progress_bar.lock().await = Some(pb.clone());
 
-        self.install_deps(&package_json).await?;
+        let lock_owner = match &root_workspace {
+            // Hoist the new packages to the workspace root node_modules
+            Some(ws) => {
+                let added: Vec<(String, String)> = added_packages
+                    .iter()
+                    .map(|(name, version)| (name.clone(), options.range_style.range(version)))
+                    .chain(
+                        peer_updates
+                            .iter()
+                            .map(|(name, version)| (name.clone(), format!("^{}", version))),
+                    )
+                    .collect();
+                self.install_deps_incremental(&ws.root_package, added).await?;
+                ws.root_package.clone()
+            }
+            None => {
+                let package_json = package_json.context("Could not read package.json")?;
+                self.install_deps(&package_json).await?;
+                package_json
+            }
+        };
 
         let installed = self.packages_installed.load(Ordering::Relaxed);
         let cached = self.packages_cached.load(Ordering::Relaxed);
 
         pb.finish_and_clear();
         *self.progress_bar.lock().await = None;
+        self.finish_dots();
 
         // Print summary
         if installed > 0 || cached > 0 {
@@ -981,35 +4062,266 @@ impl Manager {
             if installed > 0 {
                 parts.push(format!("\x1b[32m+{}\x1b[0m installed", installed));
             }
-            if cached > 0 {
-                parts.push(format!("\x1b[33m{}\x1b[0m cached", cached));
+            if cached > 0 {
+                parts.push(format!("\x1b[33m{}\x1b[0m cached", cached));
+            }
+            self.report(&parts.join("  \x1b[90m│\x1b[0m  "));
+        }
+
+        self.run_postinstalls().await?;
+        self.save_lockfile(&lock_owner.name, &lock_owner.version)
+            .await?;
+        if options.global {
+            self.link_global_binaries(&added_packages).await?;
+        }
+        Ok(())
+    }
+
+    /// Link the binaries of globally added packages into ~/.rpm/bin, with a hint when
+    /// that directory is not in PATH
+    async fn link_global_binaries(&self, added: &[(String, String)]) -> Result<()> {
+        let bin_dir = Config::path().with_file_name("bin");
+        fs::create_dir_all(&bin_dir).await?;
+        let node_modules = self.project_dir()?.join("node_modules");
+
+        for (name, _) in added {
+            let package_dir = node_modules.join(name);
+            let Ok(content) = fs::read_to_string(package_dir.join("package.json")).await else {
+                continue;
+            };
+            let Ok(package_json) = serde_json::from_str::<serde_json::Value>(&content) else {
+                continue;
+            };
+            for (bin_name, path) in bin_entries(name, &package_json["bin"]) {
+                let link = bin_dir.join(&bin_name);
+
+                #[cfg(unix)]
+                {
+                    if link.exists() || link.is_symlink() {
+                        fs::remove_file(&link).await?;
+                    }
+                    fs::symlink(package_dir.join(&path), &link).await?;
+                }
+
+                // ~/.rpm/bin is next to ~/.rpm/global
+                #[cfg(windows)]
+                {
+                    let target = windows_bin_path("..\\global\\node_modules", name, &path);
+                    let script = fs::read(package_dir.join(&path)).await.unwrap_or_default();
+                    let program = shim_program(&script, &path);
+                    fs::write(link.with_extension("cmd"), cmd_shim(&target, program.as_deref()))
+                        .await?;
+                    fs::write(link.with_extension("ps1"), ps1_shim(&target, program.as_deref()))
+                        .await?;
+                }
+
+                self.report(&format!(
+                    "\x1b[90mLinked\x1b[0m \x1b[1m{}\x1b[0m \x1b[90m({})\x1b[0m",
+                    bin_name, name
+                ));
+            }
+        }
+
+        let in_path = std::env::var_os("PATH")
+            .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir == bin_dir));
+        if !in_path {
+            output::print_line(
+                self.color_mode,
+                &format!(
+                    "\n\x1b[33m!\x1b[0m {} is not in your PATH, add it to use global binaries:",
+                    bin_dir.display()
+                ),
+            );
+            if cfg!(windows) {
+                println!("  setx PATH \"%PATH%;{}\"", bin_dir.display());
+            } else {
+                println!("  export PATH=\"{}:$PATH\"", bin_dir.display());
+            }
+        }
+        Ok(())
+    }
+
+    /// Names of the added packages to save as devDependencies with --auto-dev, after the
+    /// user confirmed them (always with --yes)
+    async fn auto_dev_packages(&self, added: &[(String, String)]) -> Result<HashSet<String>> {
+        let patterns = Config::dev_patterns()?;
+        let matched: HashSet<String> = added
+            .iter()
+            .filter(|(name, _)| is_dev_package(name, &patterns))
+            .map(|(name, _)| name.clone())
+            .collect();
+        if matched.is_empty() {
+            return Ok(matched);
+        }
+
+        let mut names: Vec<&String> = matched.iter().collect();
+        names.sort();
+        let names: Vec<&str> = names.into_iter().map(String::as_str).collect();
+        output::print_line(
+            self.color_mode,
+            &format!(
+                "\x1b[1mSaving as devDependencies:\x1b[0m {}",
+                names.join(", ")
+            ),
+        );
+        if self.auto_confirm {
+            return Ok(matched);
+        }
+
+        output::print_line(
+            self.color_mode,
+            "\x1b[1mContinue?\x1b[0m \x1b[90m[Y/n]\x1b[0m",
+        );
+        let mut stdin = BufReader::new(tokio::io::stdin());
+        let mut line = String::new();
+        stdin.read_line(&mut line).await?;
+        if line.trim().eq_ignore_ascii_case("n") {
+            self.report("\x1b[90mSaving them as dependencies instead\x1b[0m");
+            return Ok(HashSet::new());
+        }
+        Ok(matched)
+    }
+
+    /// Peer dependencies of the packages being added that the installed versions do not
+    /// satisfy. Peers that are not installed yet, or are being added too, are skipped.
+    async fn find_peer_conflicts(
+        &self,
+        added: &[(String, String, BTreeMap<String, String>)],
+    ) -> Vec<PeerConflict> {
+        let mut conflicts = Vec::new();
+        for (package, version, peers) in added {
+            for (peer, range) in peers {
+                if added.iter().any(|(name, ..)| name == peer) {
+                    continue;
+                }
+                let Some(installed) = self.get_installed_version(peer).await else {
+                    continue;
+                };
+                if version_satisfies(range, &installed) == Some(false) {
+                    conflicts.push(PeerConflict {
+                        package: package.clone(),
+                        version: version.clone(),
+                        peer: peer.clone(),
+                        range: range.clone(),
+                        installed,
+                    });
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Ask how to handle peer conflicts (updating the peers with --yes)
+    /// Returns the peers to update as (name, version)
+    async fn resolve_peer_conflicts(
+        &self,
+        conflicts: &[PeerConflict],
+    ) -> Result<Vec<(String, String)>> {
+        output::print_line(
+            self.color_mode,
+            "\n\x1b[1;33mPeer dependency conflicts:\x1b[0m",
+        );
+        for c in conflicts {
+            output::print_line(
+                self.color_mode,
+                &format!(
+                    "  \x1b[90m-\x1b[0m \x1b[1m{}@{}\x1b[0m requires \x1b[1m{}@{}\x1b[0m \
+                 but you have \x1b[1m{}@{}\x1b[0m installed",
+                    c.package, c.version, c.peer, c.range, c.peer, c.installed
+                ),
+            );
+        }
+
+        let choice = if self.auto_confirm {
+            "1".to_string()
+        } else {
+            output::print_line(
+                self.color_mode,
+                "\n\x1b[1mHow do you want to continue?\x1b[0m",
+            );
+            println!("  1) Update the installed packages to satisfy the peer dependencies");
+            println!("  2) Keep the installed versions (--legacy-peer-deps)");
+            println!("  3) Abort");
+            output::print_line(self.color_mode, "\x1b[90m[1/2/3]\x1b[0m");
+
+            let mut stdin = BufReader::new(tokio::io::stdin());
+            let mut line = String::new();
+            stdin.read_line(&mut line).await?;
+            line.trim().to_string()
+        };
+
+        match choice.as_str() {
+            "1" => {
+                // Resolve every conflict before touching anything, so a failure leaves
+                // the lockfile and node_modules as they were
+                let mut updates: Vec<(String, String, String)> = Vec::new();
+                for c in conflicts {
+                    // Several added packages may share a peer, the first range wins
+                    if updates.iter().any(|(name, _, _)| *name == c.peer) {
+                        continue;
+                    }
+                    let package = self.registry.get_package(&c.peer).await?;
+                    let resolved = self
+                        .registry
+                        .resolve_version(&package, &c.range)
+                        .with_context(|| format!("Failed to resolve {}@{}", c.peer, c.range))?;
+                    updates.push((c.peer.clone(), c.installed.clone(), resolved.version.clone()));
+                }
+
+                // Drop the installed copies so the new versions get installed
+                {
+                    let mut lockfile = self.lockfile.lock().await;
+                    for (peer, _, _) in &updates {
+                        lockfile.packages.remove(&format!("node_modules/{}", peer));
+                    }
+                }
+                for (peer, installed, version) in &updates {
+                    self.report(&format!(
+                        "\x1b[33m↑\x1b[0m \x1b[1m{}\x1b[0m \x1b[90m{} →\x1b[0m {}",
+                        peer, installed, version
+                    ));
+                    let peer_dir = self.project_dir()?.join("node_modules").join(peer);
+                    let _ = fs::remove_dir_all(peer_dir).await;
+                }
+                let updates = updates
+                    .into_iter()
+                    .map(|(peer, _, version)| (peer, version))
+                    .collect();
+                Ok(updates)
+            }
+            "2" => {
+                self.install_peers.store(false, Ordering::Relaxed);
+                self.report("\x1b[90mKeeping the installed peer versions\x1b[0m");
+                Ok(Vec::new())
+            }
+            _ => Err(RpmError::Other {
+                message: "Aborted because of peer dependency conflicts".to_string(),
+                hint: Some("package.json was not changed".to_string()),
             }
-            println!("{}", parts.join("  \x1b[90m│\x1b[0m  "));
+            .into()),
         }
-
-        self.run_postinstalls().await?;
-        self.save_lockfile(&package_json.name, &package_json.version)
-            .await?;
-        Ok(())
     }
 
     pub async fn remove_packages(&self, packages: Vec<String>) -> Result<()> {
         self.load_lockfile().await?;
-        let package_json_content = fs::read_to_string("package.json").await?;
+        let package_json_content =
+            fs::read_to_string(self.project_dir()?.join("package.json")).await?;
         let mut package_json: PackageJson = serde_json::from_str(&package_json_content)?;
+        validate_package_json(&package_json, &self.project_dir()?.join("package.json"))?;
 
         let mut removed_any = false;
 
         for name in &packages {
             let was_dep = package_json.dependencies.remove(name).is_some();
             let was_dev_dep = package_json.dev_dependencies.remove(name).is_some();
+            let was_peer_dep = package_json.peer_dependencies.remove(name).is_some();
 
-            if was_dep || was_dev_dep {
+            if was_dep || was_dev_dep || was_peer_dep {
                 removed_any = true;
-                println!("\x1b[31m-\x1b[0m \x1b[1m{}\x1b[0m", name);
+                self.report(&format!("\x1b[31m-\x1b[0m \x1b[1m{}\x1b[0m", name));
 
                 // Remove from node_modules
-                let pkg_path = PathBuf::from("node_modules").join(name);
+                let pkg_path = self.project_dir()?.join("node_modules").join(name);
                 if pkg_path.exists() {
                     fs::remove_dir_all(&pkg_path).await?;
                 }
@@ -1022,7 +4334,7 @@ impl Manager {
                 }
 
                 // Remove binary links
-                let bin_dir = PathBuf::from("node_modules").join(".bin");
+                let bin_dir = self.project_dir()?.join("node_modules").join(".bin");
                 if bin_dir.exists() {
                     if let Ok(mut entries) = fs::read_dir(&bin_dir).await {
                         while let Ok(Some(entry)) = entries.next_entry().await {
@@ -1042,17 +4354,17 @@ impl Manager {
                     }
                 }
             } else {
-                println!(
+                self.report(&format!(
                     "\x1b[33mwarn:\x1b[0m \x1b[1m{}\x1b[0m is not installed",
                     name
-                );
+                ));
             }
         }
 
         if removed_any {
             // Save updated package.json
             let new_content = serde_json::to_string_pretty(&package_json)?;
-            fs::write("package.json", new_content).await?;
+            fs::write(self.project_dir()?.join("package.json"), new_content).await?;
 
             // Save updated lockfile
             self.save_lockfile(&package_json.name, &package_json.version)
@@ -1062,7 +4374,12 @@ impl Manager {
         Ok(())
     }
 
-    pub async fn exec_package(&self, package: &str, args: Vec<String>) -> Result<()> {
+    pub async fn exec_package(
+        &self,
+        package: &str,
+        args: Vec<String>,
+        no_cache: bool,
+    ) -> Result<()> {
         // Parse package name and version
         let (name, version_range) = if let Some(idx) = package.rfind('@') {
             if idx == 0 {
@@ -1082,15 +4399,21 @@ impl Manager {
         };
 
         // First, check if binary exists locally in node_modules/.bin
-        let local_bin = PathBuf::from("node_modules").join(".bin").join(bin_name);
+        let local_bin = self.project_dir()?.join("node_modules").join(".bin").join(bin_name);
         if local_bin.exists() {
-            println!("\x1b[90mUsing local\x1b[0m \x1b[1m{}\x1b[0m\n", bin_name);
+            output::print_line(
+                self.color_mode,
+                &format!("\x1b[90mUsing local\x1b[0m \x1b[1m{}\x1b[0m\n", bin_name),
+            );
             return self.run_binary(&local_bin, args).await;
         }
 
         // Not found locally, need to fetch and run
         let spinner = self.create_spinner();
-        spinner.set_message(format!("\x1b[1mFetching\x1b[0m {}...", name));
+        spinner.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            &format!("\x1b[1mFetching\x1b[0m {}...", name),
+        ));
 
         // Fetch package metadata
         let pkg = self
@@ -1103,27 +4426,39 @@ impl Manager {
             .resolve_version(&pkg, version_range)
             .with_context(|| format!("Failed to resolve version for {}", name))?;
 
-        spinner.set_message(format!(
-            "\x1b[1mInstalling\x1b[0m {}@{}...",
-            name, resolved.version
+        spinner.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            &format!("\x1b[1mInstalling\x1b[0m {}@{}...", name, resolved.version),
         ));
 
         // Install to a temporary location within the cache
-        let temp_dir = self.installer.cache_dir.join("_npx").join(format!(
-            "{}@{}",
-            name.replace('/', "+"),
-            resolved.version
-        ));
+        let exec_cache = self.installer.cache_dir.join("_npx");
+        let temp_dir = exec_cache.join(format!("{}@{}", name.replace('/', "+"), resolved.version));
+        self.clean_exec_cache(&exec_cache, &temp_dir).await;
+
+        // --no-cache: rebuild this package's install, the store is left alone
+        if no_cache && temp_dir.exists() {
+            fs::remove_dir_all(&temp_dir).await?;
+        }
+        fs::create_dir_all(&temp_dir).await?;
+        Installer::touch_last_used(&temp_dir).await;
 
         // Install the main package
         self.installer
-            .install_package(name, &resolved.version, &resolved.dist.tarball, &temp_dir)
+            .install_package(
+                name,
+                &resolved.version,
+                &resolved.dist.tarball,
+                resolved.dist.integrity.as_deref(),
+                self.registry.tarball_token(name, &resolved.dist.tarball),
+                &temp_dir,
+            )
             .await?;
 
         // Install dependencies recursively
-        spinner.set_message(format!(
-            "\x1b[1mInstalling\x1b[0m dependencies for {}...",
-            name
+        spinner.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            &format!("\x1b[1mInstalling\x1b[0m dependencies for {}...", name),
         ));
 
         // Collect regular dependencies
@@ -1157,6 +4492,8 @@ impl Manager {
                             &dep_name,
                             &dep_resolved.version,
                             &dep_resolved.dist.tarball,
+                            dep_resolved.dist.integrity.as_deref(),
+                            self.registry.tarball_token(&dep_name, &dep_resolved.dist.tarball),
                             &temp_dir,
                         )
                         .await;
@@ -1205,16 +4542,39 @@ impl Manager {
             anyhow::bail!("Binary not found at {}", bin_path.display());
         }
 
-        println!(
-            "\x1b[90mExecuting\x1b[0m \x1b[1m{}@{}\x1b[0m\n",
-            name, resolved.version
+        output::print_line(
+            self.color_mode,
+            &format!(
+                "\x1b[90mExecuting\x1b[0m \x1b[1m{}@{}\x1b[0m\n",
+                name, resolved.version
+            ),
         );
 
         self.run_binary(&bin_path, args).await
     }
 
+    /// Remove `rpm x` installs not used for `exec-cache-days` days, except `current`
+    async fn clean_exec_cache(&self, exec_cache: &Path, current: &Path) {
+        let Ok(mut entries) = fs::read_dir(exec_cache).await else {
+            return;
+        };
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let max_age = self.config.exec_cache_days().saturating_mul(24 * 60 * 60);
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path == current || !path.is_dir() {
+                continue;
+            }
+            if Installer::last_used(&path).is_some_and(|used| now.saturating_sub(used) > max_age) {
+                let _ = Installer::remove_entry(&path).await;
+            }
+        }
+    }
+
     async fn run_binary(&self, bin_path: &PathBuf, args: Vec<String>) -> Result<()> {
-        let current_dir = std::env::current_dir()?;
+        let current_dir = self.project_dir()?;
         let local_bin_path = current_dir.join("node_modules").join(".bin");
         let path_env = std::env::var("PATH").unwrap_or_default();
         let new_path = format!("{}:{}", local_bin_path.display(), path_env);
@@ -1223,6 +4583,8 @@ impl Manager {
             .arg(bin_path)
             .args(&args)
             .env("PATH", &new_path)
+            .envs(self.node_env())
+            .envs(self.node_options_env())
             .status()
             .await?;
 
@@ -1233,8 +4595,15 @@ impl Manager {
         Ok(())
     }
 
-    pub async fn run_script(&self, script_name: &str, args: Vec<String>) -> Result<()> {
-        let package_json_content = fs::read_to_string("package.json").await?;
+    /// Run a script of package.json; `silent` discards its output and only reports a failure
+    pub async fn run_script(
+        &self,
+        script_name: &str,
+        args: Vec<String>,
+        silent: bool,
+    ) -> Result<()> {
+        let package_json_content =
+            fs::read_to_string(self.project_dir()?.join("package.json")).await?;
         let package_json: PackageJson = serde_json::from_str(&package_json_content)?;
 
         let script = match package_json.scripts.get(script_name) {
@@ -1249,14 +4618,19 @@ impl Manager {
             }
         };
 
-        println!(
-            "{}${} {}{}{}\n",
-            colors::GRAY,
-            colors::RESET,
-            colors::BOLD,
-            script,
-            colors::RESET
-        );
+        if !silent {
+            output::print_line(
+                self.color_mode,
+                &format!(
+                    "{}${} {}{}{}\n",
+                    colors::GRAY,
+                    colors::RESET,
+                    colors::BOLD,
+                    script,
+                    colors::RESET
+                ),
+            );
+        }
 
         // Build the full command with args
         let full_command = if args.is_empty() {
@@ -1266,20 +4640,53 @@ impl Manager {
         };
 
         // Add node_modules/.bin to PATH
-        let current_dir = std::env::current_dir()?;
+        let current_dir = self.project_dir()?;
         let bin_path = current_dir.join("node_modules").join(".bin");
         let path_env = std::env::var("PATH").unwrap_or_default();
         let new_path = format!("{}:{}", bin_path.display(), path_env);
 
-        let status = Command::new("sh")
-            .arg("-c")
-            .arg(&full_command)
+        let mut command = shell_command(&self.config.script_shell(), &full_command);
+        command
             .env("PATH", &new_path)
-            .status()
-            .await?;
+            .envs(self.env_file.iter().cloned())
+            .envs(self.node_env())
+            .envs(self.node_options_env())
+            .envs(package_env(&package_json, &current_dir.join("package.json")));
+        if silent {
+            command
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null());
+        }
+        // Node.js prints where its debugger listens on stderr, which is scanned for it
+        let inspecting = !silent
+            && self.node_options.as_deref().is_some_and(|flags| {
+                flags.split_whitespace().any(|flag| flag.starts_with("--inspect"))
+            });
+        let status = if inspecting {
+            let mut child = command.stderr(std::process::Stdio::piped()).spawn()?;
+            if let Some(stderr) = child.stderr.take() {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    eprintln!("{}", line);
+                    if let Some(url) = devtools_url(&line) {
+                        output::info(self.color_mode, &format!("Open {} in Chrome to debug", url));
+                    }
+                }
+            }
+            child.wait().await?
+        } else {
+            command.status().await?
+        };
 
         if !status.success() {
-            std::process::exit(status.code().unwrap_or(1));
+            let code = status.code().unwrap_or(1);
+            if silent {
+                output::error(
+                    self.color_mode,
+                    &format!("Script '{}' exited with code {}", script_name, code),
+                );
+            }
+            std::process::exit(code);
         }
 
         Ok(())
@@ -1290,26 +4697,38 @@ impl Manager {
         &self,
         script_name: &str,
         args: Vec<String>,
-        filter: Option<&str>,
+        options: RunWorkspacesOptions,
     ) -> Result<()> {
-        let root = std::env::current_dir()?;
+        let root = self.project_dir()?;
         let workspace = Workspace::discover(&root)
             .await?
             .context("Not in a workspace. Use 'rpm run' without --workspaces flag.")?;
 
+        // The root runs alongside the members as if it were one
+        let root_member = WorkspaceMember {
+            name: workspace.root_package.name.clone(),
+            path: workspace.root.clone(),
+            package_json: workspace.root_package.clone(),
+        };
+        let root_script = root_member
+            .package_json
+            .scripts
+            .get(script_name)
+            .filter(|_| options.include_root);
+
         // Find all workspaces with this script
         let scripts = workspace.get_scripts(script_name);
 
-        if scripts.is_empty() {
-            println!(
-                "\x1b[33mNo workspaces have script '{}'\x1b[0m",
-                script_name
+        if scripts.is_empty() && root_script.is_none() {
+            output::print_line(
+                self.color_mode,
+                &format!("\x1b[33mNo workspaces have script '{}'\x1b[0m", script_name),
             );
             return Ok(());
         }
 
         // Filter workspaces if specified
-        let scripts_to_run: Vec<_> = if let Some(filter_pattern) = filter {
+        let scripts_to_run: Vec<_> = if let Some(filter_pattern) = options.filter.as_deref() {
             scripts
                 .into_iter()
                 .filter(|(m, _)| {
@@ -1319,35 +4738,62 @@ impl Manager {
         } else {
             scripts
         };
+        let scripts_to_run: Vec<_> = root_script
+            .map(|script| (&root_member, script))
+            .into_iter()
+            .chain(scripts_to_run)
+            .collect();
 
         if scripts_to_run.is_empty() {
-            println!(
-                "\x1b[33mNo matching workspaces have script '{}'\x1b[0m",
-                script_name
+            output::print_line(
+                self.color_mode,
+                &format!(
+                    "\x1b[33mNo matching workspaces have script '{}'\x1b[0m",
+                    script_name
+                ),
             );
             return Ok(());
         }
 
-        println!(
-            "\x1b[1;36mRunning '{}' in {} workspace(s) (parallel)\x1b[0m\n",
-            script_name,
-            scripts_to_run.len()
+        output::print_line(
+            self.color_mode,
+            &format!(
+                "\x1b[1;36mRunning '{}' in {} workspace(s) ({})\x1b[0m\n",
+                script_name,
+                scripts_to_run.len(),
+                if options.serial { "serial" } else { "parallel" }
+            ),
         );
 
         let root_bin_path = workspace.root.join("node_modules").join(".bin");
         let path_env = std::env::var("PATH").unwrap_or_default();
         let failed = Arc::new(std::sync::atomic::AtomicBool::new(false));
         let multi_progress = &self.multi_progress;
-
-        // Execute all scripts in parallel
-        let mut tasks = FuturesUnordered::new();
+        let shell = self.config.script_shell();
+        let mut node_env = self.env_file.clone();
+        node_env.extend(self.node_env());
+        node_env.extend(self.node_options_env());
+        let stream = options.stream;
+        // Process ids of the scripts still running, so --bail can stop them
+        let running: Arc<DashMap<String, u32>> = Arc::new(DashMap::new());
+        let bail = options.bail;
+        let concurrency = options
+            .concurrency
+            .unwrap_or(scripts_to_run.len().min(DEFAULT_WORKSPACE_CONCURRENCY));
+        let semaphore = Arc::new(Semaphore::new(concurrency));
+
+        let mut tasks = Vec::new();
         
-        for (member, script) in scripts_to_run {
+        for (index, (member, script)) in scripts_to_run.into_iter().enumerate() {
             let root_bin_path = root_bin_path.clone();
             let path_env = path_env.clone();
             let args = args.clone();
             let failed = failed.clone();
             let workspace_root = workspace.root.clone();
+            let shell = &shell;
+            let node_env = &node_env;
+            let running = running.clone();
+            let semaphore = semaphore.clone();
             
             tasks.push(async move {
                 let relative_path = member
@@ -1355,6 +4801,12 @@ impl Manager {
                     .strip_prefix(&workspace_root)
                     .unwrap_or(&member.path);
 
+                // Closed by --bail, scripts still waiting for their turn never start
+                let Ok(_permit) = semaphore.acquire().await else {
+                    let path = relative_path.to_path_buf();
+                    return (member.name.clone(), path, script, false, String::new(), String::new());
+                };
+
                 // Build the full command with args
                 let full_command = if args.is_empty() {
                     script.clone()
@@ -1371,22 +4823,52 @@ impl Manager {
                     path_env
                 );
 
-                let status = Command::new("sh")
-                    .arg("-c")
-                    .arg(&full_command)
+                let mut command = shell_command(shell, &full_command);
+                command
                     .current_dir(&member.path)
                     .env("PATH", &new_path)
+                    .envs(node_env.iter().cloned())
+                    .envs(package_env(&member.package_json, &member.path.join("package.json")))
                     .stdout(std::process::Stdio::piped())
-                    .stderr(std::process::Stdio::piped())
-                    .output()
-                    .await;
-
-                let (success, output, stderr) = match status {
-                    Ok(output) => (
-                        output.status.success(),
-                        String::from_utf8_lossy(&output.stdout).to_string(),
-                        String::from_utf8_lossy(&output.stderr).to_string(),
-                    ),
+                    .stderr(std::process::Stdio::piped());
+                // Own process group, so stopping a script also stops what its shell started
+                #[cfg(unix)]
+                if bail {
+                    command.process_group(0);
+                }
+
+                let (success, output, stderr) = match command.spawn() {
+                    Ok(mut child) => {
+                        if let Some(pid) = child.id() {
+                            running.insert(member.name.clone(), pid);
+                        }
+                        let result = if stream {
+                            let color = WORKSPACE_COLORS[index % WORKSPACE_COLORS.len()];
+                            let prefix = output::maybe_strip_colors(
+                                self.color_mode,
+                                &format!("{}[{}]{}", color, member.name, colors::RESET),
+                            );
+                            tokio::join!(
+                                stream_lines(child.stdout.take(), &prefix, multi_progress),
+                                stream_lines(child.stderr.take(), &prefix, multi_progress)
+                            );
+                            match child.wait().await {
+                                Ok(status) => (status.success(), String::new(), String::new()),
+                                Err(e) => (false, String::new(), e.to_string()),
+                            }
+                        } else {
+                            match child.wait_with_output().await {
+                                Ok(output) => (
+                                    output.status.success(),
+                                    String::from_utf8_lossy(&output.stdout).to_string(),
+                                    String::from_utf8_lossy(&output.stderr).to_string(),
+                                ),
+                                Err(e) => (false, String::new(), e.to_string()),
+                            }
+                        };
+                        running.remove(&member.name);
+                        result
+                    }
                     Err(e) => (false, String::new(), e.to_string()),
                 };
 
@@ -1398,15 +4880,31 @@ impl Manager {
             });
         }
 
-        // Collect results and print them as they complete
-        while let Some((name, relative_path, script, success, output, stderr)) = tasks.next().await {
-            let _ = multi_progress.println(format!(
-                "\x1b[1;36m{}\x1b[0m \x1b[90m({})\x1b[0m",
-                name,
-                relative_path.display()
-            ));
-            let _ = multi_progress.println(format!("\x1b[90m$\x1b[0m {}", script));
-            
+        // Print a result as soon as its workspace completes
+        let print_result = |(name, relative_path, script, success, output, stderr): (
+            String,
+            PathBuf,
+            &String,
+            bool,
+            String,
+            String,
+        )| {
+            // Streamed output was already printed with its workspace prefix
+            if !stream {
+                let _ = multi_progress.println(output::maybe_strip_colors(
+                    self.color_mode,
+                    &format!(
+                        "\x1b[1;36m{}\x1b[0m \x1b[90m({})\x1b[0m",
+                        name,
+                        relative_path.display()
+                    ),
+                ));
+                let _ = multi_progress.println(output::maybe_strip_colors(
+                    self.color_mode,
+                    &format!("\x1b[90m$\x1b[0m {}", script),
+                ));
+            }
+
             if !output.is_empty() {
                 for line in output.lines() {
                     let _ = multi_progress.println(format!("  {}", line));
@@ -1414,20 +4912,56 @@ impl Manager {
             }
             if !stderr.is_empty() {
                 for line in stderr.lines() {
-                    let _ = multi_progress.println(format!("  \x1b[90m{}\x1b[0m", line));
+                    let _ = multi_progress.println(output::maybe_strip_colors(
+                        self.color_mode,
+                        &format!("  \x1b[90m{}\x1b[0m", line),
+                    ));
                 }
             }
 
             if !success {
-                let _ = multi_progress.println(format!(
-                    "\x1b[31m✗\x1b[0m \x1b[1m{}\x1b[0m failed\n",
-                    name
+                let _ = multi_progress.println(output::maybe_strip_colors(
+                    self.color_mode,
+                    &format!("\x1b[31m✗\x1b[0m \x1b[1m{}\x1b[0m failed\n", name),
                 ));
             } else {
-                let _ = multi_progress.println(format!("\x1b[32m✓\x1b[0m \x1b[1m{}\x1b[0m completed\n", name));
+                let _ = multi_progress.println(output::maybe_strip_colors(
+                    self.color_mode,
+                    &format!("\x1b[32m✓\x1b[0m \x1b[1m{}\x1b[0m completed\n", name),
+                ));
+            }
+            success
+        };
+
+        if options.serial {
+            // One workspace at a time, stopping at the first failure unless asked not to
+            for task in tasks {
+                if !print_result(task.await) && !options.continue_on_error {
+                    break;
+                }
+            }
+        } else {
+            // Execute all scripts in parallel; without --bail every workspace runs to completion
+            let mut tasks: FuturesUnordered<_> = tasks.into_iter().collect();
+            while let Some(result) = tasks.next().await {
+                let name = result.0.clone();
+                if print_result(result) || !options.bail {
+                    continue;
+                }
+
+                semaphore.close();
+                let aborted = terminate_running(&running);
+                output::error(
+                    self.color_mode,
+                    &format!("{} failed, aborted {} running workspace(s)", name, aborted),
+                );
+                // Wait for the stopped scripts to exit, their output is not shown
+                while tasks.next().await.is_some() {}
+                break;
             }
         }
 
+        // Any failure makes the exit code non-zero, with or without --continue-on-error
         if failed.load(Ordering::Relaxed) {
             std::process::exit(1);
         }
@@ -1437,18 +4971,174 @@ impl Manager {
 
     /// List all workspaces
     pub async fn list_workspaces(&self) -> Result<()> {
-        let root = std::env::current_dir()?;
+        let root = self.project_dir()?;
+        let workspace = Workspace::discover(&root)
+            .await?
+            .context("Not in a workspace root")?;
+
+        workspace.print_info(self.color_mode);
+        Ok(())
+    }
+
+    /// Bump the version of a workspace member and update the ranges that other
+    /// members declare for it
+    pub async fn workspace_version(&self, package: &str, bump: &str) -> Result<()> {
+        let root = self.project_dir()?;
+        let workspace = Workspace::discover(&root)
+            .await?
+            .context("Not in a workspace root")?;
+        self.bump_member(&workspace, package, bump).await?;
+        Ok(())
+    }
+
+    /// Bump every member with commits since the last git tag, committing and tagging
+    /// each one as "<name>@<version>" (`rpm workspaces version --changed`)
+    pub async fn workspace_version_changed(&self, bump: &str) -> Result<()> {
+        let root = self.project_dir()?;
         let workspace = Workspace::discover(&root)
             .await?
             .context("Not in a workspace root")?;
 
-        workspace.print_info();
+        let top_level = PathBuf::from(git(&root, &["rev-parse", "--show-toplevel"]).await?);
+        // Without a tag every member counts as changed
+        let changed_files = match git(&root, &["describe", "--tags", "--abbrev=0"]).await {
+            Ok(tag) => {
+                let range = format!("{}..HEAD", tag);
+                let files = git(&root, &["diff", "--name-only", &range]).await?;
+                Some(files.lines().map(|file| top_level.join(file)).collect::<Vec<_>>())
+            }
+            Err(_) => None,
+        };
+
+        let changed: Vec<String> = workspace
+            .members
+            .iter()
+            .filter(|member| {
+                let Some(files) = &changed_files else {
+                    return true;
+                };
+                let path = member.path.canonicalize().unwrap_or_else(|_| member.path.clone());
+                files.iter().any(|file| file.starts_with(&path))
+            })
+            .map(|member| member.name.clone())
+            .collect();
+        if changed.is_empty() {
+            output::print_line(
+                self.color_mode,
+                "\x1b[90mNo workspace packages changed since the last tag\x1b[0m",
+            );
+            return Ok(());
+        }
+
+        for name in &changed {
+            // Earlier bumps rewrote package.json files, so start from the current ones
+            let workspace = Workspace::discover(&root)
+                .await?
+                .context("Not in a workspace root")?;
+            let (new_version, paths) = self.bump_member(&workspace, name, bump).await?;
+            let tag = format!("{}@{}", name, new_version);
+            let paths: Vec<String> = paths
+                .iter()
+                .map(|path| path.to_string_lossy().to_string())
+                .collect();
+            let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+            git(&root, &[&["add", "--"], paths.as_slice()].concat()).await?;
+            // Only the bumped files, not whatever else is staged
+            git(&root, &[&["commit", "-m", &tag, "--"], paths.as_slice()].concat()).await?;
+            git(&root, &["tag", &tag]).await?;
+            output::print_line(
+                self.color_mode,
+                &format!("  \x1b[90mcommitted and tagged\x1b[0m {}", tag),
+            );
+        }
         Ok(())
     }
 
+    /// Bump a member's version and the ranges of the members depending on it, returns the
+    /// new version and the package.json files written
+    async fn bump_member(
+        &self,
+        workspace: &Workspace,
+        package: &str,
+        bump: &str,
+    ) -> Result<(String, Vec<PathBuf>)> {
+        let member = workspace.find_member(package).ok_or_else(|| RpmError::WorkspaceError {
+            message: format!("'{}' is not a workspace package", package),
+            hint: None,
+        })?;
+        let old_version = member.package_json.version.clone();
+        let new_version = Workspace::bump_version(&old_version, bump).ok_or_else(|| {
+            RpmError::Other {
+                message: format!("Cannot bump {} version {} with '{}'", package, old_version, bump),
+                hint: Some("Use major, minor, patch or an explicit version".to_string()),
+            }
+        })?;
+
+        // Collect every modified package.json before writing any of them. They are edited
+        // as JSON values, so fields PackageJson does not model are kept.
+        let mut updated: Vec<(PathBuf, serde_json::Value)> = Vec::new();
+        let mut target = read_json_file(&member.path.join("package.json")).await?;
+        target["version"] = serde_json::Value::String(new_version.clone());
+        updated.push((member.path.clone(), target));
+
+        let mut dependents = Vec::new();
+        for other in workspace.members.iter().filter(|m| m.name != package) {
+            let mut package_json = read_json_file(&other.path.join("package.json")).await?;
+            let mut changed = false;
+            for field in ["dependencies", "devDependencies"] {
+                let Some(range) = package_json.get_mut(field).and_then(|deps| deps.get_mut(package))
+                else {
+                    continue;
+                };
+                if let Some(bumped) = range
+                    .as_str()
+                    .and_then(|range| Workspace::bump_range(range, &new_version))
+                {
+                    *range = serde_json::Value::String(bumped);
+                    changed = true;
+                }
+            }
+            if changed {
+                dependents.push(other.name.clone());
+                updated.push((other.path.clone(), package_json));
+            }
+        }
+
+        // Write to temporary files first and rename them all once every write succeeded
+        let mut staged = Vec::new();
+        for (path, package_json) in &updated {
+            let temp_path = path.join("package.json.rpm-tmp");
+            let content = format!("{}\n", serde_json::to_string_pretty(package_json)?);
+            fs::write(&temp_path, content).await?;
+            staged.push((temp_path, path.join("package.json")));
+        }
+        let mut written = Vec::new();
+        for (temp_path, path) in staged {
+            fs::rename(&temp_path, &path).await?;
+            written.push(path);
+        }
+
+        output::print_line(
+            self.color_mode,
+            &format!(
+                "\x1b[32m✓\x1b[0m \x1b[1m{}\x1b[0m \x1b[90m{}\x1b[0m → \x1b[32m{}\x1b[0m",
+                package, old_version, new_version
+            ),
+        );
+        for name in &dependents {
+            output::print_line(
+                self.color_mode,
+                &format!("  \x1b[36m↑\x1b[0m updated range in \x1b[1m{}\x1b[0m", name),
+            );
+        }
+
+        Ok((new_version, written))
+    }
+
     /// Check if a package in node_modules matches what's expected in the lockfile
     async fn is_package_up_to_date(&self, name: &str, expected_version: &str) -> bool {
-        let pkg_json_path = std::env::current_dir()
+        let pkg_json_path = self
+            .project_dir()
             .ok()
             .map(|p| p.join("node_modules").join(name).join("package.json"));
         
@@ -1462,17 +5152,83 @@ impl Manager {
         false
     }
 
+    /// Remove packages of `root`/node_modules whose package.json is missing, cannot be
+    /// parsed or has another version than rpm-lock.json, so the install puts them back.
+    /// Their store entries are removed as well when broken (see --recover).
+    async fn remove_broken_packages(&self, root: &Path) -> Result<()> {
+        let lock = match fs::read_to_string(root.join("rpm-lock.json")).await {
+            Ok(content) => LockFile::parse(&content).unwrap_or_else(|_| LockFile::empty()),
+            Err(_) => LockFile::empty(),
+        };
+        let node_modules = root.join("node_modules");
+
+        let mut removed = 0;
+        for path in scan_node_modules(&node_modules).await {
+            // Local workspace links are not installed packages
+            let store_link = self.installer.is_store_link(&path);
+            if path.is_symlink() && !store_link {
+                continue;
+            }
+            let name = path
+                .strip_prefix(&node_modules)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .replace('\\', "/");
+
+            let installed = fs::read_to_string(path.join("package.json"))
+                .await
+                .ok()
+                .and_then(|content| serde_json::from_str::<PackageJson>(&content).ok());
+            let problem = match (&installed, lock.packages.get(&format!("node_modules/{}", name))) {
+                (None, _) => "missing or unreadable package.json".to_string(),
+                (Some(pkg), Some(entry)) if pkg.version != entry.version => {
+                    format!("version {} instead of {}", pkg.version, entry.version)
+                }
+                _ => continue,
+            };
+
+            // Hard links share the store's files, so a broken package may mean a broken
+            // store entry, which would otherwise be linked again
+            if installed.is_none() {
+                let entry = match lock.packages.get(&format!("node_modules/{}", name)) {
+                    _ if store_link => fs::read_link(&path).await.ok(),
+                    Some(entry) => Some(self.installer.get_cache_path(&name, &entry.version)),
+                    None => None,
+                };
+                if let Some(entry) = entry {
+                    let broken = std::fs::read_to_string(entry.join("package.json"))
+                        .ok()
+                        .and_then(|c| serde_json::from_str::<PackageJson>(&c).ok())
+                        .is_none();
+                    if broken {
+                        let _ = fs::remove_dir_all(&entry).await;
+                    }
+                }
+            }
+            if path.is_symlink() {
+                fs::remove_file(&path).await?;
+            } else {
+                fs::remove_dir_all(&path).await?;
+            }
+            output::warning(
+                self.color_mode,
+                &format!("removed {} ({}), it will be reinstalled", name, problem),
+            );
+            removed += 1;
+        }
+
+        if removed == 0 {
+            self.report("\x1b[90mNo broken packages in node_modules\x1b[0m");
+        }
+        Ok(())
+    }
+
     /// Compute which packages need to be installed (incremental install optimization)
     async fn compute_packages_to_install(&self, package_json: &PackageJson) -> Vec<(String, String)> {
         let lockfile = self.lockfile.lock().await;
         
         // Collect all declared dependencies
-        let all_deps: Vec<(String, String)> = package_json
-            .dependencies
-            .iter()
-            .chain(package_json.dev_dependencies.iter())
-            .map(|(name, version)| (name.clone(), version.clone()))
-            .collect();
+        let all_deps = self.root_dependencies(package_json);
         
         drop(lockfile);
         
@@ -1502,56 +5258,205 @@ impl Manager {
         }
         
         if up_to_date_count > 0 {
-            let _ = self.multi_progress.println(format!(
-                "\x1b[90m{} packages already up-to-date\x1b[0m",
-                up_to_date_count
+            let _ = self.multi_progress.println(output::maybe_strip_colors(
+                self.color_mode,
+                &format!(
+                    "\x1b[90m{} packages already up-to-date\x1b[0m",
+                    up_to_date_count
+                ),
             ));
         }
         
         packages_to_install
     }
 
-    pub async fn install(&self) -> Result<()> {
-        let root = std::env::current_dir()?;
-        
+    pub async fn install(&self, options: InstallOptions) -> Result<()> {
+        let started = std::time::Instant::now();
+        let (timing_budget, strict_timing) = (options.timing_budget, options.strict_timing);
+        let graph_output = options.graph_output.clone();
+        let project_root = self.project_dir()?;
+        if self.prefix.is_some() {
+            fs::create_dir_all(&project_root)
+                .await
+                .with_context(|| format!("Failed to use --prefix {}", project_root.display()))?;
+        }
+        let report_unused_deps = options.report_unused_deps;
+        let source_glob = match &options.source_glob {
+            Some(pattern) => Some(glob::Pattern::new(pattern).map_err(|e| RpmError::Other {
+                message: format!("Invalid --source-glob {}: {}", pattern, e),
+                hint: Some("Use a glob such as \"src/**/*.ts\"".to_string()),
+            })?),
+            None => None,
+        };
+
+        // Inside a workspace member, install the whole workspace from its root
+        let workspace = self.install_workspace_of(&project_root).await?;
+        let manager = match &workspace {
+            Some(workspace) if workspace.root != project_root => {
+                self.report(&format!(
+                    "\x1b[90mInstalling from workspace root\x1b[0m {}",
+                    workspace.root.display()
+                ));
+                self.clone().with_prefix(Some(workspace.root.clone()))
+            }
+            _ => self.clone(),
+        };
+        tokio::select! {
+            result = manager.install_project(options, workspace) => result?,
+            _ = shutdown_signal() => {
+                // Dropping the install cancels the downloads still running, postinstall
+                // scripts have their own process groups and are stopped explicitly
+                terminate_running(&self.running_postinstalls);
+                let _ = self.multi_progress.clear();
+                if self.lockfile.lock().await.packages.is_empty() {
+                    output::warning(self.color_mode, "Interrupted.");
+                } else {
+                    manager.save_partial_lockfile().await?;
+                    output::warning(self.color_mode, "Interrupted. Partial lockfile saved.");
+                }
+                std::process::exit(130);
+            }
+        }
+
+        if let Some(path) = graph_output {
+            let graph = self.lockfile.lock().await.dependency_graph();
+            fs::write(&path, serde_json::to_string_pretty(&graph)?)
+                .await
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            self.report(&format!(
+                "\x1b[90mWrote dependency graph to\x1b[0m {}",
+                path.display()
+            ));
+        }
+
+        if report_unused_deps {
+            self.report_unused_deps(&project_root, source_glob).await?;
+        }
+
+        if let Some(budget) = timing_budget {
+            let elapsed = started.elapsed().as_millis();
+            if elapsed > budget as u128 {
+                let message = format!(
+                    "Install took {}ms, exceeding the budget of {}ms",
+                    elapsed, budget
+                );
+                if strict_timing {
+                    return Err(RpmError::Other {
+                        message,
+                        hint: Some("Raise --timing-budget or drop --strict-timing".to_string()),
+                    }
+                    .into());
+                }
+                output::warning(self.color_mode, &message);
+            }
+        }
+        Ok(())
+    }
+
+    /// The workspace to install when `root` is a workspace root or one of its members
+    async fn install_workspace_of(&self, root: &Path) -> Result<Option<Workspace>> {
+        match Workspace::discover(root).await? {
+            Some(workspace) => Ok(Some(workspace)),
+            None => Ok(Workspace::find_root(root)
+                .await?
+                .filter(|workspace| workspace.members.iter().any(|m| m.path == root))),
+        }
+    }
+
+    async fn install_project(
+        &self,
+        options: InstallOptions,
+        workspace: Option<Workspace>,
+    ) -> Result<()> {
+        let root = self.project_dir()?;
+
         // Check if this is a workspace
-        if let Some(workspace) = Workspace::discover(&root).await? {
-            return self.install_workspace(&workspace).await;
+        if let Some(workspace) = workspace {
+            let root_json = workspace.root.join("package.json");
+            validate_package_json(&workspace.root_package, &root_json)?;
+            if options.recover {
+                self.remove_broken_packages(&workspace.root).await?;
+            }
+            if !options.ignore_engines {
+                self.check_engines(&workspace.root_package).await?;
+            }
+            self.install_workspace(&workspace, &options).await?;
+            if options.git_hooks {
+                self.setup_git_hooks(&workspace.root, &workspace.root_package)
+                    .await?;
+            }
+            if options.timings {
+                self.print_timings();
+            }
+            return Ok(());
         }
 
+        if options.recover {
+            self.remove_broken_packages(&root).await?;
+        }
         self.load_lockfile().await?;
-        let package_json_content = fs::read_to_string("package.json").await?;
+        self.apply_install_options(&options).await;
+        let package_json_content = fs::read_to_string(root.join("package.json")).await?;
         let package_json: PackageJson = serde_json::from_str(&package_json_content)?;
+        validate_package_json(&package_json, &root.join("package.json"))?;
+
+        if !options.ignore_engines {
+            self.check_engines(&package_json).await?;
+        }
+        self.load_overrides(&package_json);
+        let overrides_changed = self.record_overrides().await;
 
         // Reset and setup progress tracking
         self.reset_progress();
         let pb = self.create_install_progress();
-        pb.set_message("\x1b[1mChecking\x1b[0m installed packages...");
+        pb.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            "\x1b[1mChecking\x1b[0m installed packages...",
+        ));
         *self.progress_bar.lock().await = Some(pb.clone());
 
-        // Incremental install: compute which packages actually need to be installed
-        let packages_to_install = self.compute_packages_to_install(&package_json).await;
+        // Incremental install: compute which packages actually need to be installed.
+        // A changed override can apply anywhere below an up-to-date package, so then
+        // everything is walked again.
+        let packages_to_install = if overrides_changed {
+            self.root_dependencies(&package_json)
+        } else {
+            self.compute_packages_to_install(&package_json).await
+        };
         
         if packages_to_install.is_empty() {
             pb.finish_and_clear();
             *self.progress_bar.lock().await = None;
-            println!("\x1b[32m✓\x1b[0m All packages up-to-date");
+            self.report("\x1b[32m✓\x1b[0m All packages up-to-date");
+            if options.no_optional || options.include_optional {
+                // Still remember the omitted dependency types
+                self.save_lockfile(&package_json.name, &package_json.version)
+                    .await?;
+            }
+            if options.git_hooks {
+                self.setup_git_hooks(&root, &package_json).await?;
+            }
             return Ok(());
         }
 
-        pb.set_message(format!(
-            "\x1b[1mInstalling\x1b[0m {} package(s)...",
-            packages_to_install.len()
+        pb.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            &format!(
+                "\x1b[1mInstalling\x1b[0m {} package(s)...",
+                packages_to_install.len()
+            ),
         ));
 
         // Install only packages that need updating
-        self.install_deps_incremental(&package_json, packages_to_install).await?;
+        self.install_deps_incremental(&package_json, packages_to_install)
+            .await?;
 
         let installed = self.packages_installed.load(Ordering::Relaxed);
         let cached = self.packages_cached.load(Ordering::Relaxed);
 
         pb.finish_and_clear();
         *self.progress_bar.lock().await = None;
+        self.finish_dots();
 
         // Print summary
         if installed > 0 || cached > 0 {
@@ -1559,36 +5464,293 @@ impl Manager {
             if installed > 0 {
                 parts.push(format!("\x1b[32m+{}\x1b[0m installed", installed));
             }
-            if cached > 0 {
-                parts.push(format!("\x1b[33m{}\x1b[0m cached", cached));
+            if cached > 0 {
+                parts.push(format!("\x1b[33m{}\x1b[0m cached", cached));
+            }
+            self.report(&parts.join("  \x1b[90m│\x1b[0m  "));
+        } else {
+            self.report("\x1b[90mNo packages to install\x1b[0m");
+        }
+
+        if options.prefer_dedupe {
+            self.dedupe_after_install(&root.join("node_modules")).await;
+        }
+        self.run_postinstalls().await?;
+        self.save_lockfile(&package_json.name, &package_json.version)
+            .await?;
+        if options.git_hooks {
+            self.setup_git_hooks(&root, &package_json).await?;
+        }
+
+        if options.timings {
+            self.print_timings();
+        }
+
+        Ok(())
+    }
+
+    /// Warn about `dependencies` that no source file imports (rpm install
+    /// --report-unused-deps). In a workspace, each member is checked against its own files.
+    async fn report_unused_deps(&self, root: &Path, pattern: Option<glob::Pattern>) -> Result<()> {
+        let projects: Vec<(PathBuf, PackageJson)> = match Workspace::discover(root).await? {
+            Some(workspace) => std::iter::once((workspace.root, workspace.root_package))
+                .chain(workspace.members.into_iter().map(|m| (m.path, m.package_json)))
+                .collect(),
+            None => {
+                let content = fs::read_to_string(root.join("package.json")).await?;
+                vec![(root.to_path_buf(), serde_json::from_str(&content)?)]
+            }
+        };
+        let in_workspace = projects.len() > 1;
+
+        // (package.json name, unused dependency)
+        let unused = tokio::task::spawn_blocking(move || -> Result<Vec<(String, String)>> {
+            let mut unused = Vec::new();
+            for (dir, package_json) in projects {
+                let imported = imports::imported_packages(&dir, pattern.as_ref())?;
+                for name in package_json.dependencies.keys() {
+                    if !imports::is_imported(name, &imported) {
+                        unused.push((package_json.name.clone(), name.clone()));
+                    }
+                }
+            }
+            Ok(unused)
+        })
+        .await??;
+
+        if unused.is_empty() {
+            self.report("\x1b[32m✓\x1b[0m Every dependency is imported by a source file");
+            return Ok(());
+        }
+        for (project, name) in &unused {
+            if in_workspace {
+                output::warning(
+                    self.color_mode,
+                    &format!("{} is never imported ({})", name, project),
+                );
+            } else {
+                output::warning(self.color_mode, &format!("{} is never imported", name));
+            }
+        }
+        output::info(
+            self.color_mode,
+            &format!(
+                "{} possibly unused dependencies; packages loaded by config files, CLIs or \
+             computed imports are reported too",
+                unused.len()
+            ),
+        );
+        Ok(())
+    }
+
+    /// Print the cumulative time spent resolving, downloading and extracting packages
+    fn print_timings(&self) {
+        let secs = |nanos: &AtomicU64| nanos.load(Ordering::Relaxed) as f64 / 1e9;
+        output::print_line(
+            self.color_mode,
+            &format!(
+                "\x1b[90mResolved:\x1b[0m {}  \x1b[90mDownloaded:\x1b[0m {}  \
+             \x1b[90mExtracted:\x1b[0m {}",
+                output::format_duration(secs(&self.resolve_nanos)),
+                output::format_duration(secs(&self.download_nanos)),
+                output::format_duration(secs(&self.extract_nanos))
+            ),
+        );
+    }
+
+    /// Validate the `engines` field of the root package.json against rpm and Node.js
+    async fn check_engines(&self, package_json: &PackageJson) -> Result<()> {
+        let hint = Some("Use --ignore-engines to skip this check".to_string());
+
+        if let Some(required) = package_json.engines.get("rpm") {
+            let current = env!("CARGO_PKG_VERSION");
+            if version_satisfies(required, current) == Some(false) {
+                return Err(RpmError::Other {
+                    message: format!(
+                        "{} requires rpm {}, but this is rpm {}",
+                        package_json.name, required, current
+                    ),
+                    hint,
+                }
+                .into());
+            }
+        }
+
+        if let Some(required) = package_json.engines.get("node") {
+            match self.get_node_version().await {
+                Some(current) => {
+                    if version_satisfies(required, &current) == Some(false) {
+                        return Err(RpmError::Other {
+                            message: format!(
+                                "{} requires node {}, but found node {}",
+                                package_json.name, required, current
+                            ),
+                            hint,
+                        }
+                        .into());
+                    }
+                }
+                None => output::warning(
+                    self.color_mode,
+                    &format!(
+                        "could not detect the Node.js version (engines.node is {})",
+                        required
+                    ),
+                ),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run the script setting up git hooks (rpm install --git-hooks). An existing
+    /// pre-commit hook is only replaced if a known hook manager wrote it.
+    async fn setup_git_hooks(&self, root: &Path, package_json: &PackageJson) -> Result<()> {
+        let Some((hook_manager, script)) = git_hooks_script(&package_json.scripts) else {
+            output::warning(
+                self.color_mode,
+                "no prepare or postinstall script sets up husky, simple-git-hooks or lefthook",
+            );
+            return Ok(());
+        };
+        if !root.join(".git").exists() {
+            output::warning(
+                self.color_mode,
+                &format!(
+                    "{} is not a git repository, skipping git hooks",
+                    root.display()
+                ),
+            );
+            return Ok(());
+        }
+        let node_modules = root.join("node_modules");
+        if !node_modules.join(hook_manager).exists() {
+            output::warning(
+                self.color_mode,
+                &format!("{} is not installed, skipping git hooks", hook_manager),
+            );
+            return Ok(());
+        }
+        let pre_commit = root.join(".git").join("hooks").join("pre-commit");
+        if let Ok(content) = fs::read_to_string(&pre_commit).await {
+            if !HOOK_MANAGERS.iter().any(|m| content.contains(m)) {
+                output::warning(
+                    self.color_mode,
+                    ".git/hooks/pre-commit was not written by a hook manager, leaving it as is",
+                );
+                return Ok(());
             }
-            println!("{}", parts.join("  \x1b[90m│\x1b[0m  "));
-        } else {
-            println!("\x1b[90mNo packages to install\x1b[0m");
         }
 
-        self.run_postinstalls().await?;
-        self.save_lockfile(&package_json.name, &package_json.version)
+        self.report(&format!(
+            "\x1b[1mSetting up\x1b[0m {} git hooks \x1b[90m({})\x1b[0m",
+            hook_manager, script
+        ));
+        let bin_path = node_modules.join(".bin");
+        let path_env = std::env::var("PATH").unwrap_or_default();
+        let status = shell_command(&self.config.script_shell(), script)
+            .current_dir(root)
+            .env("PATH", format!("{}:{}", bin_path.display(), path_env))
+            .envs(self.node_env())
+            .envs(package_env(package_json, &root.join("package.json")))
+            .status()
             .await?;
+        if !status.success() {
+            return Err(RpmError::Other {
+                message: format!("Setting up {} git hooks failed ({})", hook_manager, status),
+                hint: Some(format!("Run `{}` to see the full output", script)),
+            }
+            .into());
+        }
+        Ok(())
+    }
 
+    /// Warn about dependencies declared with different versions across the workspace,
+    /// an error with --strict-peer-deps
+    fn check_version_conflicts(&self, workspace: &Workspace, strict: bool) -> Result<()> {
+        let conflicts = workspace.detect_version_conflicts();
+        if conflicts.is_empty() {
+            return Ok(());
+        }
+        for conflict in &conflicts {
+            let versions: Vec<String> = conflict
+                .versions
+                .iter()
+                .map(|(version, users)| format!("{} ({})", version, users.join(", ")))
+                .collect();
+            output::warning(
+                self.color_mode,
+                &format!(
+                    "{} is declared with different versions: {}",
+                    conflict.package,
+                    versions.join(", ")
+                ),
+            );
+        }
+        if strict {
+            let packages: Vec<&str> = conflicts.iter().map(|c| c.package.as_str()).collect();
+            return Err(RpmError::WorkspaceError {
+                message: format!(
+                    "Conflicting versions across the workspace: {}",
+                    packages.join(", ")
+                ),
+                hint: Some(
+                    "Use the same version everywhere, or drop --strict-peer-deps".to_string(),
+                ),
+            }
+            .into());
+        }
         Ok(())
     }
 
     /// Install dependencies for a workspace (monorepo)
-    async fn install_workspace(&self, workspace: &Workspace) -> Result<()> {
-        workspace.print_info();
-        println!();
+    async fn install_workspace(
+        &self,
+        workspace: &Workspace,
+        options: &InstallOptions,
+    ) -> Result<()> {
+        if self.reporter != Reporter::Silent {
+            workspace.print_info(self.color_mode);
+            println!();
+        }
 
         self.load_lockfile().await?;
+        self.apply_install_options(options).await;
+        self.check_version_conflicts(workspace, options.strict_peer_deps)?;
 
         // Reset and setup progress tracking
         self.reset_progress();
         let pb = self.create_install_progress();
-        pb.set_message("\x1b[1mResolving\x1b[0m workspace dependencies...");
+        pb.set_message(output::maybe_strip_colors(
+            self.color_mode,
+            "\x1b[1mResolving\x1b[0m workspace dependencies...",
+        ));
         *self.progress_bar.lock().await = Some(pb.clone());
 
-        // Get hoisted dependencies (installed at root)
-        let hoisted = workspace.get_hoisted_dependencies();
+        self.load_overrides(&workspace.root_package);
+        self.record_overrides().await;
+
+        if let Some((member, dep_name)) = workspace.missing_catalog_entry() {
+            pb.finish_and_clear();
+            *self.progress_bar.lock().await = None;
+            return Err(RpmError::Other {
+                message: format!("{} depends on {} from the catalog", member, dep_name),
+                hint: Some(format!(
+                    "Add a version for {} under `catalog:` in pnpm-workspace.yaml",
+                    dep_name
+                )),
+            }
+            .into());
+        }
+
+        // Get hoisted dependencies (installed at root, "catalog:" resolved)
+        let mut hoisted = workspace.get_hoisted_dependencies();
+        let install_dev = self.install_dev.load(Ordering::Relaxed);
+        if install_dev != self.install_prod.load(Ordering::Relaxed) {
+            // --only: keep what is declared in the selected dependency maps
+            let selected = workspace.declared_dependencies(install_dev);
+            hoisted.retain(|name, _| selected.contains(name));
+        }
         let workspace_packages = workspace.get_workspace_package_names();
 
         // Install hoisted dependencies at root
@@ -1598,14 +5760,12 @@ impl Manager {
             let manager = self.clone();
             let name = name.clone();
             let version = version.clone();
-            tasks.push(async move { manager.resolve_and_install(name, version, root).await });
+            tasks.push(async move { manager.resolve_and_install(name, version, root, None).await });
         }
 
         while let Some(result) = tasks.next().await {
             if let Err(e) = result {
-                let _ = self
-                    .multi_progress
-                    .println(format!("\x1b[31merror:\x1b[0m {}", e));
+                self.report_error(format!("\x1b[31merror:\x1b[0m {}", e));
             }
         }
 
@@ -1613,37 +5773,38 @@ impl Manager {
         let root_node_modules = workspace.root.join("node_modules");
         fs::create_dir_all(&root_node_modules).await?;
 
+        // Link members concurrently, bounded by the number of CPUs
+        let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let member_semaphore = Arc::new(Semaphore::new(workspace.members.len().min(cpus).max(1)));
+        let mut tasks = FuturesUnordered::new();
         for member in &workspace.members {
-            let link_path = root_node_modules.join(&member.name);
-            
-            // Handle scoped packages (@scope/name)
-            if member.name.contains('/') {
-                if let Some(scope) = member.name.split('/').next() {
-                    fs::create_dir_all(root_node_modules.join(scope)).await?;
-                }
-            }
-
-            // Remove existing link/dir
-            if link_path.exists() || link_path.is_symlink() {
-                let _ = fs::remove_file(&link_path).await;
-                let _ = fs::remove_dir_all(&link_path).await;
-            }
-
-            // Create symlink to workspace member
-            #[cfg(unix)]
-            {
-                let relative = pathdiff::diff_paths(&member.path, &root_node_modules)
-                    .unwrap_or_else(|| member.path.clone());
-                let _ = fs::symlink(&relative, &link_path).await;
-            }
+            let member_semaphore = member_semaphore.clone();
+            let root_node_modules = &root_node_modules;
+            tasks.push(async move {
+                let _permit = member_semaphore.acquire().await;
+                self.link_workspace_member(root_node_modules, member)
+                    .await
+                    .map(|_| member)
+            });
+        }
 
-            #[cfg(windows)]
-            {
-                let _ = tokio::fs::symlink_dir(&member.path, &link_path).await;
-            }
+        let mut linked = 0;
+        while let Some(result) = tasks.next().await {
+            let member = result?;
+            linked += 1;
+            pb.set_message(output::maybe_strip_colors(
+                self.color_mode,
+                &format!(
+                    "\x1b[1mLinking\x1b[0m workspace packages ({}/{}) \x1b[90m{}\x1b[0m",
+                    linked,
+                    workspace.members.len(),
+                    member.name
+                ),
+            ));
         }
 
-        // Link binaries from workspace packages
+        // Binaries of all members share node_modules/.bin, link them one member at a time
+        // (in workspace order, so the last member declaring a name wins as before)
         for member in &workspace.members {
             if let Some(bin) = &member.package_json.bin {
                 let _ = self.link_binaries(&workspace.root, &member.name, bin).await;
@@ -1655,9 +5816,10 @@ impl Manager {
 
         pb.finish_and_clear();
         *self.progress_bar.lock().await = None;
+        self.finish_dots();
 
         // Print summary
-        println!();
+        self.report("");
         if installed > 0 || cached > 0 {
             let mut parts = Vec::new();
             if installed > 0 {
@@ -1670,16 +5832,19 @@ impl Manager {
                 "\x1b[36m{}\x1b[0m linked",
                 workspace_packages.len()
             ));
-            println!("{}", parts.join("  \x1b[90m│\x1b[0m  "));
+            self.report(&parts.join("  \x1b[90m│\x1b[0m  "));
         } else if !workspace_packages.is_empty() {
-            println!(
+            self.report(&format!(
                 "\x1b[36m{}\x1b[0m workspace packages linked",
                 workspace_packages.len()
-            );
+            ));
         } else {
-            println!("\x1b[90mNo packages to install\x1b[0m");
+            self.report("\x1b[90mNo packages to install\x1b[0m");
         }
 
+        if options.prefer_dedupe {
+            self.dedupe_after_install(&root_node_modules).await;
+        }
         self.run_postinstalls().await?;
         self.save_lockfile(&workspace.root_package.name, &workspace.root_package.version)
             .await?;
@@ -1687,16 +5852,63 @@ impl Manager {
         Ok(())
     }
 
-    async fn install_deps(&self, package_json: &PackageJson) -> Result<()> {
-        let root = std::env::current_dir()?;
-        
-        // Collect all dependencies (regular + dev)
-        let all_deps: Vec<(String, String)> = package_json
+    /// Symlink a workspace member into the root node_modules
+    async fn link_workspace_member(
+        &self,
+        root_node_modules: &Path,
+        member: &WorkspaceMember,
+    ) -> Result<()> {
+        let link_path = root_node_modules.join(&member.name);
+
+        // Handle scoped packages (@scope/name)
+        if member.name.contains('/') {
+            if let Some(scope) = member.name.split('/').next() {
+                fs::create_dir_all(root_node_modules.join(scope)).await?;
+            }
+        }
+
+        // Remove existing link/dir
+        if link_path.exists() || link_path.is_symlink() {
+            let _ = fs::remove_file(&link_path).await;
+            let _ = fs::remove_dir_all(&link_path).await;
+        }
+
+        // Create symlink to workspace member
+        #[cfg(unix)]
+        {
+            let relative = pathdiff::diff_paths(&member.path, root_node_modules)
+                .unwrap_or_else(|| member.path.clone());
+            let _ = fs::symlink(&relative, &link_path).await;
+        }
+
+        #[cfg(windows)]
+        {
+            let _ = tokio::fs::symlink_dir(&member.path, &link_path).await;
+        }
+
+        Ok(())
+    }
+
+    /// Dependencies of the root package.json to install, honoring --only
+    fn root_dependencies(&self, package_json: &PackageJson) -> Vec<(String, String)> {
+        let prod = self.install_prod.load(Ordering::Relaxed);
+        let dev = self.install_dev.load(Ordering::Relaxed);
+        package_json
             .dependencies
             .iter()
-            .chain(package_json.dev_dependencies.iter())
+            .filter(|_| prod)
+            .chain(package_json.dev_dependencies.iter().filter(|_| dev))
+            .chain(root_peer_dependencies(package_json).filter(|_| prod))
             .map(|(name, version)| (name.clone(), version.clone()))
-            .collect();
+            .collect()
+    }
+
+    async fn install_deps(&self, package_json: &PackageJson) -> Result<()> {
+        let root = self.project_dir()?;
+        self.load_overrides(package_json);
+        
+        // Collect all dependencies (regular + dev + peer)
+        let all_deps = self.root_dependencies(package_json);
 
         // Lazy resolution optimization: identify which packages need registry fetch
         // vs which can be resolved entirely from lockfile
@@ -1707,16 +5919,7 @@ impl Manager {
         for (name, version_range) in &all_deps {
             let key = format!("node_modules/{}", name);
             if let Some(entry) = lockfile.packages.get(&key) {
-                let matches = semver::Version::parse(&entry.version)
-                    .ok()
-                    .and_then(|v| {
-                        semver::VersionReq::parse(version_range)
-                            .ok()
-                            .map(|r| r.matches(&v))
-                    })
-                    .unwrap_or(false);
-                
-                if matches || version_range == &entry.version {
+                if self.lock_entry_satisfies(&entry.version, version_range) {
                     from_lockfile.push((name.clone(), version_range.clone()));
                 } else {
                     needs_fetch.push((name.clone(), version_range.clone()));
@@ -1737,26 +5940,41 @@ impl Manager {
         for (name, version) in ordered_deps {
             let root = root.clone();
             let manager = self.clone();
-            tasks.push(async move { manager.resolve_and_install(name, version, root).await });
+            tasks.push(async move { manager.resolve_and_install(name, version, root, None).await });
         }
 
         while let Some(result) = tasks.next().await {
             if let Err(e) = result {
-                let _ = self
-                    .multi_progress
-                    .println(format!("\x1b[31merror:\x1b[0m {}", e));
+                self.report_error(format!("\x1b[31merror:\x1b[0m {}", e));
             }
         }
         Ok(())
     }
 
+    /// Whether a lockfile entry can be used for a declared range. With --exact-versions
+    /// the locked version is always used, whatever the range says.
+    fn lock_entry_satisfies(&self, locked_version: &str, version_range: &str) -> bool {
+        if self.exact_versions.load(Ordering::Relaxed) || version_range == locked_version {
+            return true;
+        }
+        semver::Version::parse(locked_version)
+            .ok()
+            .and_then(|v| {
+                semver::VersionReq::parse(version_range)
+                    .ok()
+                    .map(|r| r.matches(&v))
+            })
+            .unwrap_or(false)
+    }
+
     /// Install only the specified packages (incremental install)
     async fn install_deps_incremental(
         &self,
-        _package_json: &PackageJson,
+        package_json: &PackageJson,
         packages_to_install: Vec<(String, String)>,
     ) -> Result<()> {
-        let root = std::env::current_dir()?;
+        let root = self.project_dir()?;
+        self.load_overrides(package_json);
 
         // Lazy resolution: identify which packages need registry fetch
         let lockfile = self.lockfile.lock().await;
@@ -1766,16 +5984,7 @@ impl Manager {
         for (name, version_range) in &packages_to_install {
             let key = format!("node_modules/{}", name);
             if let Some(entry) = lockfile.packages.get(&key) {
-                let matches = semver::Version::parse(&entry.version)
-                    .ok()
-                    .and_then(|v| {
-                        semver::VersionReq::parse(version_range)
-                            .ok()
-                            .map(|r| r.matches(&v))
-                    })
-                    .unwrap_or(false);
-                
-                if matches || version_range == &entry.version {
+                if self.lock_entry_satisfies(&entry.version, version_range) {
                     from_lockfile.push((name.clone(), version_range.clone()));
                 } else {
                     needs_fetch.push((name.clone(), version_range.clone()));
@@ -1796,14 +6005,12 @@ impl Manager {
         for (name, version) in ordered_deps {
             let root = root.clone();
             let manager = self.clone();
-            tasks.push(async move { manager.resolve_and_install(name, version, root).await });
+            tasks.push(async move { manager.resolve_and_install(name, version, root, None).await });
         }
 
         while let Some(result) = tasks.next().await {
             if let Err(e) = result {
-                let _ = self
-                    .multi_progress
-                    .println(format!("\x1b[31merror:\x1b[0m {}", e));
+                self.report_error(format!("\x1b[31merror:\x1b[0m {}", e));
             }
         }
         Ok(())
@@ -1814,42 +6021,78 @@ impl Manager {
             return Ok(());
         }
 
-        let scripts_to_run: Vec<_> = if !self.auto_confirm {
-            println!("\n\x1b[1;33mPending postinstall scripts:\x1b[0m");
-            for entry in self.postinstalls.iter() {
-                println!(
-                    "  \x1b[90m-\x1b[0m \x1b[36m{}\x1b[0m \x1b[90m{}\x1b[0m",
-                    entry.key(),
-                    entry.value().1
-                );
-            }
-
-            println!("\n\x1b[1mRun these scripts?\x1b[0m \x1b[90m[y/N]\x1b[0m");
-
-            let mut stdin = BufReader::new(tokio::io::stdin());
-            let mut line = String::new();
-            stdin.read_line(&mut line).await?;
-
-            if line.trim().eq_ignore_ascii_case("y") {
-                self.postinstalls
-                    .iter()
-                    .map(|e| (e.key().clone(), e.value().clone()))
-                    .collect()
-            } else {
-                println!("\x1b[90mSkipped postinstall scripts\x1b[0m");
-                return Ok(());
-            }
+        // --yes trusts everything, whatever the configured policy
+        let policy = if self.auto_confirm {
+            Some(TrustPolicy::All)
         } else {
+            self.trust_policy
+        };
+        let all_scripts = || -> Vec<_> {
             self.postinstalls
                 .iter()
                 .map(|e| (e.key().clone(), e.value().clone()))
                 .collect()
         };
+        let scripts_to_run: Vec<_> = match policy {
+            Some(TrustPolicy::None) => {
+                self.report("\x1b[90mSkipped postinstall scripts (trust-policy: none)\x1b[0m");
+                return Ok(());
+            }
+            Some(TrustPolicy::All) => all_scripts(),
+            Some(TrustPolicy::SameScope) => {
+                let scope = project_scope(&self.project_dir()?).await;
+                let (trusted, skipped): (Vec<_>, Vec<_>) =
+                    all_scripts().into_iter().partition(|(name, _)| {
+                        scope.as_deref().is_some_and(|scope| package_scope(name) == Some(scope))
+                    });
+                for (name, _) in &skipped {
+                    self.report(&format!(
+                        "\x1b[90mSkipped postinstall of {} (trust-policy: same-scope)\x1b[0m",
+                        name
+                    ));
+                }
+                trusted
+            }
+            None => {
+                output::print_line(
+                    self.color_mode,
+                    "\n\x1b[1;33mPending postinstall scripts:\x1b[0m",
+                );
+                for entry in self.postinstalls.iter() {
+                    output::print_line(
+                        self.color_mode,
+                        &format!(
+                            "  \x1b[90m-\x1b[0m \x1b[36m{}\x1b[0m \x1b[90m{}\x1b[0m",
+                            entry.key(),
+                            entry.value().1
+                        ),
+                    );
+                }
+
+                output::print_line(
+                    self.color_mode,
+                    "\n\x1b[1mRun these scripts?\x1b[0m \x1b[90m[y/N]\x1b[0m",
+                );
+
+                let mut stdin = BufReader::new(tokio::io::stdin());
+                let mut line = String::new();
+                stdin.read_line(&mut line).await?;
+
+                if line.trim().eq_ignore_ascii_case("y") {
+                    all_scripts()
+                } else {
+                    self.report("\x1b[90mSkipped postinstall scripts\x1b[0m");
+                    return Ok(());
+                }
+            }
+        };
 
         if scripts_to_run.is_empty() {
             return Ok(());
         }
 
+        // Warns when scripts are dropped to "nobody", so before the progress bar starts
+        let script_user = self.script_user();
         let total = scripts_to_run.len();
         let completed = Arc::new(AtomicUsize::new(0));
         
@@ -1857,11 +6100,20 @@ impl Manager {
             .multi_progress
             .add(ProgressBar::new(total as u64));
         pb.set_style(ProgressStyle::default_bar()
-            .template("{spinner:.cyan} [{bar:40.cyan/blue}] {pos}/{len} \x1b[1mRunning\x1b[0m postinstall scripts (parallel)...")
+            .template(&output::maybe_strip_colors(
+                self.color_mode,
+                "{spinner:.cyan} [{bar:40.cyan/blue}] {pos}/{len} \x1b[1mRunning\x1b[0m postinstall scripts (parallel)..."
+            ))
             .unwrap()
             .progress_chars("━╸─")
             .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏"));
 
+        let node_gyp = self.config.node_gyp();
+        let shell = self.config.script_shell();
+        let node_env = self.node_env();
+        let timeout = self.postinstall_timeout;
+        let pb = &pb;
+
         // Execute postinstall scripts in parallel
         let mut tasks = FuturesUnordered::new();
         
@@ -1871,24 +6123,77 @@ impl Manager {
         for (name, (path, script)) in scripts_to_run {
             let completed = completed.clone();
             let postinstall_semaphore = postinstall_semaphore.clone();
+            let node_gyp = node_gyp.clone();
+            let shell = &shell;
+            let node_env = &node_env;
             
             tasks.push(async move {
                 let _permit = postinstall_semaphore.acquire().await;
-                
-                let status = Command::new("sh")
-                    .arg("-c")
-                    .arg(&script)
+
+                // Packages symlinked to the store (--prefer-symlinks) run their script in a
+                // private copy, and are linked again if the script left the files untouched
+                let store_entry = self.installer.materialize(&path).await.ok().flatten();
+                let before = store_entry.as_ref().map(|_| dir_fingerprint(&path));
+
+                let mut command = shell_command(shell, &script);
+                command
+                    .envs(node_env.iter().cloned())
                     .current_dir(&path)
+                    // Outside the terminal's foreground group, reading it would stop the script
+                    .stdin(std::process::Stdio::null())
                     .stdout(std::process::Stdio::null())
-                    .stderr(std::process::Stdio::null())
-                    .status()
-                    .await;
+                    .stderr(std::process::Stdio::null());
+                if let Some(node_gyp) = &node_gyp {
+                    command.env("npm_config_node_gyp", node_gyp);
+                }
+                #[cfg(unix)]
+                if let Some((uid, gid)) = script_user {
+                    command.uid(uid).gid(gid);
+                }
+                // Own process group, so a timeout also stops what the script started
+                #[cfg(unix)]
+                command.process_group(0);
 
                 // Fallback to cmd on Windows if sh fails
-                let success = match status {
-                    Ok(s) => s.success(),
+                let mut timed_out = false;
+                let success = match command.spawn() {
+                    Ok(mut child) => {
+                        if let Some(pid) = child.id() {
+                            self.running_postinstalls.insert(name.clone(), pid);
+                        }
+                        let status = tokio::time::timeout(timeout, child.wait()).await;
+                        self.running_postinstalls.remove(&name);
+                        match status {
+                            Ok(status) => status.is_ok_and(|s| s.success()),
+                            Err(_) => {
+                                #[cfg(unix)]
+                                if let Some(pid) = child.id() {
+                                    unsafe {
+                                        libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+                                    }
+                                }
+                                let _ = child.kill().await;
+                                pb.suspend(|| {
+                                    output::warning(
+                                        self.color_mode,
+                                        &format!(
+                                            "postinstall for {} timed out after {}s",
+                                            name,
+                                            timeout.as_secs()
+                                        ),
+                                    )
+                                });
+                                timed_out = true;
+                                false
+                            }
+                        }
+                    }
                     Err(_) if cfg!(windows) => {
-                        Command::new("cmd")
+                        let mut command = Command::new("cmd");
+                        if let Some(node_gyp) = &node_gyp {
+                            command.env("npm_config_node_gyp", node_gyp);
+                        }
+                        command
                             .arg("/C")
                             .arg(&script)
                             .current_dir(&path)
@@ -1902,35 +6207,133 @@ impl Manager {
                     Err(_) => false,
                 };
 
+                let mut kept_copy = false;
+                if let (Some(store_entry), Some(before)) = (store_entry, before) {
+                    if dir_fingerprint(&path) == before {
+                        let _ = self.installer.relink(&path, &store_entry).await;
+                    } else {
+                        kept_copy = true;
+                    }
+                }
+
                 completed.fetch_add(1, Ordering::Relaxed);
-                (name, success)
+                (name, success, timed_out, kept_copy)
             });
         }
 
         // Process results as they complete
         let mut failed_scripts = Vec::new();
-        while let Some((name, success)) = tasks.next().await {
+        let mut timed_out_scripts = Vec::new();
+        let mut copied = Vec::new();
+        let mut interrupted = false;
+        loop {
+            let result = tokio::select! {
+                result = tasks.next() => result,
+                // Scripts run in their own process groups, so Ctrl+C does not reach them
+                _ = shutdown_signal(), if !interrupted => {
+                    interrupted = true;
+                    terminate_running(&self.running_postinstalls);
+                    continue;
+                }
+            };
+            let Some((name, success, timed_out, kept_copy)) = result else {
+                break;
+            };
             pb.inc(1);
-            if !success {
-                failed_scripts.push(name);
+            if timed_out {
+                timed_out_scripts.push(name.clone());
+            } else if !success {
+                failed_scripts.push(name.clone());
+            }
+            if kept_copy {
+                copied.push(name);
             }
         }
 
         pb.finish_and_clear();
-        
+        if interrupted {
+            return Err(RpmError::Other {
+                message: "Interrupted while running postinstall scripts".to_string(),
+                hint: None,
+            }
+            .into());
+        }
+
         // Report any failures
         if !failed_scripts.is_empty() {
             for name in &failed_scripts {
-                let _ = self.multi_progress.println(format!(
-                    "\x1b[33mwarn:\x1b[0m postinstall script for \x1b[1m{}\x1b[0m failed",
-                    name
+                let _ = self.multi_progress.println(output::maybe_strip_colors(
+                    self.color_mode,
+                    &format!(
+                        "\x1b[33mwarn:\x1b[0m postinstall script for \x1b[1m{}\x1b[0m failed",
+                        name
+                    ),
                 ));
             }
         }
+        if !timed_out_scripts.is_empty() {
+            timed_out_scripts.sort();
+            let _ = self.multi_progress.println(output::maybe_strip_colors(
+                self.color_mode,
+                &format!(
+                    "\x1b[33mwarn:\x1b[0m postinstall timed out after {}s for \x1b[1m{}\x1b[0m",
+                    self.postinstall_timeout.as_secs(),
+                    timed_out_scripts.join(", ")
+                ),
+            ));
+        }
+        for name in &copied {
+            let _ = self.multi_progress.println(output::maybe_strip_colors(
+                self.color_mode,
+                &format!(
+                    "\x1b[33mwarn:\x1b[0m postinstall script of \x1b[1m{}\x1b[0m modified its \
+                     files, keeping a copy instead of a symlink",
+                    name
+                ),
+            ));
+        }
         
         Ok(())
     }
 
+    /// The (uid, gid) postinstall scripts run as, or None to keep the current user.
+    /// When rpm runs as root, scripts are dropped to "nobody" unless --unsafe-perm is set.
+    #[cfg(unix)]
+    fn script_user(&self) -> Option<(u32, u32)> {
+        // SAFETY: geteuid has no preconditions and cannot fail
+        if unsafe { libc::geteuid() } != 0 {
+            return None;
+        }
+
+        if self.unsafe_perm {
+            self.warn_once("running postinstall scripts as root (--unsafe-perm)");
+            return None;
+        }
+
+        // SAFETY: the name is a valid C string and the returned entry is only read
+        // before any other getpw* call
+        let nobody = unsafe {
+            let entry = libc::getpwnam(c"nobody".as_ptr());
+            if entry.is_null() {
+                None
+            } else {
+                Some(((*entry).pw_uid, (*entry).pw_gid))
+            }
+        };
+        let (uid, gid) = nobody.unwrap_or((65534, 65534));
+
+        self.warn_once(
+            "running as root, postinstall scripts will run as \"nobody\"; \
+             use --unsafe-perm to run them as root",
+        );
+        Some((uid, gid))
+    }
+
+    #[cfg(not(unix))]
+    fn script_user(&self) -> Option<(u32, u32)> {
+        None
+    }
+
     async fn link_binaries(
         &self,
         target_dir: &PathBuf,
@@ -1940,23 +6343,7 @@ impl Manager {
         let bin_dir = target_dir.join("node_modules").join(".bin");
         fs::create_dir_all(&bin_dir).await?;
 
-        let bins: BTreeMap<String, String> = match bin {
-            serde_json::Value::String(s) => {
-                let mut map = BTreeMap::new();
-                map.insert(package_name.to_string(), s.clone());
-                map
-            }
-            serde_json::Value::Object(o) => {
-                let mut map = BTreeMap::new();
-                for (k, v) in o {
-                    if let Some(s) = v.as_str() {
-                        map.insert(k.clone(), s.to_string());
-                    }
-                }
-                map
-            }
-            _ => BTreeMap::new(),
-        };
+        let bins = bin_entries(package_name, bin);
 
         for (name, path) in bins {
             let target_path = target_dir
@@ -1988,20 +6375,13 @@ impl Manager {
 
             #[cfg(windows)]
             {
-                let cmd_content = format!(
-                    "@ECHO off\r\n\"%~dp0\\..\\{}\\{}\" %*\r\n",
-                    package_name,
-                    path.replace("/", "\\")
-                );
-                fs::write(link_path.with_extension("cmd"), cmd_content).await?;
-
-                // Optional: Powershell shim
-                let ps1_content = format!(
-                    "& \"$PSScriptRoot\\..\\{}\\{}\" $args\r\n",
-                    package_name,
-                    path.replace("/", "\\")
-                );
-                fs::write(link_path.with_extension("ps1"), ps1_content).await?;
+                let target = windows_bin_path("..", package_name, &path);
+                let script = fs::read(&target_path).await.unwrap_or_default();
+                let program = shim_program(&script, &path);
+                fs::write(link_path.with_extension("cmd"), cmd_shim(&target, program.as_deref()))
+                    .await?;
+                fs::write(link_path.with_extension("ps1"), ps1_shim(&target, program.as_deref()))
+                    .await?;
             }
         }
         Ok(())
@@ -2013,74 +6393,67 @@ impl Manager {
         name: String,
         version_range: String,
         target_dir: PathBuf,
+        parent_name: Option<&str>,
     ) -> Result<()> {
-        if self.installed.contains_key(&name) {
+        // Overrides from the root package.json replace the declared range
+        let version_range = self.override_range(&name, parent_name, version_range);
+
+        if let Some(installed) = self.installed.get(&name).map(|v| v.value().clone()) {
+            self.warn_override_conflict(&name, parent_name, &version_range, &installed);
             return Ok(());
         }
 
         // Track current package being resolved
         self.set_current_package(&name, "resolving");
 
-        // Lazy resolution: First check lockfile, then check if already installed on disk
-        let lock_entry = {
-            let lock = self.lockfile.lock().await;
-            let key = format!("node_modules/{}", name);
-            lock.packages.get(&key).cloned()
-        };
-
-        let (version, tarball, deps, peer_deps, optional_deps, postinstall, bin) =
-            if let Some(entry) = lock_entry {
-                // Check if lockfile version satisfies the requested range
-                let matches = semver::Version::parse(&entry.version)
-                    .ok()
-                    .and_then(|v| {
-                        semver::VersionReq::parse(&version_range)
-                            .ok()
-                            .map(|r| r.matches(&v))
-                    })
-                    .unwrap_or(false);
-
-                if matches || version_range == entry.version {
-                    // Lockfile entry is valid - use it without any network request (lazy)
-                    (
-                        entry.version,
-                        entry.resolved,
-                        entry.dependencies,
-                        entry.peer_dependencies,
-                        entry.optional_dependencies,
-                        entry.postinstall,
-                        entry.bin,
-                    )
-                } else {
-                    // Version mismatch - need to fetch from registry
-                    self.fetch_and_resolve(&name, &version_range).await?
-                }
-            } else {
-                // Not in lockfile - need to fetch from registry
-                self.fetch_and_resolve(&name, &version_range).await?
-            };
+        let LockPackage {
+            version,
+            resolved: tarball,
+            integrity,
+            dependencies: deps,
+            peer_dependencies: peer_deps,
+            optional_dependencies: optional_deps,
+            postinstall,
+            bin,
+        } = self.resolve_lock_package(&name, &version_range).await?;
 
         // Track resolved packages
         self.packages_resolved.fetch_add(1, Ordering::Relaxed);
         self.clear_current_package(&name);
         self.update_progress();
 
-        if self.installed.contains_key(&name) {
+        if let Some(installed) = self.installed.get(&name).map(|v| v.value().clone()) {
+            self.warn_override_conflict(&name, parent_name, &version_range, &installed);
             return Ok(());
         }
         self.installed.insert(name.clone(), version.clone());
 
         let install_path = target_dir.join("node_modules").join(&name);
-        let already_exists = install_path.join("package.json").exists();
+        // Another version (e.g. from before an override changed) is replaced
+        let already_exists = match fs::read_to_string(install_path.join("package.json")).await {
+            Ok(content) => serde_json::from_str::<serde_json::Value>(&content)
+                .is_ok_and(|installed| installed["version"] == version.as_str()),
+            Err(_) => false,
+        };
 
         if !already_exists {
             // Track current package being installed
             self.set_current_package(&name, "installing");
+            let size = self.registry.unpacked_size(&name, &version);
+            self.bytes_total.fetch_add(size.unwrap_or(0), Ordering::Relaxed);
             
             let install_res = async {
-                let install_dir = std::env::current_dir().unwrap();
+                let install_dir = self.project_dir()?;
+                let token = self.registry.tarball_token(&name, &tarball);
                 self.installer
-                    .install_package(&name, &version, &tarball, &install_dir)
+                    .install_package(
+                        &name,
+                        &version,
+                        &tarball,
+                        integrity.as_deref(),
+                        token,
+                        &install_dir,
+                    )
                     .await
             }
             .await;
@@ -2091,7 +6464,9 @@ impl Manager {
                 Ok(_) => {
                     // Track installed packages
                     self.packages_installed.fetch_add(1, Ordering::Relaxed);
+                    self.bytes_done.fetch_add(size.unwrap_or(0), Ordering::Relaxed);
                     self.update_progress();
+                    self.report_dot();
 
                     // Collect postinstall if exists
                     if let Some(script) = &postinstall {
@@ -2100,7 +6475,7 @@ impl Manager {
                     }
                 }
                 Err(e) => {
-                    let _ = self.multi_progress.println(format!(
+                    self.report_error(format!(
                         "{}✗{} {}{}{}@{} failed: {}",
                         colors::RED,
                         colors::RESET,
@@ -2132,7 +6507,7 @@ impl Manager {
                 LockPackage {
                     version: version.clone(),
                     resolved: tarball.clone(),
-                    integrity: None,
+                    integrity: integrity.clone(),
                     dependencies: deps.clone(),
                     peer_dependencies: peer_deps.clone(),
                     optional_dependencies: optional_deps.clone(),
@@ -2150,10 +6525,12 @@ impl Manager {
             all_deps.push((dep_name.clone(), dep_ver.clone()));
         }
 
-        // Peer dependencies (auto-installed like npm 7+)
-        for (dep_name, dep_ver) in peer_deps {
-            if !self.installed.contains_key(&dep_name) {
-                all_deps.push((dep_name.clone(), dep_ver.clone()));
+        // Peer dependencies (auto-installed like npm 7+, unless --legacy-peer-deps)
+        if self.install_peers.load(Ordering::Relaxed) {
+            for (dep_name, dep_ver) in peer_deps {
+                if !self.installed.contains_key(&dep_name) {
+                    all_deps.push((dep_name.clone(), dep_ver.clone()));
+                }
             }
         }
 
@@ -2165,25 +6542,32 @@ impl Manager {
 
         // Install regular and peer dependencies
         let mut tasks = FuturesUnordered::new();
+        let parent = name.as_str();
         for (dep_name, dep_ver) in all_deps {
             let target_dir = target_dir.clone();
             let manager = self.clone();
             tasks.push(async move {
                 manager
-                    .resolve_and_install(dep_name, dep_ver, target_dir)
+                    .resolve_and_install(dep_name, dep_ver, target_dir, Some(parent))
                     .await
             });
         }
 
         while let Some(result) = tasks.next().await {
             if let Err(e) = result {
-                let _ = self
-                    .multi_progress
-                    .println(format!("\x1b[33mwarn:\x1b[0m {} - {}", name, e));
+                let _ = self.multi_progress.println(output::maybe_strip_colors(
+                    self.color_mode,
+                    &format!("\x1b[33mwarn:\x1b[0m {} - {}", name, e),
+                ));
             }
         }
 
         // Install optional dependencies (with platform checking, failures are silently ignored)
+        let optional_deps_list = if self.install_optional.load(Ordering::Relaxed) {
+            optional_deps_list
+        } else {
+            Vec::new()
+        };
         for (dep_name, dep_ver) in optional_deps_list {
             // Skip if already installed
             if self.installed.contains_key(&dep_name) {
@@ -2198,7 +6582,7 @@ impl Manager {
                 Ok(true) => {
                     let target_dir = target_dir.clone();
                     let _ = self
-                        .resolve_and_install(dep_name, dep_ver, target_dir)
+                        .resolve_and_install(dep_name, dep_ver, target_dir, Some(&name))
                         .await;
                 }
                 Ok(false) => {
@@ -2212,8 +6596,42 @@ impl Manager {
         Ok(())
     }
 
+    /// Resolve a package to the lockfile entry an install would record
+    /// Lazy resolution: the lockfile entry is used as-is when it satisfies the range,
+    /// the registry is only asked otherwise
+    async fn resolve_lock_package(&self, name: &str, version_range: &str) -> Result<LockPackage> {
+        let lock_entry = {
+            let lock = self.lockfile.lock().await;
+            let key = format!("node_modules/{}", name);
+            lock.packages.get(&key).cloned()
+        };
+        if let Some(entry) = lock_entry {
+            if self.lock_entry_satisfies(&entry.version, version_range) {
+                return Ok(entry);
+            }
+        }
+
+        let (version, tarball, integrity, deps, peer_deps, optional_deps, postinstall, bin) =
+            self.fetch_and_resolve(name, version_range).await?;
+        Ok(LockPackage {
+            version,
+            resolved: tarball,
+            integrity,
+            dependencies: deps,
+            peer_dependencies: peer_deps,
+            optional_dependencies: optional_deps,
+            postinstall,
+            bin,
+        })
+    }
+
     /// Check if an optional dependency is compatible with the current platform
     async fn check_optional_dep_compatible(&self, name: &str, range: &str) -> Result<bool> {
+        let key = format!("{}@{}", name, range);
+        if let Some(compatible) = self.optional_compat.get(&key) {
+            return Ok(*compatible);
+        }
+
         // Handle package aliases (e.g., "npm:@babel/traverse@^7.25.3")
         let (actual_name, actual_range) = if let Some(alias) = parse_package_alias(range) {
             (alias.actual_name, alias.version_range)
@@ -2223,7 +6641,9 @@ impl Manager {
         
         let package = self.registry.get_package(&actual_name).await?;
         let resolved = self.registry.resolve_version(&package, &actual_range)?;
-        Ok(is_version_platform_compatible(resolved))
+        let compatible = is_version_platform_compatible(resolved);
+        self.optional_compat.insert(key, compatible);
+        Ok(compatible)
     }
 
     async fn fetch_and_resolve(
@@ -2233,6 +6653,7 @@ impl Manager {
     ) -> Result<(
         String,
         String,
+        Option<String>,
         BTreeMap<String, String>,
         BTreeMap<String, String>,
         BTreeMap<String, String>,
@@ -2248,8 +6669,11 @@ impl Manager {
             (name.to_string(), range.to_string())
         };
         
+        let started = std::time::Instant::now();
         let package = self.registry.get_package(&actual_name).await?;
+        self.adjust_concurrency();
         let resolved = self.registry.resolve_version(&package, &actual_range)?;
+        add_elapsed(&self.resolve_nanos, started);
 
         let postinstall = resolved
             .scripts
@@ -2260,6 +6684,7 @@ impl Manager {
         Ok((
             resolved.version.clone(),
             resolved.dist.tarball.clone(),
+            resolved.dist.integrity.clone(),
             resolved.dependencies.clone(),
             resolved.peer_dependencies.clone(),
             resolved.optional_dependencies.clone(),
@@ -2268,3 +6693,141 @@ impl Manager {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dependency_lines_circular() {
+        let mut tree = InstalledTree::new();
+        tree.insert("a".to_string(), ("1.0.0".to_string(), vec!["b".to_string()]));
+        tree.insert("b".to_string(), ("2.0.0".to_string(), vec!["a".to_string()]));
+        let mut lines = Vec::new();
+        let mut ancestors = vec!["a".to_string()];
+        let mut expanded = HashSet::new();
+        push_dependency_lines("a", &tree, 1, None, &mut ancestors, &mut expanded, &mut lines);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("b@") && !lines[0].contains("(circular)"));
+        assert!(lines[1].contains("a@") && lines[1].contains("(circular)"));
+
+        lines.clear();
+        push_dependency_lines("a", &tree, 1, Some(0), &mut ancestors, &mut expanded, &mut lines);
+        assert!(lines.is_empty());
+    }
+
+    #[test]
+    fn test_validate_package_json() {
+        let parse = |json: &str| serde_json::from_str::<PackageJson>(json).unwrap();
+        let path = Path::new("package.json");
+        assert!(validate_package_json(&parse(r#"{"name":"app","version":"1.2.3"}"#), path).is_ok());
+        assert!(validate_package_json(&parse(r#"{"name":"app"}"#), path).is_ok());
+        assert!(validate_package_json(&parse(r#"{"name":"","version":"1.0.0"}"#), path).is_err());
+        assert!(validate_package_json(&parse(r#"{"name":"app","version":"1.0"}"#), path).is_err());
+    }
+
+    #[test]
+    fn test_parse_env_file() {
+        let content = "# comment\n\nexport HOST=localhost # local\nURL=\"http://${HOST}:${PORT}\"\n\
+                       RAW='${HOST}'\nEMPTY=\nnot a variable\n";
+        let lookup = |name: &str| (name == "PORT").then(|| "3000".to_string());
+        let vars = parse_env_file(content, true, lookup).unwrap();
+        let vars: Vec<(&str, &str)> = vars.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        assert_eq!(
+            vars,
+            vec![
+                ("HOST", "localhost"),
+                ("URL", "http://localhost:3000"),
+                ("RAW", "${HOST}"),
+                ("EMPTY", ""),
+            ]
+        );
+        let kept = parse_env_file("PORT=80\nURL=${PORT}", false, lookup).unwrap();
+        assert_eq!(kept[1], ("URL".to_string(), "3000".to_string()));
+
+        let content = concat!(
+            "QUOTED=\"say \\\"hi\\\" \\\\n\"\n",
+            "KEY='-----BEGIN-----\nabc\n-----END-----'\n",
+            "NEXT=\"a\\nb # not a comment\" # comment\n"
+        );
+        let vars = parse_env_file(content, true, lookup).unwrap();
+        assert_eq!(vars[0].1, "say \"hi\" \\n");
+        assert_eq!(vars[1].1, "-----BEGIN-----\nabc\n-----END-----");
+        assert_eq!(vars[2].1, "a\nb # not a comment");
+
+        let unterminated = parse_env_file("A=1\nB=\"open\nC=3\n", true, lookup);
+        assert_eq!(
+            unterminated.unwrap_err(),
+            "line 2: the value of B is missing its closing \" quote"
+        );
+    }
+
+    #[test]
+    fn test_devtools_url() {
+        assert_eq!(
+            devtools_url("Debugger listening on ws://127.0.0.1:9229/4f0e").as_deref(),
+            Some(concat!(
+                "devtools://devtools/bundled/js_app.html?experiments=true&v8only=true",
+                "&ws=127.0.0.1:9229/4f0e"
+            ))
+        );
+        assert_eq!(devtools_url("For help, see: https://nodejs.org/en/docs/inspector"), None);
+    }
+
+    #[test]
+    fn test_requires_module() {
+        assert!(!requires_module("--max-old-space-size=4096 --enable-source-maps"));
+        assert!(requires_module("-r ./setup.js"));
+        assert!(requires_module("--require=./setup.js"));
+        assert!(requires_module("--import tsx"));
+        assert!(requires_module("--trace-warnings --loader=ts-node/esm"));
+        assert!(requires_module("--experimental-loader ./hooks.mjs"));
+        assert!(!requires_module("--importance"));
+    }
+
+    #[test]
+    fn test_git_hooks_script() {
+        let mut scripts = HashMap::new();
+        scripts.insert("build".to_string(), "tsc".to_string());
+        assert_eq!(git_hooks_script(&scripts), None);
+        scripts.insert("postinstall".to_string(), "simple-git-hooks".to_string());
+        scripts.insert("prepare".to_string(), "husky".to_string());
+        assert_eq!(git_hooks_script(&scripts), Some(("husky", "husky")));
+    }
+
+    #[test]
+    fn test_cmd_shim_scoped_package() {
+        assert_eq!(
+            cmd_shim(&windows_bin_path("..", "@scope/tool", "./bin/cli.js"), Some("node")),
+            "@ECHO off\r\nnode \"%~dp0\\..\\@scope\\tool\\bin\\cli.js\" %*\r\n"
+        );
+        assert!(ps1_shim(&windows_bin_path("..", "@scope/tool", "bin/cli.js"), Some("node"))
+            .contains("& node \"$basedir\\..\\@scope\\tool\\bin\\cli.js\" $args"));
+        assert_eq!(
+            cmd_shim(&windows_bin_path("..", "tool", "bin/tool.exe"), None),
+            "@ECHO off\r\n\"%~dp0\\..\\tool\\bin\\tool.exe\" %*\r\n"
+        );
+    }
+
+    #[test]
+    fn test_shim_program() {
+        let program = |script: &str, path: &str| shim_program(script.as_bytes(), path);
+        assert_eq!(program("#!/usr/bin/env node\r\nrequire('x')", "cli").as_deref(), Some("node"));
+        assert_eq!(program("#!/bin/sh -e\necho", "run.sh").as_deref(), Some("sh -e"));
+        assert_eq!(
+            program("#!/usr/bin/env -S node --no-warnings\n", "cli.js").as_deref(),
+            Some("node --no-warnings")
+        );
+        assert_eq!(program("console.log(1)", "cli.js").as_deref(), Some("node"));
+        assert_eq!(program("MZ\u{90}", "tool.exe"), None);
+    }
+
+    #[test]
+    fn test_can_dedupe_by_range() {
+        assert!(can_dedupe("4.17.21", "4.17.21", None));
+        assert!(can_dedupe("4.17.21", "4.0.0", Some("^4.0.0")));
+        assert!(!can_dedupe("4.17.21", "3.10.1", Some("^3.0.0")));
+        assert!(!can_dedupe("4.17.21", "4.0.0", None));
+        assert!(!can_dedupe("4.17.21", "4.0.0", Some("not-a-range")));
+    }
+}
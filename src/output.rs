@@ -52,12 +52,44 @@ pub mod symbols {
     pub const SEPARATOR: &str = "│";
 }
 
+// ============================================================================
+// Reporter Styles
+// ============================================================================
+
+/// How progress is reported while installing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Reporter {
+    /// Spinners and progress lines
+    #[default]
+    Default,
+    /// Only errors
+    Silent,
+    /// One dot per installed package, no spinners
+    Dot,
+}
+
+/// Whether to color output, set by the global --color / --no-color flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    /// Decide from NO_COLOR, FORCE_COLOR and whether stdout is a terminal
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
 // ============================================================================
 // Output Helper Functions
 // ============================================================================
 
-/// Check if colors should be used based on terminal and environment
-pub fn should_use_colors() -> bool {
+/// Check if colors should be used based on the color flags, terminal and environment
+pub fn should_use_colors(mode: ColorMode) -> bool {
+    match mode {
+        ColorMode::Always => return true,
+        ColorMode::Never => return false,
+        ColorMode::Auto => {}
+    }
+
     // Respect NO_COLOR environment variable (https://no-color.org/)
     if std::env::var("NO_COLOR").is_ok() {
         return false;
@@ -73,8 +105,8 @@ pub fn should_use_colors() -> bool {
 }
 
 /// Strip ANSI color codes from a string if colors are disabled
-pub fn maybe_strip_colors(s: &str) -> String {
-    if should_use_colors() {
+pub fn maybe_strip_colors(mode: ColorMode, s: &str) -> String {
+    if should_use_colors(mode) {
         s.to_string()
     } else {
         // Simple regex-free ANSI stripping
@@ -97,66 +129,76 @@ pub fn maybe_strip_colors(s: &str) -> String {
     }
 }
 
+/// Print a line, without its color codes when colors are disabled
+pub fn print_line(mode: ColorMode, line: &str) {
+    println!("{}", maybe_strip_colors(mode, line));
+}
+
+/// Print a line to stderr, without its color codes when colors are disabled
+pub fn eprint_line(mode: ColorMode, line: &str) {
+    eprintln!("{}", maybe_strip_colors(mode, line));
+}
+
 /// Print a success message with green checkmark
-pub fn success(msg: &str) {
+pub fn success(mode: ColorMode, msg: &str) {
     use colors::*;
     use symbols::*;
     let output = format!("{GREEN}{SUCCESS}{RESET} {msg}");
-    println!("{}", maybe_strip_colors(&output));
+    println!("{}", maybe_strip_colors(mode, &output));
 }
 
 /// Print a warning message with yellow exclamation
-pub fn warning(msg: &str) {
+pub fn warning(mode: ColorMode, msg: &str) {
     use colors::*;
     let output = format!("{YELLOW}warn:{RESET} {msg}");
-    println!("{}", maybe_strip_colors(&output));
+    println!("{}", maybe_strip_colors(mode, &output));
 }
 
 /// Print an error message with red X
-pub fn error(msg: &str) {
+pub fn error(mode: ColorMode, msg: &str) {
     use colors::*;
     let output = format!("{BOLD_RED}error:{RESET} {msg}");
-    eprintln!("{}", maybe_strip_colors(&output));
+    eprintln!("{}", maybe_strip_colors(mode, &output));
 }
 
 /// Print an info message
-pub fn info(msg: &str) {
+pub fn info(mode: ColorMode, msg: &str) {
     use colors::*;
     let output = format!("{CYAN}info:{RESET} {msg}");
-    println!("{}", maybe_strip_colors(&output));
+    println!("{}", maybe_strip_colors(mode, &output));
 }
 
 /// Print a hint/suggestion message (indented, dimmed)
-pub fn hint(msg: &str) {
+pub fn hint(mode: ColorMode, msg: &str) {
     use colors::*;
     let output = format!("      {GRAY}{msg}{RESET}");
-    println!("{}", maybe_strip_colors(&output));
+    println!("{}", maybe_strip_colors(mode, &output));
 }
 
 /// Print a package added message
-pub fn package_added(name: &str, version: &str) {
+pub fn package_added(mode: ColorMode, name: &str, version: &str) {
     use colors::*;
     use symbols::*;
     let output = format!("{GREEN}{PLUS}{RESET} {BOLD}{name}{RESET}@{GRAY}{version}{RESET}");
-    println!("{}", maybe_strip_colors(&output));
+    println!("{}", maybe_strip_colors(mode, &output));
 }
 
 /// Print a package removed message
-pub fn package_removed(name: &str) {
+pub fn package_removed(mode: ColorMode, name: &str) {
     use colors::*;
     use symbols::*;
     let output = format!("{RED}{MINUS}{RESET} {BOLD}{name}{RESET}");
-    println!("{}", maybe_strip_colors(&output));
+    println!("{}", maybe_strip_colors(mode, &output));
 }
 
 /// Print a package updated message
-pub fn package_updated(name: &str, old_version: &str, new_version: &str) {
+pub fn package_updated(mode: ColorMode, name: &str, old_version: &str, new_version: &str) {
     use colors::*;
     use symbols::*;
     let output = format!(
         "{CYAN}{ARROW_UP}{RESET} {BOLD}{name}{RESET} {GRAY}{old_version}{RESET} {ARROW_RIGHT} {GREEN}{new_version}{RESET}"
     );
-    println!("{}", maybe_strip_colors(&output));
+    println!("{}", maybe_strip_colors(mode, &output));
 }
 
 /// Format an install summary line
@@ -223,8 +265,11 @@ pub enum RpmError {
     /// Binary not found in package
     BinaryNotFound { package: String, binary: String },
 
-    /// Workspace error
-    WorkspaceError { message: String },
+    /// Workspace error, with a hint replacing the generic workspace tip
+    WorkspaceError {
+        message: String,
+        hint: Option<String>,
+    },
 
     /// Generic error with optional hint
     Other {
@@ -361,12 +406,15 @@ impl fmt::Display for RpmError {
                 Ok(())
             }
 
-            RpmError::WorkspaceError { message } => {
+            RpmError::WorkspaceError { message, hint } => {
                 write!(f, "{message}")?;
-                write!(
-                    f,
-                    "\n\n      {GRAY}Tip: Make sure you're in a workspace root with 'workspaces' field in package.json{RESET}"
-                )?;
+                match hint {
+                    Some(h) => write!(f, "\n\n      {GRAY}Tip: {h}{RESET}")?,
+                    None => write!(
+                        f,
+                        "\n\n      {GRAY}Tip: Make sure you're in a workspace root with 'workspaces' field in package.json{RESET}"
+                    )?,
+                }
                 Ok(())
             }
 
@@ -400,6 +448,26 @@ pub fn format_duration(secs: f64) -> String {
     }
 }
 
+/// Format how long ago something happened (e.g. "3d ago", "2w ago")
+pub fn format_age(secs: u64) -> String {
+    const MINUTE: u64 = 60;
+    const HOUR: u64 = MINUTE * 60;
+    const DAY: u64 = HOUR * 24;
+    const WEEK: u64 = DAY * 7;
+
+    if secs < MINUTE {
+        "just now".to_string()
+    } else if secs < HOUR {
+        format!("{}m ago", secs / MINUTE)
+    } else if secs < DAY {
+        format!("{}h ago", secs / HOUR)
+    } else if secs < WEEK {
+        format!("{}d ago", secs / DAY)
+    } else {
+        format!("{}w ago", secs / WEEK)
+    }
+}
+
 /// Format bytes in a human-readable way
 pub fn format_bytes(bytes: u64) -> String {
     const KB: u64 = 1024;
@@ -454,6 +522,20 @@ mod tests {
         assert_eq!(format_duration(65.0), "1m 5.0s");
     }
 
+    #[test]
+    fn test_color_mode_overrides_detection() {
+        assert!(should_use_colors(ColorMode::Always));
+        assert!(!should_use_colors(ColorMode::Never));
+        assert_eq!(
+            maybe_strip_colors(ColorMode::Never, "\x1b[1mrpm\x1b[0m"),
+            "rpm"
+        );
+        assert_eq!(
+            maybe_strip_colors(ColorMode::Always, "\x1b[1mrpm\x1b[0m"),
+            "\x1b[1mrpm\x1b[0m"
+        );
+    }
+
     #[test]
     fn test_format_bytes() {
         assert_eq!(format_bytes(500), "500 B");
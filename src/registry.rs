@@ -1,11 +1,23 @@
 use crate::output::RpmError;
 use crate::types::{RegistryPackage, RegistryVersion};
 use anyhow::Result;
+use chrono::{DateTime, FixedOffset};
 use dashmap::DashMap;
 use reqwest::Client;
 use semver::{Version, VersionReq};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// Upper bound for concurrent registry requests and downloads
+pub const MAX_PARALLEL_DOWNLOADS: usize = 50;
+
+/// Number of metadata requests measured before concurrency is adapted
+const LATENCY_WARMUP_SAMPLES: u64 = 5;
+
+/// Average latency above which concurrency is reduced
+const SLOW_LATENCY_MS: u64 = 500;
 
 /// Represents a resolved package alias
 /// e.g., "npm:@babel/traverse@^7.25.3" -> actual_name: "@babel/traverse", version_range: "^7.25.3"
@@ -56,28 +68,208 @@ pub fn parse_package_alias(version_spec: &str) -> Option<ResolvedAlias> {
     })
 }
 
+/// Check whether a version satisfies an npm-style range
+/// Handles "||" alternatives and space separated comparators (">=14 <16"), which
+/// `semver::VersionReq` only accepts comma separated
+/// Returns None if the range or version cannot be parsed
+pub fn version_satisfies(range: &str, version: &str) -> Option<bool> {
+    let version = Version::parse(version.trim().trim_start_matches('v')).ok()?;
+
+    for alternative in range.split("||") {
+        // Join dangling operators with their version (">= 14" -> ">=14")
+        let mut comparators: Vec<String> = Vec::new();
+        let mut pending_op = String::new();
+        for token in alternative.split_whitespace() {
+            if token.chars().all(|c| "<>=~^".contains(c)) {
+                pending_op.push_str(token);
+            } else {
+                comparators.push(format!("{}{}", pending_op, token));
+                pending_op.clear();
+            }
+        }
+
+        if comparators.is_empty() {
+            // An empty alternative matches any version
+            return Some(true);
+        }
+
+        let req = VersionReq::parse(&comparators.join(", ")).ok()?;
+        if req.matches(&version) {
+            return Some(true);
+        }
+    }
+
+    Some(false)
+}
+
+/// Registry used for packages without a configured scope registry
+pub const DEFAULT_REGISTRY: &str = "https://registry.npmjs.org";
+
+/// Connection pool settings shared by registry and tarball requests
+#[derive(Debug, Clone, Copy)]
+pub struct HttpOptions {
+    /// Idle connections kept open per host
+    pub max_sockets: usize,
+    pub pool_idle_timeout: Duration,
+}
+
+impl HttpOptions {
+    pub fn build_client(&self) -> Client {
+        Client::builder()
+            .pool_max_idle_per_host(self.max_sockets)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .tcp_keepalive(Duration::from_secs(60))
+            .build()
+            .unwrap_or_else(|_| Client::new())
+    }
+}
+
 #[derive(Clone)]
 pub struct Registry {
     client: Client,
     base_url: String,
+    // Auth token for base_url
+    token: Option<String>,
+    // Scope (e.g. "@myorg") -> (base_url, auth token) for private registries
+    scope_registries: Arc<HashMap<String, (String, Option<String>)>>,
+    // Mirror asked for packages of base_url when it does not answer within the timeout
+    mirror: Option<(String, Duration)>,
     cache: Arc<DashMap<String, RegistryPackage>>,
+    // Rolling average latency of metadata requests, in milliseconds
+    latency_avg_ms: Arc<AtomicU64>,
+    latency_samples: Arc<AtomicU64>,
 }
 
 impl Registry {
-    pub fn new() -> Self {
-        // Configure client with connection pooling and keep-alive
-        let client = Client::builder()
-            .pool_max_idle_per_host(20)
-            .pool_idle_timeout(Duration::from_secs(30))
-            .tcp_keepalive(Duration::from_secs(60))
-            .build()
-            .unwrap_or_else(|_| Client::new());
-
+    /// Registry using an existing client (and its connection pool)
+    pub fn with_client(client: Client) -> Self {
         Self {
             client,
-            base_url: "https://registry.npmjs.org".to_string(),
+            base_url: DEFAULT_REGISTRY.to_string(),
+            token: None,
+            scope_registries: Arc::new(HashMap::new()),
+            mirror: None,
             cache: Arc::new(DashMap::new()),
+            latency_avg_ms: Arc::new(AtomicU64::new(0)),
+            latency_samples: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Use another registry than registry.npmjs.org for unscoped packages
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Authenticate requests to the default registry
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// Fetch packages of these scopes from their own registry, with their token
+    pub fn with_scope_registries(
+        mut self,
+        scope_registries: HashMap<String, (String, Option<String>)>,
+    ) -> Self {
+        self.scope_registries = Arc::new(scope_registries);
+        self
+    }
+
+    /// Fall back to a mirror of the default registry when it takes longer than `timeout`
+    pub fn with_mirror(mut self, mirror: Option<(String, Duration)>) -> Self {
+        self.mirror = mirror;
+        self
+    }
+
+    /// Base URL and auth token to use for a package
+    fn registry_for(&self, name: &str) -> (&str, Option<&str>) {
+        name.strip_prefix('@')
+            .and_then(|_| name.split('/').next())
+            .and_then(|scope| self.scope_registries.get(scope))
+            .map(|(url, token)| (url.as_str(), token.as_deref()))
+            .unwrap_or((self.base_url.as_str(), self.token.as_deref()))
+    }
+
+    /// URL of the registry (of `scope`, if given) and the username its token belongs to,
+    /// None if there is no token or the registry rejects it
+    pub async fn whoami(&self, scope: Option<&str>) -> Result<(String, Option<String>)> {
+        let (base_url, token) = match scope {
+            Some(scope) => self.registry_for(&format!("{}/", scope)),
+            None => (self.base_url.as_str(), self.token.as_deref()),
+        };
+        let Some(token) = token else {
+            return Ok((base_url.to_string(), None));
+        };
+
+        let url = format!("{}/-/whoami", base_url);
+        let resp = self
+            .client
+            .get(&url)
+            .bearer_auth(token)
+            .send()
+            .await
+            .map_err(|e| RpmError::NetworkError {
+                name: base_url.to_string(),
+                status: None,
+                message: e.to_string(),
+            })?;
+        let status = resp.status();
+        if status.as_u16() == 401 || status.as_u16() == 403 {
+            return Ok((base_url.to_string(), None));
+        }
+        if !status.is_success() {
+            return Err(RpmError::NetworkError {
+                name: base_url.to_string(),
+                status: Some(status.as_u16()),
+                message: status.to_string(),
+            }
+            .into());
+        }
+
+        let body: serde_json::Value = resp.json().await.map_err(|e| RpmError::ParseError {
+            name: url.clone(),
+            message: e.to_string(),
+        })?;
+        let username = body["username"].as_str().map(str::to_string);
+        Ok((base_url.to_string(), username))
+    }
+
+    /// Record the latency of a metadata request
+    /// Plain average over the warm-up samples, then an exponential moving average
+    fn record_latency(&self, elapsed: Duration) {
+        let sample = elapsed.as_millis() as u64;
+        let n = self.latency_samples.fetch_add(1, Ordering::Relaxed) + 1;
+        let _ = self
+            .latency_avg_ms
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |avg| {
+                let weight = n.min(LATENCY_WARMUP_SAMPLES);
+                Some((avg * (weight - 1) + sample) / weight)
+            });
+    }
+
+    /// Concurrency to use for registry requests based on the measured latency
+    /// Stays at MAX_PARALLEL_DOWNLOADS until the warm-up requests are measured
+    pub fn adaptive_concurrency(&self) -> usize {
+        if self.latency_samples.load(Ordering::Relaxed) < LATENCY_WARMUP_SAMPLES {
+            return MAX_PARALLEL_DOWNLOADS;
+        }
+
+        let avg_ms = self.latency_avg_ms.load(Ordering::Relaxed);
+        if avg_ms <= SLOW_LATENCY_MS {
+            return MAX_PARALLEL_DOWNLOADS;
         }
+
+        let scaled = MAX_PARALLEL_DOWNLOADS as u64 * SLOW_LATENCY_MS / avg_ms;
+        (scaled as usize).max(1)
+    }
+
+    /// Unpacked size of a version whose metadata was already fetched, when the registry
+    /// reports both `dist.fileCount` and `dist.unpackedSize`
+    pub fn unpacked_size(&self, name: &str, version: &str) -> Option<u64> {
+        let package = self.cache.get(name)?;
+        let dist = &package.versions.get(version)?.dist;
+        dist.file_count.and(dist.unpacked_size)
     }
 
     pub async fn get_package(&self, name: &str) -> Result<RegistryPackage> {
@@ -86,8 +278,30 @@ impl Registry {
             return Ok(cached.value().clone());
         }
 
-        let url = format!("{}/{}", self.base_url, name);
-        let resp = match self.client.get(&url).send().await {
+        let (base_url, token) = self.registry_for(name);
+        let url = format!("{}/{}", base_url, name);
+        let mut request = self.client.get(&url);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let started = Instant::now();
+        let response = match &self.mirror {
+            // Scoped registries are private, the mirror only stands in for the default one
+            Some((mirror_url, timeout)) if base_url == self.base_url => {
+                tokio::select! {
+                    response = request.send() => response,
+                    // Dropping the pending request cancels it
+                    _ = tokio::time::sleep(*timeout) => {
+                        self.client
+                            .get(format!("{}/{}", mirror_url, name))
+                            .send()
+                            .await
+                    }
+                }
+            }
+            _ => request.send().await,
+        };
+        let resp = match response {
             Ok(r) => r,
             Err(e) => {
                 return Err(RpmError::NetworkError {
@@ -134,12 +348,45 @@ impl Registry {
             }
         };
 
+        self.record_latency(started.elapsed());
+
         // Store in cache
         self.cache.insert(name.to_string(), package.clone());
 
         Ok(package)
     }
 
+    /// Auth token to send with a tarball request, only when the tarball is hosted on the
+    /// package's registry (other hosts must not see it)
+    pub fn tarball_token(&self, name: &str, url: &str) -> Option<&str> {
+        let (base_url, token) = self.registry_for(name);
+        token.filter(|_| same_registry(base_url, url))
+    }
+
+    /// Whether a tarball can still be downloaded (HEAD request)
+    /// Only 404 and 410 count as gone, other failures are returned as errors
+    pub async fn tarball_exists(&self, name: &str, url: &str) -> Result<bool> {
+        let mut request = self.client.head(url);
+        if let Some(token) = self.tarball_token(name, url) {
+            request = request.bearer_auth(token);
+        }
+        let resp = request.send().await.map_err(|e| RpmError::NetworkError {
+            name: name.to_string(),
+            status: None,
+            message: e.to_string(),
+        })?;
+        match resp.status().as_u16() {
+            404 | 410 => Ok(false),
+            _ if resp.status().is_success() => Ok(true),
+            status => Err(RpmError::NetworkError {
+                name: name.to_string(),
+                status: Some(status),
+                message: resp.status().to_string(),
+            }
+            .into()),
+        }
+    }
+
     /// Generate package name suggestions for typos
     fn generate_package_suggestions(&self, name: &str) -> Vec<String> {
         // Common npm package prefixes/suffixes that users might forget
@@ -174,6 +421,49 @@ impl Registry {
         &self,
         package: &'a RegistryPackage,
         range: &str,
+    ) -> Result<&'a RegistryVersion> {
+        self.resolve_version_filtered(package, range, |_| true)
+    }
+
+    /// Resolve the highest version matching `range` that was published before `before`
+    pub fn resolve_version_before<'a>(
+        &self,
+        package: &'a RegistryPackage,
+        range: &str,
+        before: &DateTime<FixedOffset>,
+    ) -> Result<&'a RegistryVersion> {
+        let published_before = |v: &RegistryVersion| {
+            package
+                .time
+                .get(&v.version)
+                .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+                .map(|t| t < *before)
+                .unwrap_or(false)
+        };
+
+        // A dist-tag points at a single version, if it is too new use the newest
+        // stable release from before the date instead
+        let range = match package.dist_tags.get(range) {
+            Some(tag_version)
+                if !package
+                    .versions
+                    .get(tag_version)
+                    .map(published_before)
+                    .unwrap_or(false) =>
+            {
+                "*"
+            }
+            _ => range,
+        };
+
+        self.resolve_version_filtered(package, range, published_before)
+    }
+
+    fn resolve_version_filtered<'a>(
+        &self,
+        package: &'a RegistryPackage,
+        range: &str,
+        filter: impl Fn(&RegistryVersion) -> bool,
     ) -> Result<&'a RegistryVersion> {
         // Try dist-tags first (e.g., "latest", "next", "beta")
         if let Some(tag_version) = package.dist_tags.get(range) {
@@ -197,6 +487,7 @@ impl Registry {
                 Version::parse(&v.version)
                     .map(|parsed| req.matches(&parsed))
                     .unwrap_or(false)
+                    && filter(v)
             })
             .collect();
 
@@ -233,3 +524,120 @@ impl Registry {
         versions.into_iter().map(|s| s.to_string()).collect()
     }
 }
+
+/// Whether `url` is on the registry at `base_url`: same scheme, host and port,
+/// and a path below the registry's path
+fn same_registry(base_url: &str, url: &str) -> bool {
+    let (Ok(base), Ok(url)) = (reqwest::Url::parse(base_url), reqwest::Url::parse(url)) else {
+        return false;
+    };
+    let base_path = base.path().trim_end_matches('/');
+    base.scheme() == url.scheme()
+        && base.host_str() == url.host_str()
+        && base.port_or_known_default() == url.port_or_known_default()
+        && (url.path() == base_path || url.path().starts_with(&format!("{}/", base_path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_satisfies() {
+        assert_eq!(version_satisfies(">=14", "v18.2.0"), Some(true));
+        assert_eq!(version_satisfies(">=14 <16", "18.2.0"), Some(false));
+        assert_eq!(version_satisfies(">= 14 < 16", "14.1.0"), Some(true));
+        assert_eq!(version_satisfies("^12 || ^14", "14.0.0"), Some(true));
+        assert_eq!(version_satisfies("", "1.0.0"), Some(true));
+        assert_eq!(version_satisfies("not a range", "1.0.0"), None);
+    }
+
+    #[test]
+    fn test_registry_for_scope() {
+        let mut scopes = HashMap::new();
+        scopes.insert(
+            "@myorg".to_string(),
+            (
+                "https://npm.myorg.com".to_string(),
+                Some("secret".to_string()),
+            ),
+        );
+        let registry = Registry::with_client(Client::new()).with_scope_registries(scopes);
+        assert_eq!(
+            registry.registry_for("@myorg/private"),
+            ("https://npm.myorg.com", Some("secret"))
+        );
+        assert_eq!(
+            registry.registry_for("@other/pkg"),
+            ("https://registry.npmjs.org", None)
+        );
+        assert_eq!(
+            registry.registry_for("myorg"),
+            ("https://registry.npmjs.org", None)
+        );
+    }
+
+    #[test]
+    fn test_tarball_token_only_for_registry_host() {
+        let mut scopes = HashMap::new();
+        scopes.insert(
+            "@myorg".to_string(),
+            (
+                "https://npm.myorg.com/npm".to_string(),
+                Some("secret".to_string()),
+            ),
+        );
+        let registry = Registry::with_client(Client::new()).with_scope_registries(scopes);
+        let token = |url| registry.tarball_token("@myorg/pkg", url);
+        assert_eq!(
+            token("https://npm.myorg.com/npm/@myorg/pkg/-/pkg-1.0.0.tgz"),
+            Some("secret")
+        );
+        assert_eq!(
+            token("https://npm.myorg.com:443/npm/pkg.tgz"),
+            Some("secret")
+        );
+        assert_eq!(token("https://npm.myorg.com.evil.net/npm/pkg.tgz"), None);
+        assert_eq!(token("https://npm.myorg.com/npmx/pkg.tgz"), None);
+        assert_eq!(token("http://npm.myorg.com/npm/pkg.tgz"), None);
+        assert_eq!(token("https://npm.myorg.com:8443/npm/pkg.tgz"), None);
+    }
+
+    #[tokio::test]
+    async fn test_slow_registry_falls_back_to_mirror() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        // The registry accepts connections but never answers
+        let slow = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let slow_url = format!("http://{}", slow.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((socket, _)) = slow.accept().await {
+                connections.push(socket);
+            }
+        });
+
+        let mirror = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mirror_url = format!("http://{}", mirror.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = mirror.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = socket.read(&mut buf).await;
+            let body = r#"{"name":"pkg","versions":{},"dist-tags":{}}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let mut registry = Registry::with_client(Client::new())
+            .with_mirror(Some((mirror_url, Duration::from_millis(100))));
+        registry.base_url = slow_url;
+        let package = registry.get_package("pkg").await.unwrap();
+        assert_eq!(package._name, "pkg");
+    }
+}
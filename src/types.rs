@@ -1,6 +1,6 @@
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 fn default_version() -> String {
     "0.0.0".to_string()
@@ -36,6 +36,58 @@ where
     Ok(opt.unwrap_or_default())
 }
 
+/// Deserialize a string map that may be malformed in published packages
+/// (e.g. legacy array-style "engines"); non-object values become an empty map
+/// and scalar entries are converted to strings
+fn deserialize_lenient_btreemap<'de, D>(
+    deserializer: D,
+) -> Result<BTreeMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: Option<Value> = Option::deserialize(deserializer)?;
+    let mut map = BTreeMap::new();
+    if let Some(Value::Object(o)) = value {
+        for (key, value) in o {
+            match value {
+                Value::String(s) => {
+                    map.insert(key, s);
+                }
+                Value::Number(n) => {
+                    map.insert(key, n.to_string());
+                }
+                Value::Bool(b) => {
+                    map.insert(key, b.to_string());
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(map)
+}
+
+/// Deserialize the `overrides` field of package.json
+/// Only plain string entries are kept; for object entries the "." key (the override
+/// for the package itself) is used and nested overrides are ignored
+fn deserialize_overrides<'de, D>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let opt: Option<BTreeMap<String, Value>> = Option::deserialize(deserializer)?;
+    let mut overrides = BTreeMap::new();
+    for (name, value) in opt.unwrap_or_default() {
+        let version = match value {
+            Value::String(s) => Some(s),
+            Value::Object(o) => o.get(".").and_then(|v| v.as_str()).map(|s| s.to_string()),
+            _ => None,
+        };
+        if let Some(version) = version {
+            overrides.insert(name, version);
+        }
+    }
+    Ok(overrides)
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct PackageJson {
     pub name: String,
@@ -56,6 +108,87 @@ pub struct PackageJson {
     /// Workspace glob patterns (e.g., ["packages/*", "apps/*"])
     #[serde(default)]
     pub workspaces: Vec<String>,
+    /// Forced versions for (transitive) dependencies, e.g. {"lodash": "4.17.21"}
+    #[serde(
+        default,
+        deserialize_with = "deserialize_overrides",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub overrides: BTreeMap<String, String>,
+    /// Yarn-style overrides, used when `overrides` is absent
+    #[serde(
+        default,
+        deserialize_with = "deserialize_overrides",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub resolutions: BTreeMap<String, String>,
+    /// Required tool versions, e.g. {"node": ">=18", "rpm": ">=0.1.0"}
+    #[serde(
+        default,
+        deserialize_with = "deserialize_lenient_btreemap",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub engines: BTreeMap<String, String>,
+    /// Values exposed to scripts as npm_package_config_<key>
+    #[serde(
+        default,
+        deserialize_with = "deserialize_lenient_btreemap",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub config: BTreeMap<String, String>,
+}
+
+impl PackageJson {
+    /// Overrides to apply to the dependency tree
+    /// Falls back to Yarn's `resolutions` (returned flag is true) when `overrides` is empty;
+    /// nested resolution paths like "package-a/package-b" only keep the final package name
+    pub fn effective_overrides(&self) -> (BTreeMap<String, String>, bool) {
+        if !self.overrides.is_empty() || self.resolutions.is_empty() {
+            return (self.overrides.clone(), false);
+        }
+
+        let overrides = self
+            .resolutions
+            .iter()
+            .map(|(path, version)| (resolution_package_name(path), version.clone()))
+            .collect();
+        (overrides, true)
+    }
+}
+
+/// Split an override key into the parent path and the overridden package
+/// e.g. "lodash" -> (None, "lodash"), "react-router/lodash" -> (Some("react-router"), "lodash"),
+/// "@scope/a/@scope/b" -> (Some("@scope/a"), "@scope/b"), "a/b/c" -> (Some("a/b"), "c")
+pub fn split_override_path(path: &str) -> (Option<&str>, &str) {
+    // Byte offsets where each package name of the path starts
+    let mut starts = vec![0];
+    let mut in_scope = path.starts_with('@');
+    for (i, c) in path.char_indices() {
+        if c != '/' {
+            continue;
+        }
+        if in_scope {
+            in_scope = false;
+        } else {
+            starts.push(i + 1);
+            in_scope = path[i + 1..].starts_with('@');
+        }
+    }
+    match starts.as_slice() {
+        [.., package] if *package > 0 => (Some(&path[..*package - 1]), &path[*package..]),
+        _ => (None, path),
+    }
+}
+
+/// Get the final package name of a resolution path
+/// e.g. "**/lodash" -> "lodash", "a/@scope/b" -> "@scope/b"
+fn resolution_package_name(path: &str) -> String {
+    let segments: Vec<&str> = path.split('/').collect();
+    match segments.as_slice() {
+        [.., scope, name] if scope.starts_with('@') => format!("{}/{}", scope, name),
+        [.., name] => name.to_string(),
+        [] => path.to_string(),
+    }
 }
 
 /// Represents a workspace member with its path and package.json
@@ -73,6 +206,9 @@ pub struct RegistryPackage {
     #[serde(rename = "dist-tags")]
     pub dist_tags: HashMap<String, String>,
     pub versions: HashMap<String, RegistryVersion>,
+    /// Publish times keyed by version (plus "created" and "modified")
+    #[serde(default, deserialize_with = "deserialize_null_default_hashmap")]
+    pub time: HashMap<String, String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -110,15 +246,29 @@ pub struct RegistryVersion {
 #[derive(Debug, Deserialize, Clone)]
 pub struct RegistryDist {
     pub tarball: String,
-    #[allow(dead_code)]
     pub integrity: Option<String>,
+    #[serde(default, rename = "fileCount")]
+    pub file_count: Option<u64>,
+    /// Size of the extracted package in bytes
+    #[serde(default, rename = "unpackedSize")]
+    pub unpacked_size: Option<u64>,
 }
 
+/// Current rpm-lock.json format, older lockfiles are migrated when loaded
+pub const LOCKFILE_VERSION: u32 = 3;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LockFile {
     pub name: String,
     pub version: String,
     pub lockfile_version: u32,
+    /// Dependency types left out of the install (e.g. ["optional"])
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub omit: Vec<String>,
+    /// Overrides of the root package.json the packages were resolved with ("parent/name"
+    /// for scoped ones), so the next install notices when they change
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub overrides: BTreeMap<String, String>,
     #[serde(default)]
     pub packages: BTreeMap<String, LockPackage>,
 }
@@ -147,3 +297,289 @@ pub struct LockPackage {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub bin: Option<Value>,
 }
+
+impl LockFile {
+    /// An empty lockfile in the current format
+    pub fn empty() -> Self {
+        Self {
+            name: String::new(),
+            version: String::new(),
+            lockfile_version: LOCKFILE_VERSION,
+            omit: Vec::new(),
+            overrides: BTreeMap::new(),
+            packages: BTreeMap::new(),
+        }
+    }
+
+    /// Parse rpm-lock.json, migrating lockfiles older than `LOCKFILE_VERSION`
+    pub fn parse(content: &str) -> Result<Self, serde_json::Error> {
+        let mut value: Value = serde_json::from_str(content)?;
+        let version = value
+            .get("lockfile_version")
+            .or_else(|| value.get("lockfileVersion"))
+            .and_then(Value::as_u64)
+            .unwrap_or(1);
+        if version < LOCKFILE_VERSION as u64 {
+            migrate_lockfile(&mut value);
+        }
+        serde_json::from_value(value)
+    }
+
+    /// Record the overrides packages are resolved with. Entries of packages whose override
+    /// was added, changed or removed since are dropped, so they are resolved again.
+    /// Returns whether the overrides changed.
+    pub fn update_overrides(&mut self, overrides: BTreeMap<String, String>) -> bool {
+        if self.overrides == overrides {
+            return false;
+        }
+        let changed: BTreeSet<String> = self
+            .overrides
+            .iter()
+            .filter(|(key, range)| overrides.get(*key) != Some(*range))
+            .chain(
+                overrides
+                    .iter()
+                    .filter(|(key, range)| self.overrides.get(*key) != Some(*range)),
+            )
+            .map(|(key, _)| split_override_path(key).1.to_string())
+            .collect();
+        self.packages.retain(|key, _| {
+            let name = key
+                .rsplit_once("node_modules/")
+                .map_or(key.as_str(), |(_, n)| n);
+            !changed.contains(name)
+        });
+        self.overrides = overrides;
+        true
+    }
+
+    /// Drop entries whose dependencies are not all locked, repeatedly, so the packages
+    /// of an interrupted install are resolved again instead of being taken as complete
+    pub fn retain_complete(&mut self) {
+        loop {
+            let incomplete: Vec<String> =
+                self.packages
+                    .iter()
+                    .filter(|(_, entry)| {
+                        entry.dependencies.keys().any(|dep| {
+                            !self.packages.contains_key(&format!("node_modules/{}", dep))
+                        })
+                    })
+                    .map(|(key, _)| key.clone())
+                    .collect();
+            if incomplete.is_empty() {
+                return;
+            }
+            for key in incomplete {
+                self.packages.remove(&key);
+            }
+        }
+    }
+
+    /// Packages and their dependency relationships as
+    /// `{nodes: [{id, version}], edges: [{source, target}]}` (see --graph-output).
+    /// Edges to packages that are not installed are left out.
+    pub fn dependency_graph(&self) -> Value {
+        let name = |key: &str| key.trim_start_matches("node_modules/").to_string();
+        let nodes: Vec<Value> = self
+            .packages
+            .iter()
+            .map(|(key, entry)| serde_json::json!({ "id": name(key), "version": entry.version }))
+            .collect();
+        let edges: Vec<Value> = self
+            .packages
+            .iter()
+            .flat_map(|(key, entry)| {
+                entry
+                    .dependencies
+                    .keys()
+                    .chain(entry.optional_dependencies.keys())
+                    .chain(entry.peer_dependencies.keys())
+                    .filter(|dep| self.packages.contains_key(&format!("node_modules/{}", dep)))
+                    .map(move |dep| serde_json::json!({ "source": name(key), "target": dep }))
+            })
+            .collect();
+        serde_json::json!({ "nodes": nodes, "edges": edges })
+    }
+}
+
+/// Bring an old lockfile to the current format: npm-style v1 `dependencies` become
+/// `packages`, keys get their "node_modules/" prefix, snake_case fields are renamed and
+/// missing fields filled in. Entries without a version are dropped.
+fn migrate_lockfile(value: &mut Value) {
+    let Some(lock) = value.as_object_mut() else {
+        return;
+    };
+    lock.remove("lockfileVersion");
+    lock.insert("lockfile_version".to_string(), LOCKFILE_VERSION.into());
+    for field in ["name", "version"] {
+        if !lock.get(field).is_some_and(Value::is_string) {
+            lock.insert(field.to_string(), Value::String(String::new()));
+        }
+    }
+
+    let old_packages = match lock.remove("packages") {
+        Some(Value::Object(packages)) => packages,
+        _ => match lock.remove("dependencies") {
+            Some(Value::Object(dependencies)) => dependencies,
+            _ => serde_json::Map::new(),
+        },
+    };
+
+    let mut packages = serde_json::Map::new();
+    for (key, mut entry) in old_packages {
+        let Some(fields) = entry.as_object_mut() else {
+            continue;
+        };
+        if !fields.get("version").is_some_and(Value::is_string) {
+            continue;
+        }
+        if !fields.get("resolved").is_some_and(Value::is_string) {
+            fields.insert("resolved".to_string(), Value::String(String::new()));
+        }
+        for (old, new) in [
+            ("requires", "dependencies"),
+            ("peer_dependencies", "peerDependencies"),
+            ("optional_dependencies", "optionalDependencies"),
+        ] {
+            if let Some(deps) = fields.remove(old) {
+                fields.entry(new).or_insert(deps);
+            }
+        }
+        // Null dependency maps would not deserialize
+        fields.retain(|_, field| !field.is_null());
+
+        let key = if key.starts_with("node_modules/") || key.is_empty() {
+            key
+        } else {
+            format!("node_modules/{}", key)
+        };
+        packages.insert(key, entry);
+    }
+    lock.insert("packages".to_string(), Value::Object(packages));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_old_lockfile() {
+        let old = r#"{
+            "name": "app",
+            "lockfileVersion": 1,
+            "dependencies": {
+                "lodash": {
+                    "version": "4.17.21",
+                    "resolved": "https://registry.npmjs.org/lodash/-/lodash-4.17.21.tgz",
+                    "integrity": null,
+                    "requires": { "a": "^1.0.0" },
+                    "peer_dependencies": { "b": "^2.0.0" }
+                },
+                "broken": { "resolved": "x" }
+            }
+        }"#;
+        let lock = LockFile::parse(old).unwrap();
+        assert_eq!(lock.lockfile_version, LOCKFILE_VERSION);
+        assert_eq!(lock.version, "");
+        assert_eq!(lock.packages.len(), 1);
+        let lodash = &lock.packages["node_modules/lodash"];
+        assert_eq!(lodash.version, "4.17.21");
+        assert_eq!(lodash.integrity, None);
+        assert_eq!(lodash.dependencies["a"], "^1.0.0");
+        assert_eq!(lodash.peer_dependencies["b"], "^2.0.0");
+    }
+
+    #[test]
+    fn test_split_override_path() {
+        assert_eq!(split_override_path("lodash"), (None, "lodash"));
+        assert_eq!(split_override_path("@types/node"), (None, "@types/node"));
+        assert_eq!(
+            split_override_path("react-router/lodash"),
+            (Some("react-router"), "lodash")
+        );
+        assert_eq!(
+            split_override_path("@scope/a/@scope/b"),
+            (Some("@scope/a"), "@scope/b")
+        );
+        assert_eq!(split_override_path("a/b/c"), (Some("a/b"), "c"));
+    }
+
+    #[test]
+    fn test_update_overrides() {
+        let entry = r#"{ "version": "1.0.0", "resolved": "", "integrity": null }"#;
+        let mut lock = LockFile::empty();
+        for key in [
+            "node_modules/a",
+            "node_modules/lodash",
+            "node_modules/a/node_modules/lodash",
+        ] {
+            lock.packages
+                .insert(key.to_string(), serde_json::from_str(entry).unwrap());
+        }
+        let overrides = BTreeMap::from([("a/lodash".to_string(), "4.17.21".to_string())]);
+        assert!(lock.update_overrides(overrides.clone()));
+        assert_eq!(
+            lock.packages.keys().collect::<Vec<_>>(),
+            vec!["node_modules/a"]
+        );
+        assert!(!lock.update_overrides(overrides));
+    }
+
+    #[test]
+    fn test_dependency_graph() {
+        let lock = LockFile::parse(
+            r#"{
+                "name": "app",
+                "version": "1.0.0",
+                "lockfile_version": 3,
+                "packages": {
+                    "node_modules/a": {
+                        "version": "1.0.0", "resolved": "", "integrity": null,
+                        "dependencies": { "b": "^2.0.0", "missing": "*" }
+                    },
+                    "node_modules/b": { "version": "2.1.0", "resolved": "", "integrity": null }
+                }
+            }"#,
+        )
+        .unwrap();
+        let graph = lock.dependency_graph();
+        assert_eq!(
+            graph["nodes"][1],
+            serde_json::json!({ "id": "b", "version": "2.1.0" })
+        );
+        assert_eq!(
+            graph["edges"],
+            serde_json::json!([{ "source": "a", "target": "b" }])
+        );
+    }
+
+    #[test]
+    fn test_retain_complete() {
+        // "a" depends on a package that is not locked, "c" on "a"
+        let mut lock = LockFile::parse(
+            r#"{
+                "name": "app",
+                "version": "1.0.0",
+                "lockfile_version": 3,
+                "packages": {
+                    "node_modules/a": {
+                        "version": "1.0.0", "resolved": "", "integrity": null,
+                        "dependencies": { "b": "^2.0.0", "missing": "*" }
+                    },
+                    "node_modules/b": { "version": "2.1.0", "resolved": "", "integrity": null },
+                    "node_modules/c": {
+                        "version": "1.0.0", "resolved": "", "integrity": null,
+                        "dependencies": { "a": "^1.0.0" }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        lock.retain_complete();
+        assert_eq!(
+            lock.packages.keys().collect::<Vec<_>>(),
+            vec!["node_modules/b"]
+        );
+    }
+}
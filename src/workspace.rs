@@ -1,15 +1,28 @@
+use crate::output::{self, ColorMode};
 use crate::types::{PackageJson, WorkspaceMember};
 use anyhow::{Context, Result};
 use glob::glob;
 use serde::Deserialize;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use tokio::fs;
 
 /// pnpm-workspace.yaml structure
 #[derive(Debug, Deserialize)]
 struct PnpmWorkspace {
+    #[serde(default)]
     packages: Vec<String>,
+    /// Shared dependency versions referenced as "catalog:" by members
+    #[serde(default)]
+    catalog: HashMap<String, String>,
+}
+
+/// A dependency declared with different versions across the workspace
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionConflict {
+    pub package: String,
+    /// Each declared version with the workspace packages declaring it
+    pub versions: Vec<(String, Vec<String>)>,
 }
 
 /// Workspace manager for handling monorepo operations
@@ -21,6 +34,8 @@ pub struct Workspace {
     pub root_package: PackageJson,
     /// All workspace members
     pub members: Vec<WorkspaceMember>,
+    /// Versions for dependencies declared as "catalog:" (pnpm-workspace.yaml)
+    pub catalog: HashMap<String, String>,
 }
 
 impl Workspace {
@@ -39,7 +54,8 @@ impl Workspace {
             serde_json::from_str(&content).context("Failed to parse package.json")?;
 
         // Try to get workspace patterns from multiple sources
-        let workspace_patterns = Self::get_workspace_patterns(root, &root_package).await;
+        let pnpm_workspace = Self::read_pnpm_workspace(root).await;
+        let workspace_patterns = Self::get_workspace_patterns(&root_package, &pnpm_workspace);
 
         // Check if this is a workspace root
         if workspace_patterns.is_empty() {
@@ -52,33 +68,47 @@ impl Workspace {
             root: root.to_path_buf(),
             root_package,
             members,
+            catalog: pnpm_workspace.map(|w| w.catalog).unwrap_or_default(),
         }))
     }
 
+    /// Find the workspace that `start` belongs to by walking up the directory tree
+    pub async fn find_root(start: &Path) -> Result<Option<Self>> {
+        for dir in start.ancestors() {
+            if let Some(workspace) = Self::discover(dir).await? {
+                return Ok(Some(workspace));
+            }
+        }
+        Ok(None)
+    }
+
     /// Get workspace patterns from package.json or pnpm-workspace.yaml
-    async fn get_workspace_patterns(root: &Path, root_package: &PackageJson) -> Vec<String> {
+    fn get_workspace_patterns(
+        root_package: &PackageJson,
+        pnpm_workspace: &Option<PnpmWorkspace>,
+    ) -> Vec<String> {
         // 1. Check package.json workspaces field (npm/yarn style)
         if !root_package.workspaces.is_empty() {
             return root_package.workspaces.clone();
         }
 
         // 2. Check pnpm-workspace.yaml (pnpm style)
-        let pnpm_workspace_path = root.join("pnpm-workspace.yaml");
-        if let Ok(content) = fs::read_to_string(&pnpm_workspace_path).await {
-            if let Ok(pnpm_workspace) = serde_yaml::from_str::<PnpmWorkspace>(&content) {
-                return pnpm_workspace.packages;
-            }
-        }
+        pnpm_workspace
+            .as_ref()
+            .map(|w| w.packages.clone())
+            .unwrap_or_default()
+    }
 
-        // 3. Also check pnpm-workspace.yml (alternative extension)
-        let pnpm_workspace_yml = root.join("pnpm-workspace.yml");
-        if let Ok(content) = fs::read_to_string(&pnpm_workspace_yml).await {
-            if let Ok(pnpm_workspace) = serde_yaml::from_str::<PnpmWorkspace>(&content) {
-                return pnpm_workspace.packages;
+    /// Read pnpm-workspace.yaml (or the .yml alternative extension)
+    async fn read_pnpm_workspace(root: &Path) -> Option<PnpmWorkspace> {
+        for file in ["pnpm-workspace.yaml", "pnpm-workspace.yml"] {
+            if let Ok(content) = fs::read_to_string(root.join(file)).await {
+                if let Ok(pnpm_workspace) = serde_yaml::from_str::<PnpmWorkspace>(&content) {
+                    return Some(pnpm_workspace);
+                }
             }
         }
-
-        Vec::new()
+        None
     }
 
     /// Discover all workspace members from glob patterns
@@ -135,7 +165,7 @@ impl Workspace {
             for (dep_name, version) in dependencies {
                 deps.entry(dep_name.clone())
                     .or_default()
-                    .entry(version.clone())
+                    .entry(self.resolve_catalog(dep_name, version))
                     .or_default()
                     .push(pkg_name.to_string());
             }
@@ -154,6 +184,36 @@ impl Workspace {
         deps
     }
 
+    /// Dependencies declared with more than one version (range) across the root and the
+    /// members. Workspace packages themselves are skipped, they are linked locally.
+    pub fn detect_version_conflicts(&self) -> Vec<VersionConflict> {
+        self.collect_all_dependencies()
+            .into_iter()
+            .filter(|(package, versions)| {
+                versions.len() > 1 && !self.members.iter().any(|m| &m.name == package)
+            })
+            .map(|(package, versions)| VersionConflict {
+                package,
+                versions: versions.into_iter().collect(),
+            })
+            .collect()
+    }
+
+    /// Names declared as devDependencies (`dev`) or dependencies anywhere in the workspace
+    pub fn declared_dependencies(&self, dev: bool) -> HashSet<String> {
+        std::iter::once(&self.root_package)
+            .chain(self.members.iter().map(|m| &m.package_json))
+            .flat_map(|package_json| {
+                if dev {
+                    package_json.dev_dependencies.keys()
+                } else {
+                    package_json.dependencies.keys()
+                }
+            })
+            .cloned()
+            .collect()
+    }
+
     /// Get hoisted dependencies (shared across workspaces, resolved to single version)
     /// Uses the highest version when there are conflicts
     pub fn get_hoisted_dependencies(&self) -> BTreeMap<String, String> {
@@ -190,6 +250,40 @@ impl Workspace {
         hoisted
     }
 
+    /// Replace a "catalog:" range with the version from the catalog
+    /// Left unchanged if the catalog has no entry, see `missing_catalog_entry`
+    pub fn resolve_catalog(&self, name: &str, range: &str) -> String {
+        match range {
+            "catalog:" | "catalog:default" => self
+                .catalog
+                .get(name)
+                .cloned()
+                .unwrap_or_else(|| range.to_string()),
+            _ => range.to_string(),
+        }
+    }
+
+    /// First (member, dependency) using "catalog:" without a catalog entry for it
+    pub fn missing_catalog_entry(&self) -> Option<(String, String)> {
+        let root = std::iter::once((&self.root_package.name, &self.root_package));
+        let members = self.members.iter().map(|m| (&m.name, &m.package_json));
+        for (member, package_json) in root.chain(members) {
+            let deps = package_json
+                .dependencies
+                .iter()
+                .chain(&package_json.dev_dependencies);
+            for (dep_name, range) in deps {
+                if self
+                    .resolve_catalog(dep_name, range)
+                    .starts_with("catalog:")
+                {
+                    return Some((member.clone(), dep_name.clone()));
+                }
+            }
+        }
+        None
+    }
+
     /// Compare two version strings (simple comparison, prefers higher versions)
     fn compare_versions(v1: &str, v2: &str) -> std::cmp::Ordering {
         // Strip prefixes like ^, ~, >=, etc.
@@ -238,20 +332,71 @@ impl Workspace {
             .collect()
     }
 
+    /// Compute the new version for `rpm workspace version`: "major", "minor", "patch"
+    /// or an explicit version. Like npm, a prerelease of the bumped version is released
+    /// as is ("1.2.3-beta.1" patch -> "1.2.3", "2.0.0-rc.1" major -> "2.0.0").
+    pub fn bump_version(current: &str, bump: &str) -> Option<String> {
+        let mut version = semver::Version::parse(current).ok()?;
+        let pre = !version.pre.is_empty();
+        match bump {
+            "major" if pre && version.minor == 0 && version.patch == 0 => {}
+            "major" => {
+                version.major += 1;
+                version.minor = 0;
+                version.patch = 0;
+            }
+            "minor" if pre && version.patch == 0 => {}
+            "minor" => {
+                version.minor += 1;
+                version.patch = 0;
+            }
+            "patch" if pre => {}
+            "patch" => version.patch += 1,
+            explicit => return semver::Version::parse(explicit).ok().map(|v| v.to_string()),
+        }
+        version.pre = semver::Prerelease::EMPTY;
+        version.build = semver::BuildMetadata::EMPTY;
+        Some(version.to_string())
+    }
+
+    /// Point a dependency range at `new_version`, keeping its prefix
+    /// ("^1.0.0" -> "^1.1.0", "workspace:~1.0.0" -> "workspace:~1.1.0").
+    /// Returns None when the range does not name a version ("workspace:^", "*", tags).
+    pub fn bump_range(range: &str, new_version: &str) -> Option<String> {
+        if let Some(rest) = range.strip_prefix("workspace:") {
+            return Self::bump_range(rest, new_version).map(|r| format!("workspace:{}", r));
+        }
+
+        let version = range.trim_start_matches(['^', '~', '=', '>', '<']);
+        let prefix = &range[..range.len() - version.len()];
+        semver::Version::parse(version).ok()?;
+        let bumped = format!("{}{}", prefix, new_version);
+        (bumped != range).then_some(bumped)
+    }
+
     /// Print workspace info
-    pub fn print_info(&self) {
-        println!(
-            "\x1b[1;36mWorkspace:\x1b[0m \x1b[1m{}\x1b[0m",
-            self.root_package.name
+    pub fn print_info(&self, color_mode: ColorMode) {
+        output::print_line(
+            color_mode,
+            &format!(
+                "\x1b[1;36mWorkspace:\x1b[0m \x1b[1m{}\x1b[0m",
+                self.root_package.name
+            ),
+        );
+        output::print_line(
+            color_mode,
+            &format!("\x1b[90m{} packages\x1b[0m\n", self.members.len()),
         );
-        println!("\x1b[90m{} packages\x1b[0m\n", self.members.len());
 
         for member in &self.members {
             let relative_path = member.path.strip_prefix(&self.root).unwrap_or(&member.path);
-            println!(
-                "  \x1b[32m•\x1b[0m \x1b[1m{}\x1b[0m \x1b[90m({})\x1b[0m",
-                member.name,
-                relative_path.display()
+            output::print_line(
+                color_mode,
+                &format!(
+                    "  \x1b[32m•\x1b[0m \x1b[1m{}\x1b[0m \x1b[90m({})\x1b[0m",
+                    member.name,
+                    relative_path.display()
+                ),
             );
         }
     }